@@ -0,0 +1,185 @@
+//! Catalog of the symbols the price provider has prices of.
+//! It is persisted as the symbols separated by commas and read from flash in place.
+//! Until a catalog is fetched for the first time, a built-in list is used.
+
+use heapless::String;
+
+/// Upper bound for the size of a stored catalog
+pub const MAX_CATALOG_LENGTH: usize = 2048;
+
+const BUILT_IN_SYMBOLS: [&str; 127] = [
+    "ETH", "BTC", "BNB", "XRP", "MATIC", "DOGE", "ETC", "ADA", "LTC", "DOT", "BCH", "EOS", "LINK",
+    "FIL", "UNI", "XLM", "VET", "TRX", "BTT", "SOL", "LUNA", "OMG", "CAKE", "YFI", "HT", "QTUM",
+    "NEO", "SUSHI", "OKB", "BSV", "AAVE", "THETA", "ONT", "ZEC", "KSM", "XVS", "RUNE", "DASH",
+    "CHZ", "MKR", "ATOM", "SXP", "BAKE", "ENJ", "WAVES", "XMR", "MANA", "ONE", "WRX", "XTZ", "CRV",
+    "FTT", "HOT", "IOST", "AVAX", "HBAR", "FTM", "ZEN", "MIOTA", "ZIL", "CHR", "GRT", "KAVA", "SC",
+    "ALGO", "WBTC", "1INCH", "COMP", "LSK", "ZRX", "SRM", "FLOW", "BAT", "NANO", "SNX", "XEM",
+    "ICX", "RSR", "REEF", "BURGER", "RLC", "TRB", "KLAY", "EGLD", "ONGAS", "NEAR", "DENT", "RVN",
+    "LRC", "VTHO", "KNC", "WIN", "ANKR", "BAND", "JST", "BTG", "BNT", "REP", "CRO", "ALPHA",
+    "OCEAN", "HIVE", "TFUEL", "STORJ", "YFII", "SUN", "OGN", "STMX", "COTI", "GT", "MTL", "MLK",
+    "MONA", "SNT", "DGB", "QKC", "CELR", "INJ", "SOC", "PAXG", "REN", "UNFI", "NKN", "CELO", "BAL",
+    "STEEM", "DAI",
+];
+
+fn is_symbol(text: &str) -> bool {
+    !text.is_empty()
+        && text.len() <= 16
+        && text
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Catalog<'a> {
+    BuiltIn,
+    /// Symbols separated by commas, as written by `CatalogBuilder`
+    Stored(&'a str),
+}
+
+impl<'a> Catalog<'a> {
+    /// Catalog stored in `blob`, or the built-in one if nothing valid is stored
+    pub fn from_blob(blob: Option<&'a [u8]>) -> Self {
+        match blob.map(core::str::from_utf8) {
+            Some(Ok(text)) if !text.is_empty() && text.split(',').all(is_symbol) => {
+                Catalog::Stored(text)
+            }
+            _ => Catalog::BuiltIn,
+        }
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &'a str> {
+        let (stored, built_in): (&'a str, &'static [&'static str]) = match *self {
+            Catalog::BuiltIn => ("", &BUILT_IN_SYMBOLS),
+            Catalog::Stored(text) => (text, &[]),
+        };
+
+        stored
+            .split(',')
+            .filter(|symbol| !symbol.is_empty())
+            .chain(built_in.iter().copied())
+    }
+
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.symbols().any(|listed| listed == symbol)
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Collects the symbols listed by a price provider into the stored format
+#[derive(Default)]
+pub struct CatalogBuilder {
+    text: String<MAX_CATALOG_LENGTH>,
+}
+
+impl CatalogBuilder {
+    pub fn new() -> Self {
+        CatalogBuilder::default()
+    }
+
+    /// Adds `symbol` in upper case. Symbols that aren't tickers, are already there or don't
+    /// fit anymore are left out, providers list the most important ones first.
+    pub fn add(&mut self, symbol: &str) {
+        let mut upper = String::<16>::new();
+        for c in symbol.chars() {
+            if upper.push(c.to_ascii_uppercase()).is_err() {
+                return;
+            }
+        }
+
+        if !is_symbol(&upper) || self.catalog().contains(&upper) {
+            return;
+        }
+        if self.text.len() + upper.len() + 1 > MAX_CATALOG_LENGTH {
+            return;
+        }
+
+        if !self.text.is_empty() {
+            self.text.push(',').ok();
+        }
+        self.text.push_str(&upper).ok();
+    }
+
+    pub fn catalog(&self) -> Catalog<'_> {
+        Catalog::Stored(&self.text)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.text.as_bytes()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_until_stored() {
+        assert_eq!(Catalog::from_blob(None), Catalog::BuiltIn);
+        assert_eq!(Catalog::from_blob(Some(b"")), Catalog::BuiltIn);
+        assert_eq!(Catalog::from_blob(Some(b"BTC,<b>")), Catalog::BuiltIn);
+        assert_eq!(Catalog::from_blob(Some(&[0xff, 0xff])), Catalog::BuiltIn);
+
+        let built_in = Catalog::BuiltIn;
+        assert_eq!(built_in.len(), 127);
+        assert!(built_in.contains("1INCH"));
+        assert!(!built_in.contains("GERO"));
+    }
+
+    #[test]
+    fn stored() {
+        let catalog = Catalog::from_blob(Some(b"BTC,ETH,1INCH"));
+
+        assert_eq!(catalog, Catalog::Stored("BTC,ETH,1INCH"));
+        assert!(catalog
+            .symbols()
+            .eq(["BTC", "ETH", "1INCH"].iter().copied()));
+        assert!(catalog.contains("ETH"));
+        assert!(!catalog.contains("ET"));
+        assert!(!catalog.contains("DOGE"));
+    }
+
+    #[test]
+    fn builder() {
+        let mut builder = CatalogBuilder::new();
+        assert!(builder.is_empty());
+
+        builder.add("btc");
+        builder.add("ETH");
+        builder.add("BTC");
+        builder.add("");
+        builder.add("USD/T");
+        builder.add("AVERYLONGTOKENNAME");
+
+        assert_eq!(builder.as_bytes(), b"BTC,ETH");
+        assert_eq!(
+            Catalog::from_blob(Some(builder.as_bytes())),
+            builder.catalog()
+        );
+    }
+
+    #[test]
+    fn builder_stops_when_full() {
+        let mut builder = CatalogBuilder::new();
+        let mut symbol = String::<16>::new();
+        for i in 0..1000 {
+            symbol.clear();
+            core::fmt::write(&mut symbol, format_args!("T{}", i)).ok();
+            builder.add(&symbol);
+        }
+
+        assert!(builder.as_bytes().len() <= MAX_CATALOG_LENGTH);
+        assert!(builder.catalog().contains("T0"));
+        assert!(!builder.catalog().contains("T999"));
+    }
+}
@@ -0,0 +1,455 @@
+//! Device configuration and its persisted format.
+//! The configuration is stored as an `application/x-www-form-urlencoded` string,
+//! the same format the setup wizard submits.
+
+use dice_http::form::{append_pair, decode, FormError, RawPairs};
+use dice_http_client::crypto_api_client::MAX_API_KEY_LENGTH;
+use heapless::{String, Vec};
+
+pub const MAX_SYMBOLS: usize = 64;
+/// Most currencies prices are shown in at once, one price line each
+pub const MAX_QUOTE_CURRENCIES: usize = 2;
+pub const MAX_BRIGHTNESS: u8 = 8;
+pub const MIN_REFRESH_INTERVAL: u32 = 5;
+pub const MAX_REFRESH_INTERVAL: u32 = 3600;
+/// Upper bound for the size of a serialized configuration
+pub const SERIALIZED_CONFIG_SIZE: usize = 2048;
+
+const DEFAULT_SYMBOLS: [&str; 8] = ["ETH", "BTC", "BNB", "XRP", "MATIC", "DOGE", "ETC", "ADA"];
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    Form(FormError),
+    InvalidCurrency,
+    TooManyCurrencies,
+    InvalidSymbol,
+    TooManySymbols,
+    InvalidBrightness,
+    InvalidRefreshInterval,
+    InvalidSetupFlag,
+    InvalidApiKey,
+}
+
+impl From<FormError> for ConfigError {
+    fn from(error: FormError) -> Self {
+        ConfigError::Form(error)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceConfig {
+    /// False until the user finishes the first-boot setup wizard
+    pub setup_complete: bool,
+    /// Quote currencies of displayed prices. The first one is always there and the 24h
+    /// change is shown in it.
+    pub currencies: Vec<String<8>, MAX_QUOTE_CURRENCIES>,
+    pub symbols: Vec<String<16>, MAX_SYMBOLS>,
+    /// Display brightness, 1 to `MAX_BRIGHTNESS`
+    pub brightness: u8,
+    /// Time between price updates in seconds
+    pub refresh_interval: u32,
+    /// Keys of the price providers, empty if not set. They are stored but never sent back
+    /// to the browser.
+    pub cryptocompare_api_key: String<MAX_API_KEY_LENGTH>,
+    pub coingecko_api_key: String<MAX_API_KEY_LENGTH>,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        let mut symbols = Vec::new();
+        for symbol in DEFAULT_SYMBOLS.iter() {
+            symbols.push(String::from(*symbol)).ok();
+        }
+
+        let mut currencies = Vec::new();
+        currencies.push(String::from("USD")).ok();
+
+        DeviceConfig {
+            setup_complete: false,
+            currencies,
+            symbols,
+            brightness: 1,
+            refresh_interval: 30,
+            cryptocompare_api_key: String::new(),
+            coingecko_api_key: String::new(),
+        }
+    }
+}
+
+fn is_ticker(text: &str, max_length: usize) -> bool {
+    !text.is_empty() && text.len() <= max_length && text.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+fn is_api_key(text: &str) -> bool {
+    !text.is_empty()
+        && text.len() <= MAX_API_KEY_LENGTH
+        && text
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+fn update_api_key(
+    key: &mut String<MAX_API_KEY_LENGTH>,
+    value: Option<&str>,
+) -> Result<(), ConfigError> {
+    match value {
+        None | Some("") => Ok(()),
+        Some(value) if is_api_key(value) => {
+            *key = String::from(value);
+            Ok(())
+        }
+        Some(_) => Err(ConfigError::InvalidApiKey),
+    }
+}
+
+impl DeviceConfig {
+    /// Serialize the configuration for storage or backup.
+    /// The API keys are included, the result must not be served over HTTP.
+    pub fn to_form(&self) -> Result<String<SERIALIZED_CONFIG_SIZE>, ConfigError> {
+        let mut form = String::new();
+        let mut number = String::<16>::new();
+
+        append_pair(
+            &mut form,
+            "setup",
+            if self.setup_complete { "1" } else { "0" },
+        )?;
+        for currency in self.currencies.iter() {
+            append_pair(&mut form, "currency", currency)?;
+        }
+
+        core::fmt::write(&mut number, format_args!("{}", self.brightness)).ok();
+        append_pair(&mut form, "brightness", &number)?;
+
+        number.clear();
+        core::fmt::write(&mut number, format_args!("{}", self.refresh_interval)).ok();
+        append_pair(&mut form, "refresh", &number)?;
+
+        for symbol in self.symbols.iter() {
+            append_pair(&mut form, "symbol", symbol)?;
+        }
+
+        if !self.cryptocompare_api_key.is_empty() {
+            append_pair(&mut form, "cryptocompare_key", &self.cryptocompare_api_key)?;
+        }
+        if !self.coingecko_api_key.is_empty() {
+            append_pair(&mut form, "coingecko_key", &self.coingecko_api_key)?;
+        }
+
+        Ok(form)
+    }
+
+    /// Parse a configuration. Missing fields keep their current values, and so do API keys
+    /// sent empty. `clear_key` names a provider whose key is removed, `add_symbol` is a
+    /// symbol appended to the sent ones.
+    /// # Arguments
+    /// * `body` - Configuration in the format produced by `to_form`
+    pub fn update_from_form(&mut self, body: &[u8]) -> Result<(), ConfigError> {
+        //Fields are applied to a copy as they are decoded, so a failed update changes nothing
+        let mut updated = self.clone();
+        let mut currencies_sent = false;
+        let mut symbols_sent = false;
+        //The symbol from the search box goes after the checked ones, and the keys are set
+        //after the ones to clear are cleared
+        let mut added = None;
+        let mut cryptocompare_key = None;
+        let mut coingecko_key = None;
+
+        for (key, value) in RawPairs::new(body) {
+            let key = decode::<24>(key)?;
+            let value = decode::<MAX_API_KEY_LENGTH>(value)?;
+
+            match key.as_str() {
+                "setup" => {
+                    updated.setup_complete = match value.as_str() {
+                        "1" => true,
+                        "0" => false,
+                        _ => return Err(ConfigError::InvalidSetupFlag),
+                    };
+                }
+                "currency" => {
+                    if !currencies_sent {
+                        updated.currencies.clear();
+                        currencies_sent = true;
+                    }
+                    //An empty value is an unused currency select
+                    if value.is_empty() {
+                        continue;
+                    }
+                    if !is_ticker(&value, 8) {
+                        return Err(ConfigError::InvalidCurrency);
+                    }
+                    if updated.currencies.iter().any(|known| *known == value) {
+                        continue;
+                    }
+                    updated
+                        .currencies
+                        .push(String::from(value.as_str()))
+                        .map_err(|_| ConfigError::TooManyCurrencies)?;
+                }
+                "brightness" => {
+                    updated.brightness = match value.parse::<u8>() {
+                        Ok(value) if (1..=MAX_BRIGHTNESS).contains(&value) => value,
+                        _ => return Err(ConfigError::InvalidBrightness),
+                    };
+                }
+                "refresh" => {
+                    updated.refresh_interval = match value.parse::<u32>() {
+                        Ok(value)
+                            if (MIN_REFRESH_INTERVAL..=MAX_REFRESH_INTERVAL).contains(&value) =>
+                        {
+                            value
+                        }
+                        _ => return Err(ConfigError::InvalidRefreshInterval),
+                    };
+                }
+                //The pages send the kept symbols as checkboxes and one more from the search
+                //box, which is left empty if nothing is added
+                "symbol" | "add_symbol" => {
+                    if !symbols_sent {
+                        updated.symbols.clear();
+                        symbols_sent = true;
+                    }
+                    if key == "add_symbol" {
+                        added = Some(value);
+                        continue;
+                    }
+                    if !is_ticker(&value, 16) {
+                        return Err(ConfigError::InvalidSymbol);
+                    }
+                    updated
+                        .symbols
+                        .push(String::from(value.as_str()))
+                        .map_err(|_| ConfigError::TooManySymbols)?;
+                }
+                "clear_key" => match value.as_str() {
+                    "cryptocompare" => updated.cryptocompare_api_key.clear(),
+                    "coingecko" => updated.coingecko_api_key.clear(),
+                    _ => return Err(ConfigError::InvalidApiKey),
+                },
+                "cryptocompare_key" => cryptocompare_key = Some(value),
+                "coingecko_key" => coingecko_key = Some(value),
+                _ => {}
+            }
+        }
+
+        if currencies_sent && updated.currencies.is_empty() {
+            return Err(ConfigError::InvalidCurrency);
+        }
+
+        if let Some(added) = added.filter(|added| !added.is_empty()) {
+            if !is_ticker(&added, 16) {
+                return Err(ConfigError::InvalidSymbol);
+            }
+            let mut symbol = String::<16>::new();
+            for c in added.chars() {
+                symbol.push(c.to_ascii_uppercase()).ok();
+            }
+            if !updated.symbols.contains(&symbol) {
+                updated
+                    .symbols
+                    .push(symbol)
+                    .map_err(|_| ConfigError::TooManySymbols)?;
+            }
+        }
+
+        update_api_key(
+            &mut updated.cryptocompare_api_key,
+            cryptocompare_key.as_deref(),
+        )?;
+        update_api_key(&mut updated.coingecko_api_key, coingecko_key.as_deref())?;
+
+        *self = updated;
+        Ok(())
+    }
+
+    /// Parse a stored configuration. Fields missing from `body` get default values.
+    pub fn from_form(body: &[u8]) -> Result<Self, ConfigError> {
+        let mut config = DeviceConfig::default();
+        config.update_from_form(body)?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_roundtrip() {
+        let config = DeviceConfig::default();
+        let form = config.to_form().unwrap();
+
+        assert_eq!(
+            form,
+            "setup=0&currency=USD&brightness=1&refresh=30&symbol=ETH&symbol=BTC&symbol=BNB\
+&symbol=XRP&symbol=MATIC&symbol=DOGE&symbol=ETC&symbol=ADA"
+        );
+        assert_eq!(DeviceConfig::from_form(form.as_bytes()).unwrap(), config);
+    }
+
+    #[test]
+    fn setup_wizard_update() {
+        let mut config = DeviceConfig::default();
+        config
+            .update_from_form(
+                b"setup=1&currency=EUR&symbol=1INCH&symbol=BTC&brightness=3&refresh=60",
+            )
+            .unwrap();
+
+        assert!(config.setup_complete);
+        assert_eq!(config.currencies, ["EUR"]);
+        assert_eq!(config.symbols.len(), 2);
+        assert_eq!(config.symbols[0], "1INCH");
+        assert_eq!(config.brightness, 3);
+        assert_eq!(config.refresh_interval, 60);
+    }
+
+    #[test]
+    fn partial_update_keeps_other_fields() {
+        let mut config = DeviceConfig::default();
+        config.update_from_form(b"brightness=2").unwrap();
+
+        assert_eq!(config.brightness, 2);
+        assert_eq!(config.symbols.len(), 8);
+        assert_eq!(config.currencies, ["USD"]);
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let mut config = DeviceConfig::default();
+
+        assert_eq!(
+            config.update_from_form(b"brightness=0"),
+            Err(ConfigError::InvalidBrightness)
+        );
+        assert_eq!(
+            config.update_from_form(b"brightness=9"),
+            Err(ConfigError::InvalidBrightness)
+        );
+        assert_eq!(
+            config.update_from_form(b"refresh=1"),
+            Err(ConfigError::InvalidRefreshInterval)
+        );
+        assert_eq!(
+            config.update_from_form(b"currency=U%26D"),
+            Err(ConfigError::InvalidCurrency)
+        );
+        assert_eq!(
+            config.update_from_form(b"currency="),
+            Err(ConfigError::InvalidCurrency)
+        );
+        assert_eq!(
+            config.update_from_form(b"currency=USD&currency=BTC&currency=EUR"),
+            Err(ConfigError::TooManyCurrencies)
+        );
+        assert_eq!(
+            config.update_from_form(b"symbol=BTC&symbol="),
+            Err(ConfigError::InvalidSymbol)
+        );
+        assert_eq!(
+            config.update_from_form(b"setup=yes"),
+            Err(ConfigError::InvalidSetupFlag)
+        );
+        assert_eq!(
+            config.update_from_form(b"refresh=%"),
+            Err(ConfigError::Form(FormError::InvalidPercentEncoding))
+        );
+
+        //failed updates leave the configuration untouched
+        assert_eq!(config, DeviceConfig::default());
+    }
+
+    #[test]
+    fn api_keys() {
+        let mut config = DeviceConfig::default();
+        config
+            .update_from_form(b"cryptocompare_key=0a1b2c3d&coingecko_key=CG-x_Y")
+            .unwrap();
+        assert_eq!(config.cryptocompare_api_key, "0a1b2c3d");
+        assert_eq!(config.coingecko_api_key, "CG-x_Y");
+
+        //the keys are stored with the rest of the configuration
+        let form = config.to_form().unwrap();
+        assert!(form.ends_with("&cryptocompare_key=0a1b2c3d&coingecko_key=CG-x_Y"));
+        assert_eq!(DeviceConfig::from_form(form.as_bytes()).unwrap(), config);
+
+        //the setup page never shows the keys, so an empty field keeps them
+        config
+            .update_from_form(b"cryptocompare_key=&coingecko_key=&brightness=4")
+            .unwrap();
+        assert_eq!(config.cryptocompare_api_key, "0a1b2c3d");
+        assert_eq!(config.coingecko_api_key, "CG-x_Y");
+
+        config.update_from_form(b"clear_key=coingecko").unwrap();
+        assert_eq!(config.cryptocompare_api_key, "0a1b2c3d");
+        assert!(config.coingecko_api_key.is_empty());
+
+        //a new key replaces the cleared one, wherever it is in the form
+        config
+            .update_from_form(b"cryptocompare_key=e4f5&clear_key=cryptocompare")
+            .unwrap();
+        assert_eq!(config.cryptocompare_api_key, "e4f5");
+        config
+            .update_from_form(b"clear_key=cryptocompare&cryptocompare_key=0a1b2c3d")
+            .unwrap();
+        assert_eq!(config.cryptocompare_api_key, "0a1b2c3d");
+
+        let unchanged = config.clone();
+        assert_eq!(
+            config.update_from_form(b"coingecko_key=a%26b"),
+            Err(ConfigError::InvalidApiKey)
+        );
+        assert_eq!(
+            config.update_from_form(b"clear_key=binance"),
+            Err(ConfigError::InvalidApiKey)
+        );
+        assert_eq!(config, unchanged);
+    }
+
+    #[test]
+    fn second_currency() {
+        let mut config = DeviceConfig::default();
+        config
+            .update_from_form(b"currency=USD&currency=BTC")
+            .unwrap();
+        assert_eq!(config.currencies, ["USD", "BTC"]);
+
+        let form = config.to_form().unwrap();
+        assert!(form.starts_with("setup=0&currency=USD&currency=BTC&"));
+        assert_eq!(DeviceConfig::from_form(form.as_bytes()).unwrap(), config);
+
+        //the second select left empty, or set to the first currency
+        config.update_from_form(b"currency=EUR&currency=").unwrap();
+        assert_eq!(config.currencies, ["EUR"]);
+        config
+            .update_from_form(b"currency=EUR&currency=EUR")
+            .unwrap();
+        assert_eq!(config.currencies, ["EUR"]);
+    }
+
+    #[test]
+    fn symbol_added_from_search() {
+        let mut config = DeviceConfig::default();
+        config
+            .update_from_form(b"symbol=BTC&symbol=ETH&add_symbol=arb")
+            .unwrap();
+        assert_eq!(config.symbols, ["BTC", "ETH", "ARB"]);
+
+        //an empty search box only keeps the checked symbols, even none
+        config.update_from_form(b"symbol=ETH&add_symbol=").unwrap();
+        assert_eq!(config.symbols, ["ETH"]);
+        config.update_from_form(b"add_symbol=").unwrap();
+        assert!(config.symbols.is_empty());
+
+        config
+            .update_from_form(b"symbol=ETH&add_symbol=eth")
+            .unwrap();
+        assert_eq!(config.symbols, ["ETH"]);
+
+        assert_eq!(
+            config.update_from_form(b"add_symbol=ET%2FH"),
+            Err(ConfigError::InvalidSymbol)
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Quote currencies prices can be shown in, and how prices are written on the display.
+
+use core::fmt::Write;
+use heapless::String;
+
+/// Currencies offered in the web UI. Any currency the price providers know can be set
+/// through the configuration, these are the common ones.
+pub const CURRENCIES: [&str; 10] = [
+    "USD", "EUR", "GBP", "PLN", "CHF", "JPY", "CAD", "AUD", "BTC", "ETH",
+];
+
+/// Longest price, prefix included, that fits a display region
+pub const MAX_PRICE_LENGTH: usize = 10;
+/// Significant digits shown of prices below 1, e.g. prices in BTC
+const SIGNIFICANT_DIGITS: usize = 4;
+const MAX_DECIMALS: usize = 8;
+
+/// The display font only has ASCII glyphs, currencies without an ASCII symbol are
+/// written with their code
+const SYMBOLS: [(&str, &str); 3] = [("USD", "$"), ("CAD", "C$"), ("AUD", "A$")];
+
+/// Text drawn in front of prices in `currency`, e.g. `$` or `EUR `
+pub fn prefix(currency: &str) -> String<16> {
+    let mut prefix = String::new();
+    match SYMBOLS
+        .iter()
+        .find(|(code, _)| code.eq_ignore_ascii_case(currency))
+    {
+        Some((_, symbol)) => {
+            prefix.push_str(symbol).ok();
+        }
+        None => {
+            for c in currency.chars() {
+                prefix.push(c.to_ascii_uppercase()).ok();
+            }
+            prefix.push(' ').ok();
+        }
+    }
+    prefix
+}
+
+/// Writes `price` with the prefix of `currency`. Decimals are dropped until the text fits
+/// in `MAX_PRICE_LENGTH`, but at least two significant digits of small prices are kept.
+pub fn format_price(currency: &str, price: f32) -> String<32> {
+    //position of the first significant digit after the decimal point, of prices below 1
+    let mut leading_zeros = 0;
+    let mut scaled = if price < 0.0 { -price } else { price };
+    while scaled > 0.0 && scaled < 1.0 && leading_zeros < MAX_DECIMALS {
+        scaled *= 10.0;
+        leading_zeros += 1;
+    }
+
+    let (mut decimals, min_decimals) = if leading_zeros == 0 {
+        (2, 0)
+    } else {
+        let decimals = leading_zeros + SIGNIFICANT_DIGITS - 1;
+        (decimals.min(MAX_DECIMALS), leading_zeros + 1)
+    };
+
+    let prefix = prefix(currency);
+    loop {
+        let mut text = String::new();
+        core::write!(text, "{}{:.*}", prefix, decimals, price).ok();
+
+        if text.len() <= MAX_PRICE_LENGTH || decimals <= min_decimals {
+            return text;
+        }
+        decimals -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(prefix("USD"), "$");
+        assert_eq!(prefix("cad"), "C$");
+        assert_eq!(prefix("eur"), "EUR ");
+        assert_eq!(prefix("BTC"), "BTC ");
+    }
+
+    #[test]
+    fn prices_fit_the_region() {
+        assert_eq!(format_price("USD", 43127.0), "$43127.00");
+        assert_eq!(format_price("USD", 2.5), "$2.50");
+        assert_eq!(format_price("USD", 0.0), "$0.00");
+        assert_eq!(format_price("EUR", 43127.0), "EUR 43127");
+        assert_eq!(format_price("EUR", 2291.5), "EUR 2291.5");
+    }
+
+    #[test]
+    fn small_prices_keep_significant_digits() {
+        assert_eq!(format_price("USD", 0.08237), "$0.08237");
+        assert_eq!(format_price("BTC", 0.05312), "BTC 0.0531");
+        //too long even with two digits, it is drawn past the region
+        assert_eq!(format_price("BTC", 0.0000019), "BTC 0.0000019");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::currency;
 use core::{fmt::Write, usize};
 use embedded_graphics::drawable::Drawable;
 use embedded_graphics::prelude::Primitive;
@@ -20,7 +21,7 @@ use embedded_graphics::{
     image::Image,
     pixelcolor::Rgb888,
     prelude::{Point, Size},
-    primitives::{Line, Triangle},
+    primitives::{Line, Rectangle, Triangle},
     style::PrimitiveStyle,
     style::TextStyle,
     DrawTarget,
@@ -125,15 +126,23 @@ impl<const PIN_POSITIONS0: Pins, const PIN_POSITIONS1: Pins, const PIN_POSITIONS
 }
 pub struct Screen {
     display_regions: Vec<(usize, usize), 32>,
+    // Lines of prices under every symbol, one for each currency
+    price_lines: usize,
 }
 impl Screen {
     pub fn new() -> Self {
         let mut screen = Screen {
             display_regions: Vec::<(usize, usize), 32>::new(),
+            price_lines: 1,
         };
         screen.generate_regions(SCREEN_RESOLUTION.0, SCREEN_RESOLUTION.1, FONT_SIZE);
         screen
     }
+    /// Makes room for `lines` lines of prices under every symbol, fewer symbols fit on the screen
+    pub fn set_price_lines(&mut self, lines: usize) {
+        self.price_lines = lines.max(1);
+        self.generate_regions(SCREEN_RESOLUTION.0, SCREEN_RESOLUTION.1, FONT_SIZE);
+    }
     fn generate_regions(
         &mut self,
         screen_width: usize,
@@ -141,7 +150,7 @@ impl Screen {
         font_size: (usize, usize),
     ) {
         let mut regions = Vec::<(usize, usize), 32>::new();
-        let min_vertical_space = font_size.1 * 2;
+        let min_vertical_space = font_size.1 * (1 + self.price_lines);
         let mut min_horizontal_space = (7 + 9 * font_size.0) / SINGLE_DISPLAY_RESOLUTION.0;
 
         min_horizontal_space = min_horizontal_space * SINGLE_DISPLAY_RESOLUTION.0;
@@ -161,19 +170,60 @@ impl Screen {
     }
 }
 
+/// Size of the header at the start of a TGA image
+pub const TGA_HEADER_SIZE: usize = 18;
+
+/// Checks if `header`, the first `TGA_HEADER_SIZE` bytes of a TGA image, describes an image
+/// that can be drawn as the intro logo. Lets an upload be refused before the whole of it
+/// has arrived.
+pub fn is_valid_logo_header(header: &[u8]) -> bool {
+    if header.len() < TGA_HEADER_SIZE {
+        return false;
+    }
+
+    let color_map_type = header[1];
+    let image_type = header[2];
+    let width = u16::from_le_bytes([header[12], header[13]]) as usize;
+    let height = u16::from_le_bytes([header[14], header[15]]) as usize;
+    let pixel_depth = header[16];
+
+    //Color mapped, true color and black and white images, plain or run-length encoded
+    matches!(color_map_type, 0 | 1)
+        && matches!(image_type, 1 | 2 | 3 | 9 | 10 | 11)
+        && matches!(pixel_depth, 8 | 16 | 24 | 32)
+        && width <= SCREEN_RESOLUTION.0
+        && height <= SCREEN_RESOLUTION.1
+}
+
+/// Checks if `data` is a TGA image that can be drawn as the intro logo
+pub fn is_valid_logo(data: &[u8]) -> bool {
+    match Tga::from_slice(data) {
+        Ok(tga) => {
+            tga.width() as usize <= SCREEN_RESOLUTION.0
+                && tga.height() as usize <= SCREEN_RESOLUTION.1
+        }
+        Err(_) => false,
+    }
+}
+
 pub trait DrawableCrypto<DisplayT: DrawTarget<Rgb888>> {
     /// Methods incorportaing text objects and DrawablePrimitives
+    /// * `prices` - current prices by currency, drawn one per line. The change is shown
+    ///   of the first one, against `price_24h`
     fn draw_crypto(
         &mut self,
         display: &mut DisplayT,
         symbol: String<16>,
-        price: Option<f32>,
-        change: Option<f32>,
+        prices: &[(&str, Option<f32>)],
+        price_24h: Option<f32>,
         font_size: (i32, i32),
         region: usize,
     );
-    fn draw_intro(&mut self, display: &mut DisplayT);
+    fn draw_intro(&mut self, display: &mut DisplayT, custom_logo: Option<&[u8]>);
+    fn draw_setup_info(&mut self, display: &mut DisplayT, ip: Option<[u8; 4]>);
     fn draw_wallet(&mut self, display: &mut DisplayT, wallet_value: f32, daily_change: f32);
+    /// Shows a short error message on the bottom line, over whatever is drawn there
+    fn draw_error(&mut self, display: &mut DisplayT, message: &str);
     fn clear(&mut self, display: &mut DisplayT);
 }
 pub trait DrawablePrimitives<DisplayT: DrawTarget<Rgb888>> {
@@ -243,7 +293,7 @@ impl<DisplayT: DrawTarget<Rgb888>> DrawableCrypto<DisplayT> for Screen {
         &mut self,
         display: &mut DisplayT,
         mut symbol: String<16>,
-        price_tick: Option<f32>,
+        prices: &[(&str, Option<f32>)],
         price_24h: Option<f32>,
         font_size: (i32, i32),
         region: usize,
@@ -251,8 +301,11 @@ impl<DisplayT: DrawTarget<Rgb888>> DrawableCrypto<DisplayT> for Screen {
         if self.display_regions.is_empty() {
             panic!("Display_regions empty Bruh");
         }
-        // Selecting regions of the screen
-        let region = self.display_regions.get(region).unwrap();
+        // Selecting regions of the screen, symbols that don't fit aren't drawn
+        let region = match self.display_regions.get(region) {
+            Some(region) => region,
+            None => return,
+        };
         let (x, y) = (region.0 as i32, region.1 as i32);
 
         // Shortening too long cryptocurrency symbols
@@ -264,113 +317,158 @@ impl<DisplayT: DrawTarget<Rgb888>> DrawableCrypto<DisplayT> for Screen {
             .into_styled(TextStyle::new(Font6x8, Rgb888::new(255, 170, 0)))
             .draw(display);
 
+        let price_tick = prices.first().and_then(|(_, price)| *price);
+
         // Drawing price change in [%]
-        match (price_tick, price_24h) {
-            (Some(pt), Some(p24h)) => {
-                // c stands for change
-                let mut c = (pt - p24h) * 100.0 / pt;
-                {
-                    let temp = (c * 10.0) as i32;
-                    c = (temp as f32) / 10.0
-                }
-                /*
-                    Handling edge cases when:
-                        - Change is over 99.99%; it would take too much space on the screen
-                        - Different lengths of cryptocurrency symbols,
-                          if the length is 4 or more the decimal point
-                          is dropped depending on the change
-                */
-                if c > 99.99 {
-                    c = 99.99;
-                } else if c < -99.99 {
-                    c = -99.99;
-                }
-                if c > 0.0 {
-                    self.draw_up_arrow(display, 1 + x, y);
-                } else if c < 0.0 {
-                    self.draw_down_arrow(display, 1 + x, y);
+        if let (Some(pt), Some(p24h)) = (price_tick, price_24h) {
+            // c stands for change
+            let mut c = (pt - p24h) * 100.0 / pt;
+            {
+                let temp = (c * 10.0) as i32;
+                c = (temp as f32) / 10.0
+            }
+            /*
+                Handling edge cases when:
+                    - Change is over 99.99%; it would take too much space on the screen
+                    - Different lengths of cryptocurrency symbols,
+                      if the length is 4 or more the decimal point
+                      is dropped depending on the change
+            */
+            if c > 99.99 {
+                c = 99.99;
+            } else if c < -99.99 {
+                c = -99.99;
+            }
+            if c > 0.0 {
+                self.draw_up_arrow(display, 1 + x, y);
+            } else if c < 0.0 {
+                self.draw_down_arrow(display, 1 + x, y);
+            } else {
+                self.draw_line(display, 1 + x, y)
+            }
+
+            // If cryptocurrency symbol is longer than 4 letters, the change is rounded to an integer
+            // .round() doesn't work, sadge
+            if symbol.len() > 4 || (symbol.len() == 4 && (c > 9.9 || c < -9.9)) {
+                let temp = c as i32;
+                let decimal: f32 = c - temp as f32;
+                if decimal < 0.5 {
+                    c = temp as f32;
                 } else {
-                    self.draw_line(display, 1 + x, y)
+                    c = (temp + 1) as f32;
                 }
+            }
+            // Drawing the artithmetic symbol for the change
+            let mut change_string = if c >= 0.0 {
+                String::<32>::from("+")
+            } else {
+                String::<32>::from("")
+            };
 
-                // If cryptocurrency symbol is longer than 4 letters, the change is rounded to an integer
-                // .round() doesn't work, sadge
-                if symbol.len() > 4 || (symbol.len() == 4 && (c > 9.9 || c < -9.9)) {
-                    let temp = c as i32;
-                    let decimal: f32 = c - temp as f32;
-                    if decimal < 0.5 {
-                        c = temp as f32;
-                    } else {
-                        c = (temp + 1) as f32;
-                    }
-                }
-                // Drawing the artithmetic symbol for the change
-                let mut change_string = if c >= 0.0 {
-                    String::<32>::from("+")
-                } else {
-                    String::<32>::from("")
-                };
+            // Workaround reason: if symbol.len == 3 and the change is round it wont draw out ".0"
+            // I don't have an idea how to do it otherwise
+            if symbol.len() == 3 {
+                core::write!(change_string, "{:.1}", c).ok();
+            } else {
+                core::write!(change_string, "{}", c).ok();
+            }
 
-                // Workaround reason: if symbol.len == 3 and the change is round it wont draw out ".0"
-                // I don't have an idea how to do it otherwise
-                if symbol.len() == 3 {
-                    core::write!(change_string, "{:.1}", c).ok();
-                } else {
-                    core::write!(change_string, "{}", c).ok();
-                }
+            core::write!(change_string, "{}", "%").ok();
 
-                core::write!(change_string, "{}", "%").ok();
+            let _change = Text::new(
+                change_string.as_str(),
+                Point::new(
+                    x + Screen::calculate_change_x_pos(symbol.len(), c) as i32,
+                    y,
+                ),
+            )
+            .into_styled(TextStyle::new(Font6x8, Rgb888::new(255, 170, 0)))
+            .draw(display);
+        }
 
-                let _change = Text::new(
-                    change_string.as_str(),
-                    Point::new(
-                        x + Screen::calculate_change_x_pos(symbol.len(), c) as i32,
-                        y,
-                    ),
-                )
+        // Drawing prices, one line for each currency
+        for (line, (currency, price)) in prices.iter().enumerate() {
+            let position = Point::new(1 + x, font_size.1 * (line as i32 + 1) + y);
+            let price_string = match price {
+                Some(price) => currency::format_price(currency, *price),
+                // If prices for the display have not been fetched yet "PENDING..." is drawn out instead
+                None if line == 0 => String::from("PENDING..."),
+                None => continue,
+            };
+            let _value = Text::new(price_string.as_str(), position)
                 .into_styled(TextStyle::new(Font6x8, Rgb888::new(255, 170, 0)))
                 .draw(display);
-
-                /* !!!IF THE CODE WORKS WITHOUT THIS COMMENT, YOU CAN YEET IT OUT!!!
-                // let mut price_string = String::<U32>::from("$");
-                // core::write!(price_string, "{:.2}", pt).ok();
-                // let _value = Text::new(price_string.as_str(), Point::new(1 + x, font_size.1 + y))
-                //     .into_styled(TextStyle::new(Font6x8, Rgb888::new(255, 170, 0)))
-                //     .draw(display);
-                 */
-            }
-            // Drawing price
-            (Some(pt), None) => {
-                let mut price_string = String::<32>::from("$");
-                core::write!(price_string, "{:.2}", pt).ok();
-                let _value = Text::new(price_string.as_str(), Point::new(1 + x, font_size.1 + y))
-                    .into_styled(TextStyle::new(Font6x8, Rgb888::new(255, 170, 0)))
-                    .draw(display);
-            }
-            // If prices for the display have not been fetched yet "PENDING..." is drawn out instead
-            _ => {
-                let _value = Text::new("PENDING...", Point::new(1 + x, font_size.1 + y))
-                    .into_styled(TextStyle::new(Font6x8, Rgb888::new(255, 170, 0)))
-                    .draw(display);
-            }
         }
     }
 
-    // Drawing intro screen
-    fn draw_intro(&mut self, display: &mut DisplayT) {
+    // Drawing intro screen. Uploaded logo is used if it's a valid image, built-in one otherwise
+    fn draw_intro(&mut self, display: &mut DisplayT, custom_logo: Option<&[u8]>) {
         let thaumatec_logo = include_bytes!("../img/thaumatec_tg_box.tga");
-        let tga = Tga::from_slice(thaumatec_logo).unwrap();
+        let logo = match custom_logo {
+            Some(logo) if is_valid_logo(logo) => logo,
+            _ => thaumatec_logo,
+        };
+        let tga = Tga::from_slice(logo).unwrap();
         let image: Image<Tga, Rgb888> = Image::new(&tga, Point::zero());
         match image.draw(display) {
             Ok(()) => (),
             _ => panic!("Failed at drawing intro screen"),
         }
     }
+    // Drawing first-boot instructions: the address of the setup page, or a notice that
+    // the address hasn't been assigned yet
+    fn draw_setup_info(&mut self, display: &mut DisplayT, ip: Option<[u8; 4]>) {
+        let style = TextStyle::new(Font6x8, Rgb888::new(255, 170, 0));
+
+        let _title = Text::new("DICE SETUP", Point::new(1, 0))
+            .into_styled(TextStyle::new(Font6x8, Rgb888::new(75, 146, 231)))
+            .draw(display);
+
+        match ip {
+            Some(ip) => {
+                let mut address = String::<32>::new();
+                core::write!(address, "{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]).ok();
+
+                let _hint = Text::new("Open in browser:", Point::new(1, 16))
+                    .into_styled(style)
+                    .draw(display);
+                let _scheme = Text::new("http://", Point::new(1, 28))
+                    .into_styled(style)
+                    .draw(display);
+                let _address = Text::new(address.as_str(), Point::new(1, 38))
+                    .into_styled(style)
+                    .draw(display);
+            }
+            None => {
+                let _hint = Text::new("Waiting for DHCP...", Point::new(1, 16))
+                    .into_styled(style)
+                    .draw(display);
+            }
+        }
+    }
     ///TODO:
     #[allow(unused_variables)]
     fn draw_wallet(&mut self, display: &mut DisplayT, wallet_value: f32, daily_change: f32) {
         unimplemented!();
     }
+    // Drawing an error message in red on the bottom line of the screen
+    fn draw_error(&mut self, display: &mut DisplayT, message: &str) {
+        let y = (SCREEN_RESOLUTION.1 - FONT_SIZE.1) as i32;
+
+        let _background = Rectangle::new(
+            Point::new(0, y),
+            Point::new(
+                SCREEN_RESOLUTION.0 as i32 - 1,
+                SCREEN_RESOLUTION.1 as i32 - 1,
+            ),
+        )
+        .into_styled(PrimitiveStyle::with_fill(Rgb888::new(0, 0, 0)))
+        .draw(display);
+
+        let _message = Text::new(message, Point::new(1, y))
+            .into_styled(TextStyle::new(Font6x8, Rgb888::new(255, 0, 0)))
+            .draw(display);
+    }
     // Clearing screen
     fn clear(&mut self, display: &mut DisplayT) {
         display.clear(Rgb888::new(0, 0, 0)).ok();
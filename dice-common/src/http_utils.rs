@@ -1,39 +1,7 @@
 #![allow(dead_code)]
 
-use heapless::String;
 use httparse::Status;
 
-/// Generates a GET request
-/// * `url` - a full url for resources you want to fetch
-fn http_get(url: &str) -> String<256> {
-    let mut request: String<256> = String::new();
-    let (hostname, path) = url.split_at(url.chars().position(|x| x == '/').unwrap());
-
-    request.push_str("GET ").unwrap();
-    request.push_str(path).unwrap();
-    request.push_str(" HTTP/1.1\r\nHost: ").unwrap();
-    request.push_str(hostname).unwrap();
-    request.push_str("\r\n\r\n").unwrap();
-
-    request
-}
-/// Genertaes a POST request
-/// * `url` - a full url for resources you want to fetch
-/// * `content` - the body of the post request
-fn http_post(url: &str, content: &str) -> String<256> {
-    let mut request: String<256> = String::new();
-    let (hostname, path) = url.split_at(url.chars().position(|x| x == '/').unwrap());
-
-    request.push_str("POST ").unwrap();
-    request.push_str(path).unwrap();
-    request.push_str(" HTTP/1.1\r\nHost: ").unwrap();
-    request.push_str(hostname).unwrap();
-    request.push_str("\r\n\r\n").unwrap();
-    request.push_str(content).unwrap();
-
-    request
-}
-
 /// Prints the body of a POST request
 /// * `post_req` - a post request in plaintext
 fn get_body(post_req: &str) -> Result<&str, httparse::Error> {
@@ -97,23 +65,6 @@ fn get_body_wrong_header() {
     assert_eq!(get_body(req).unwrap(), "Hello");
 }
 #[test]
-fn http_post_simple() {
-    let url = "albicla.com/admin/password";
-    let body = "show me ur password";
-    assert_eq!(
-        http_post(url, body),
-        "POST /admin/password HTTP/1.1\r\nHost: albicla.com\r\n\r\nshow me ur password"
-    );
-}
-#[test]
-fn http_get_simple() {
-    let url = "albicla.com/admin/password";
-    assert_eq!(
-        http_get(url),
-        "GET /admin/password HTTP/1.1\r\nHost: albicla.com\r\n\r\n"
-    );
-}
-#[test]
 fn respond_bad_request() {
     let req = "POST /api/TodoItems HTTP/1.1\r\nERROR\r\nContent-Type: application/json\r\nUser-Agent: PostmanRuntime/7.26.10\r\nAccept: */*\r\nPostman-Token: 9cb5469f-5d87-48d6-89f6-208b4eec51fa\r\nHost: localhost:8000\r\nConnection: keep-alive\r\nContent-Length: 83\r\n\r\n{\r\n\"Id\":1,\r\n\"name\":\"aaa\",\r\n\"isComplete\":true,\r\n\"Secret\":\"secret\"\r\n}";
     assert_ne!(respond(req), "200 OK");
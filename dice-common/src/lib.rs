@@ -1,6 +1,9 @@
 #![no_std]
 #![feature(const_generics)]
 
+pub mod catalog;
+pub mod config;
+pub mod currency;
 pub mod http_utils;
 pub use smoltcp;
 pub mod display;
@@ -0,0 +1,89 @@
+//! Delays between retries of failed requests, growing exponentially with every failure
+
+/// Exponential backoff with jitter. Every failure doubles the delay, up to `max_delay`, and
+/// a random part of it is taken off so that devices don't retry in lockstep.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    base_delay: u32,
+    max_delay: u32,
+    failures: u8,
+}
+
+impl Backoff {
+    /// Delays are given in any unit, the caller's ticks, usually seconds
+    pub fn new(base_delay: u32, max_delay: u32) -> Self {
+        Self {
+            base_delay,
+            max_delay,
+            failures: 0,
+        }
+    }
+
+    /// Records a failure and returns the delay before the next attempt. The delay lies
+    /// between half and all of `base_delay * 2^failures`, capped at `max_delay`, where
+    /// `random` picks the point in that range. A delay the server asked for with
+    /// `Retry-After` is never shortened.
+    pub fn next_delay(&mut self, random: u32, retry_after: Option<u32>) -> u32 {
+        let exponential = (self.base_delay as u64) << self.failures.min(32);
+        let exponential = exponential.min(self.max_delay as u64) as u32;
+
+        let half = exponential / 2;
+        let delay = exponential - half + random % (half + 1);
+
+        self.failures = self.failures.saturating_add(1);
+
+        delay.max(retry_after.unwrap_or(0))
+    }
+
+    /// Number of failures since the last success
+    pub fn failures(&self) -> u8 {
+        self.failures
+    }
+
+    /// Records a success, the next failure starts again from `base_delay`
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_grow_up_to_the_cap() {
+        let mut backoff = Backoff::new(4, 100);
+
+        //with no randomness the delays are at the low end of the range
+        for expected in &[2, 4, 8, 16, 32, 50, 50] {
+            assert_eq!(backoff.next_delay(0, None), *expected);
+        }
+        assert_eq!(backoff.failures(), 7);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(2, None), 4);
+
+        //doubling doesn't overflow after many failures
+        for _ in 0..300 {
+            assert!(backoff.next_delay(7, None) <= 100);
+        }
+    }
+
+    #[test]
+    fn jitter_stays_in_range() {
+        let mut backoff = Backoff::new(10, 1000);
+        backoff.next_delay(0, None);
+
+        for (random, expected) in &[(0, 10), (5, 15), (10, 20), (11, 10)] {
+            assert_eq!(backoff.clone().next_delay(*random, None), *expected);
+        }
+    }
+
+    #[test]
+    fn retry_after_is_honored() {
+        let mut backoff = Backoff::new(10, 60);
+
+        assert_eq!(backoff.next_delay(0, Some(120)), 120);
+        assert_eq!(backoff.next_delay(0, Some(3)), 10);
+    }
+}
@@ -0,0 +1,753 @@
+use crate::{
+    client::HttpClient,
+    crypto_api_client::{
+        self, insert_quote, is_concatenation, parse_time, update_stats, CandleReader,
+        CryptoApiClient, CryptoApiError, History, Interval, MarketStats, MarketStatsMap, PairName,
+        ParseErrorKind, Prices, UnknownPairs, MAX_CANDLES, MAX_PAIR_LENGTH, MAX_QUOTES,
+    },
+    json::{Path, Token},
+    request::Request,
+    resolver::StaticResolver,
+};
+use core::ops::Range;
+use drogue_network::{addr::Ipv4Addr, tcp::TcpStack};
+use heapless::{String, Vec};
+
+const NAME: &str = "Binance";
+
+/// There is no DNS on the device, the API host is resolved from this table.
+/// The host is behind a CDN, its address may change.
+const HOSTS: [(&str, Ipv4Addr); 1] = [("api.binance.com", Ipv4Addr::new(13, 227, 62, 83))];
+const RESOLVER: StaticResolver = StaticResolver::new(&HOSTS);
+/// Redirects followed when the API moves an endpoint
+const MAX_REDIRECTS: u8 = 3;
+/// Longest key or string kept while parsing responses, longer ones are skipped
+const MAX_TOKEN_LENGTH: usize = 32;
+/// Deepest values read from responses: `[<INDEX>].lastPrice`
+const MAX_DEPTH: usize = 2;
+/// Error code of a request with a trading pair the exchange doesn't have
+const INVALID_SYMBOL: &str = "-1121";
+
+type JsonPath = Path<MAX_TOKEN_LENGTH, MAX_DEPTH>;
+
+/// Assets traded against the symbols in place of currencies the exchange has no pairs for
+const QUOTE_ASSETS: [(&str, &str); 1] = [("USD", "USDT")];
+
+pub struct BinanceApiClient<StackT: TcpStack> {
+    //All requests go to the same host, a single connection is kept open
+    http: HttpClient<StackT, StaticResolver<'static>, 1>,
+    //pairs the exchange doesn't have, left out of the requests
+    unknown_pairs: UnknownPairs,
+}
+
+impl<StackT: TcpStack> BinanceApiClient<StackT> {
+    pub fn new() -> Self {
+        BinanceApiClient {
+            http: HttpClient::new(RESOLVER).max_redirects(MAX_REDIRECTS),
+            unknown_pairs: UnknownPairs::new(),
+        }
+    }
+
+    /// Trading pairs the exchange doesn't have, e.g. `GEROUSDT`. The symbols get no prices
+    /// in the currencies of these pairs.
+    pub fn unknown_pairs(&self) -> &[PairName] {
+        &self.unknown_pairs
+    }
+
+    /// Reads the 24 hour ticker of every symbol traded against each of `currencies`
+    fn get_tickers<'a, const MAX_CURRENCIES: usize>(
+        &mut self,
+        network: &mut StackT,
+        symbols: &'a [String<16>],
+        currencies: &'a [String<8>],
+    ) -> Result<Tickers<'a, MAX_CURRENCIES>, CryptoApiError> {
+        let mut tickers = Tickers::new(symbols, currencies)?;
+        let quotes = tickers.quotes.clone();
+        let http = &mut self.http;
+
+        //The whole batch is rejected if one of the pairs is unknown
+        crypto_api_client::read_known_pairs(
+            pair_names(symbols, &quotes).count(),
+            &mut self.unknown_pairs,
+            &|index| pair_names(symbols, &quotes).nth(index).flatten(),
+            &mut |batch, unknown| {
+                //Nothing to ask for when every symbol is priced in itself
+                let request = match construct_request(symbols, &quotes, batch, unknown)? {
+                    Some(request) => request,
+                    None => return Ok(()),
+                };
+
+                let result =
+                    crypto_api_client::read_json(http, network, &request, |path, token| {
+                        tickers.visit(path, token)
+                    });
+                tickers.check(result)
+            },
+        )?;
+
+        Ok(tickers)
+    }
+}
+
+impl<StackT: TcpStack> Default for BinanceApiClient<StackT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<StackT: TcpStack, const MAX_CURRENCIES: usize> CryptoApiClient<StackT, MAX_CURRENCIES>
+    for BinanceApiClient<StackT>
+{
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn disconnect(&mut self, network: &mut StackT) {
+        self.http.close_all(network);
+    }
+
+    //Public market data needs no key
+    fn set_api_key(&mut self, _provider: &str, _api_key: &str) -> Result<(), CryptoApiError> {
+        Ok(())
+    }
+
+    fn get_openday_price(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currencies: &[String<8>],
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError> {
+        let tickers = self.get_tickers(network, symbols, currencies)?;
+        Ok(tickers.open_prices)
+    }
+
+    fn get_current_prices(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currencies: &[String<8>],
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError> {
+        let tickers = self.get_tickers(network, symbols, currencies)?;
+        Ok(tickers.last_prices)
+    }
+
+    fn get_market_stats(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currency: &String<8>,
+    ) -> Result<MarketStatsMap<MAX_CURRENCIES>, CryptoApiError> {
+        //The exchange doesn't know how many coins there are, so there is no market cap
+        let tickers = self.get_tickers(network, symbols, core::slice::from_ref(currency))?;
+        Ok(tickers.stats)
+    }
+
+    fn get_history(
+        &mut self,
+        network: &mut StackT,
+        symbol: &String<16>,
+        currency: &String<8>,
+        interval: Interval,
+        count: usize,
+    ) -> Result<History, CryptoApiError> {
+        let count = count.min(MAX_CANDLES);
+        let quote = quote_asset(currency).ok_or(CryptoApiError::RequestError)?;
+        //The price of an asset in itself never changes, there is nothing to ask for
+        if is_same_asset(symbol, &quote) {
+            return Ok(History::new());
+        }
+
+        let request = construct_history_request(symbol, &quote, interval, count)?;
+        let mut candles = Candles::new(interval);
+
+        let result =
+            crypto_api_client::read_json(&mut self.http, network, &request, |path, token| {
+                candles.visit(path, token)
+            });
+
+        check_error(candles.error_code.as_deref(), result)?;
+        Ok(candles.reader.finish(count))
+    }
+
+    fn list_symbols(
+        &mut self,
+        network: &mut StackT,
+        currency: &String<8>,
+        on_symbol: &mut dyn FnMut(&str),
+    ) -> Result<(), CryptoApiError> {
+        let quote = quote_asset(currency).ok_or(CryptoApiError::RequestError)?;
+        //The lightest list of all trading pairs, `[{"symbol":"ETHBTC","price":"0.05"}]`
+        let request = Request::get("https://api.binance.com/api/v3/ticker/price")
+            .map_err(|_| CryptoApiError::RequestError)?;
+
+        crypto_api_client::read_json(&mut self.http, network, &request, |path, token| {
+            visit_listed(on_symbol, &quote, path, token);
+            Ok(())
+        })
+    }
+}
+
+/// Calls `on_symbol` with the base asset of a pair quoted in `quote`
+fn visit_listed(on_symbol: &mut dyn FnMut(&str), quote: &str, path: &JsonPath, token: Token) {
+    if let Token::String(Some(pair)) = token {
+        if path.matches(&["*", "symbol"]) && pair.len() > quote.len() && pair.ends_with(quote) {
+            on_symbol(&pair[..pair.len() - quote.len()]);
+        }
+    }
+}
+
+/// Values read from a `/ticker/24hr` response, by symbol and currency:
+/// `[{"symbol":"BTCUSDT","openPrice":"43718.01","lastPrice":"43127.00",...}]`
+struct Tickers<'a, const MAX_CURRENCIES: usize> {
+    //symbols asked for, the pairs in the response are translated back to them
+    symbols: &'a [String<16>],
+    //currencies asked for, with the assets they are quoted in
+    quotes: Vec<(&'a str, String<8>), MAX_QUOTES>,
+    //symbol and currency of the ticker being read
+    pair: Option<(&'a str, &'a str)>,
+    last_prices: Prices<MAX_CURRENCIES>,
+    open_prices: Prices<MAX_CURRENCIES>,
+    //of the pairs with the first currency
+    stats: MarketStatsMap<MAX_CURRENCIES>,
+    //errors come as `{"code":-1121,"msg":"Invalid symbol."}`
+    error_code: Option<String<8>>,
+}
+
+impl<'a, const MAX_CURRENCIES: usize> Tickers<'a, MAX_CURRENCIES> {
+    fn new(symbols: &'a [String<16>], currencies: &'a [String<8>]) -> Result<Self, CryptoApiError> {
+        let mut tickers = Tickers {
+            symbols,
+            quotes: Vec::new(),
+            pair: None,
+            last_prices: Prices::new(),
+            open_prices: Prices::new(),
+            stats: MarketStatsMap::new(),
+            error_code: None,
+        };
+
+        for currency in currencies.iter() {
+            let quote = quote_asset(currency).ok_or(CryptoApiError::RequestError)?;
+
+            //There is no pair of an asset with itself, its price is known anyway
+            for symbol in symbols
+                .iter()
+                .filter(|symbol| is_same_asset(symbol, &quote))
+            {
+                for prices in &mut [&mut tickers.last_prices, &mut tickers.open_prices] {
+                    insert_quote(prices, symbol, currency, 1.0).ok();
+                }
+            }
+
+            tickers
+                .quotes
+                .push((currency.as_str(), quote))
+                .map_err(|_| CryptoApiError::RequestError)?;
+        }
+
+        Ok(tickers)
+    }
+
+    fn visit(&mut self, path: &JsonPath, token: Token) -> Result<(), ParseErrorKind> {
+        if path.matches(&["code"]) {
+            if let Token::Number(code) = token {
+                self.error_code = code.parse().ok();
+            }
+            return Ok(());
+        }
+
+        //The symbol comes first in every ticker
+        match (path.len(), path.key(1), token) {
+            (2, Some("symbol"), Token::String(pair)) => {
+                self.pair = pair.and_then(|pair| self.pair_of(pair));
+                if self.pair.is_none() {
+                    return Err(ParseErrorKind::UnexpectedValue);
+                }
+            }
+            (2, Some("lastPrice"), token) => insert_price(&mut self.last_prices, self.pair, token)?,
+            (2, Some("openPrice"), token) => insert_price(&mut self.open_prices, self.pair, token)?,
+            (2, Some("highPrice"), token) => {
+                self.update_stats(token, |stats, high| stats.high_24h = Some(high))?
+            }
+            (2, Some("lowPrice"), token) => {
+                self.update_stats(token, |stats, low| stats.low_24h = Some(low))?
+            }
+            //`quoteVolume` is the value traded
+            (2, Some("volume"), token) => {
+                self.update_stats(token, |stats, volume| stats.volume_24h = Some(volume))?
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Sets a value of the market stats of the ticker being read, if it is of the first currency
+    fn update_stats(
+        &mut self,
+        token: Token,
+        update: impl FnOnce(&mut MarketStats, f32),
+    ) -> Result<(), ParseErrorKind> {
+        let value = parse_price(token)?;
+        let (symbol, currency) = self.pair.ok_or(ParseErrorKind::UnexpectedValue)?;
+
+        match self.quotes.first() {
+            Some(&(main, _)) if main == currency => {
+                update_stats(&mut self.stats, symbol, |stats| update(stats, value))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Symbol and currency asked for that `pair` trades, e.g. `BTCUSDT`
+    fn pair_of(&self, pair: &str) -> Option<(&'a str, &'a str)> {
+        for symbol in self.symbols.iter() {
+            for (currency, quote) in self.quotes.iter() {
+                if is_concatenation(pair, &[symbol, quote]) {
+                    return Some((symbol.as_str(), currency));
+                }
+            }
+        }
+        None
+    }
+
+    /// Turns the result of reading a response into an error, ready for the next response
+    fn check(&mut self, result: Result<(), CryptoApiError>) -> Result<(), CryptoApiError> {
+        check_error(self.error_code.take().as_deref(), result)
+    }
+}
+
+/// Values read from a `/klines` response, every candle is an array:
+/// `[[1705312800000,"43718.01","43840.00","43600.00","43700.00","1234.5",...]]`
+struct Candles {
+    reader: CandleReader,
+    error_code: Option<String<8>>,
+}
+
+impl Candles {
+    fn new(interval: Interval) -> Self {
+        Candles {
+            reader: CandleReader::new(interval),
+            error_code: None,
+        }
+    }
+
+    fn visit(&mut self, path: &JsonPath, token: Token) -> Result<(), ParseErrorKind> {
+        if path.matches(&["code"]) {
+            if let Token::Number(code) = token {
+                self.error_code = code.parse().ok();
+            }
+            return Ok(());
+        }
+
+        let (index, column) = match (path.len(), path.index(0), path.index(1)) {
+            (2, Some(index), Some(column)) => (index, column),
+            _ => return Ok(()),
+        };
+        //open time, open, high, low and close come first
+        match column {
+            0 => {
+                let time = parse_time(token, 1000)?;
+                self.reader.update(index, |candle| candle.time = time);
+            }
+            1..=4 => {
+                let price = parse_price(token)?;
+                self.reader.update(index, |candle| match column {
+                    1 => candle.open = price,
+                    2 => candle.high = price,
+                    3 => candle.low = price,
+                    _ => candle.close = price,
+                });
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// Turns the error the exchange gives for pairs it doesn't have into `UnknownSymbol`
+fn check_error(
+    error_code: Option<&str>,
+    result: Result<(), CryptoApiError>,
+) -> Result<(), CryptoApiError> {
+    match result {
+        Err(CryptoApiError::Status { status: 400, .. }) if error_code == Some(INVALID_SYMBOL) => {
+            Err(CryptoApiError::UnknownSymbol)
+        }
+        result => result,
+    }
+}
+
+/// Adds a price, which the exchange sends as a string to keep its precision
+fn insert_price<const MAX_CURRENCIES: usize>(
+    prices: &mut Prices<MAX_CURRENCIES>,
+    pair: Option<(&str, &str)>,
+    token: Token,
+) -> Result<(), ParseErrorKind> {
+    let price = parse_price(token)?;
+    let (symbol, currency) = pair.ok_or(ParseErrorKind::UnexpectedValue)?;
+
+    insert_quote(prices, symbol, currency, price)
+}
+
+/// Numbers are sent as strings to keep their precision
+fn parse_price(token: Token) -> Result<f32, ParseErrorKind> {
+    match token {
+        Token::String(Some(price)) => price.parse().map_err(|_| ParseErrorKind::UnexpectedValue),
+        _ => Err(ParseErrorKind::UnexpectedValue),
+    }
+}
+
+/// Asset the exchange trades the symbols against for `currency`
+fn quote_asset(currency: &str) -> Option<String<8>> {
+    let quote = QUOTE_ASSETS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(currency))
+        .map_or(currency, |&(_, quote)| quote);
+    uppercase(quote)
+}
+
+fn is_same_asset(symbol: &str, quote: &str) -> bool {
+    symbol.eq_ignore_ascii_case(quote)
+}
+
+fn uppercase<const SIZE: usize>(text: &str) -> Option<String<SIZE>> {
+    let mut uppercase = String::new();
+    for c in text.chars() {
+        uppercase.push(c.to_ascii_uppercase()).ok()?;
+    }
+    Some(uppercase)
+}
+
+/// Names of the trading pairs of `symbols` with `quotes`, e.g. `BTCUSDT`, in the order they
+/// are requested. Pairs of an asset with itself are left out, names too long are `None`.
+fn pair_names<'a>(
+    symbols: &'a [String<16>],
+    quotes: &'a [(&'a str, String<8>)],
+) -> impl Iterator<Item = Option<PairName>> + 'a {
+    symbols.iter().flat_map(move |symbol| {
+        quotes
+            .iter()
+            .filter(move |(_, quote)| !is_same_asset(symbol, quote))
+            .map(move |(_, quote)| {
+                let mut pair = uppercase::<MAX_PAIR_LENGTH>(symbol)?;
+                pair.push_str(quote).ok()?;
+                Some(pair)
+            })
+    })
+}
+
+/// Builds a GET request with symbols - a JSON array of the trading pairs with the indices
+/// in `batch`, e.g. `["BTCUSDT"]`, leaving out the `unknown` ones. There is no request if
+/// no pair is left, or all would be of an asset with itself.
+fn construct_request(
+    symbols: &[String<16>],
+    quotes: &[(&str, String<8>)],
+    batch: Range<usize>,
+    unknown: &[PairName],
+) -> Result<Option<Request<'static>>, CryptoApiError> {
+    let mut pairs: String<512> = String::new();
+
+    let mut push = |text: &str| {
+        pairs
+            .push_str(text)
+            .map_err(|_| CryptoApiError::RequestError)
+    };
+
+    let mut count = 0;
+    push("[")?;
+    for (index, pair) in pair_names(symbols, quotes).enumerate() {
+        let pair = pair.ok_or(CryptoApiError::RequestError)?;
+        if !batch.contains(&index) || unknown.contains(&pair) {
+            continue;
+        }
+        if count > 0 {
+            push(",")?;
+        }
+        push("\"")?;
+        push(&pair)?;
+        push("\"")?;
+        count += 1;
+    }
+    push("]")?;
+
+    if count == 0 {
+        return Ok(None);
+    }
+
+    let request = Request::get("https://api.binance.com/api/v3/ticker/24hr")
+        .map_err(|_| CryptoApiError::RequestError)?
+        .query("symbols", &pairs);
+
+    Ok(Some(request))
+}
+
+/// Builds a GET request for the candles of the pair of `symbol` with `quote`
+fn construct_history_request(
+    symbol: &str,
+    quote: &str,
+    interval: Interval,
+    count: usize,
+) -> Result<Request<'static>, CryptoApiError> {
+    let mut pair = uppercase::<24>(symbol).ok_or(CryptoApiError::RequestError)?;
+    pair.push_str(quote)
+        .map_err(|_| CryptoApiError::RequestError)?;
+    let mut limit = String::<8>::new();
+    core::fmt::write(&mut limit, format_args!("{}", count))
+        .map_err(|_| CryptoApiError::RequestError)?;
+
+    let request = Request::get("https://api.binance.com/api/v3/klines")
+        .map_err(|_| CryptoApiError::RequestError)?
+        .query("symbol", &pair)
+        .query(
+            "interval",
+            match interval {
+                Interval::Hour => "1h",
+                Interval::Day => "1d",
+            },
+        )
+        .query("limit", &limit);
+
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto_api_client::tests::{currencies, price, read_fixture};
+    use crate::test_support::{ok_response, MockStack, Step};
+
+    const TICKER_24HR: &[u8] = include_bytes!("../fixtures/binance/ticker_24hr.json");
+    const INVALID_SYMBOL_ERROR: &[u8] = include_bytes!("../fixtures/binance/invalid_symbol.json");
+    const KLINES: &[u8] = include_bytes!("../fixtures/binance/klines.json");
+    const TICKER_PRICE: &[u8] = include_bytes!("../fixtures/binance/ticker_price.json");
+
+    fn symbols(symbols: &[&str]) -> Vec<String<16>, 4> {
+        symbols.iter().map(|&symbol| String::from(symbol)).collect()
+    }
+
+    fn read_tickers<'a>(
+        body: &[u8],
+        symbols: &'a [String<16>],
+        currencies: &'a [String<8>],
+    ) -> Result<Tickers<'a, 4>, ParseErrorKind> {
+        let mut tickers = Tickers::new(symbols, currencies).unwrap();
+        read_fixture(body, |path, token| tickers.visit(path, token))?;
+        Ok(tickers)
+    }
+
+    #[test]
+    fn tickers() {
+        let symbols = symbols(&["BTC", "ETH"]);
+        let currencies = currencies(&["USD", "BTC"]);
+        let tickers = read_tickers(TICKER_24HR, &symbols, &currencies).unwrap();
+
+        assert_eq!(tickers.last_prices.len(), 2);
+        assert_eq!(price(&tickers.last_prices, "BTC", "USD"), Some(43127.0));
+        assert_eq!(price(&tickers.last_prices, "ETH", "USD"), Some(2291.5));
+        assert_eq!(price(&tickers.last_prices, "ETH", "BTC"), Some(0.05313));
+        assert_eq!(price(&tickers.open_prices, "BTC", "USD"), Some(43718.01));
+        assert_eq!(price(&tickers.open_prices, "ETH", "USD"), Some(2245.68));
+        assert_eq!(price(&tickers.open_prices, "ETH", "BTC"), Some(0.05137));
+        //there is no BTCBTC pair
+        assert_eq!(price(&tickers.last_prices, "BTC", "BTC"), Some(1.0));
+    }
+
+    #[test]
+    fn market_stats() {
+        let symbols = symbols(&["BTC", "ETH"]);
+        let currencies = currencies(&["USD", "BTC"]);
+        let tickers = read_tickers(TICKER_24HR, &symbols, &currencies).unwrap();
+
+        assert_eq!(
+            tickers.stats.get(&String::from("ETH")),
+            Some(&MarketStats {
+                high_24h: Some(2297.8),
+                low_24h: Some(2231.19),
+                volume_24h: Some(302934.15),
+                market_cap: None,
+            })
+        );
+        //the ETHBTC ticker is left out
+        assert_eq!(tickers.stats.len(), 2);
+        assert_eq!(
+            tickers.stats.get(&String::from("BTC")).unwrap().high_24h,
+            Some(43840.0)
+        );
+    }
+
+    #[test]
+    fn pairs_for_another_quote() {
+        //pairs with a quote asset that wasn't asked for can't be told apart from other symbols
+        let symbols = symbols(&["BTC", "ETH"]);
+        assert_eq!(
+            read_tickers(TICKER_24HR, &symbols, &currencies(&["EUR"])).err(),
+            Some(ParseErrorKind::UnexpectedValue)
+        );
+    }
+
+    #[test]
+    fn unknown_pairs() {
+        let status = CryptoApiError::Status {
+            status: 400,
+            retry_after: None,
+        };
+        let symbols = symbols(&["BTC", "NOPE"]);
+        let currencies = currencies(&["USD"]);
+
+        let mut tickers = read_tickers(INVALID_SYMBOL_ERROR, &symbols, &currencies).unwrap();
+        assert_eq!(
+            tickers.check(Err(status)).err(),
+            Some(CryptoApiError::UnknownSymbol)
+        );
+
+        //other failures are returned as they are
+        let mut tickers = read_tickers(
+            b"{\"code\":-1100,\"msg\":\"Illegal characters\"}",
+            &symbols,
+            &currencies,
+        )
+        .unwrap();
+        assert_eq!(tickers.check(Err(status)).err(), Some(status));
+    }
+
+    #[test]
+    fn pairs_are_sent_as_an_array() {
+        let symbols = symbols(&["btc", "ETH"]);
+        let usd_btc = currencies(&["usd", "BTC"]);
+        let tickers = Tickers::<4>::new(&symbols, &usd_btc).unwrap();
+        let request = construct_request(&symbols, &tickers.quotes, 0..3, &[])
+            .unwrap()
+            .unwrap()
+            .serialize::<512>()
+            .unwrap();
+
+        assert!(request.starts_with(
+            b"GET /api/v3/ticker/24hr?symbols=%5B%22BTCUSDT%22%2C%22ETHUSDT%22%2C%22ETHBTC%22%5D \
+              HTTP/1.1\r\n"
+        ));
+        assert_eq!(tickers.quotes[0], ("usd", String::from("USDT")));
+
+        //a part of the batch, without the pairs known to be missing
+        let request = construct_request(&symbols, &tickers.quotes, 1..3, &["ETHBTC".into()])
+            .unwrap()
+            .unwrap()
+            .serialize::<512>()
+            .unwrap();
+        assert!(request.starts_with(b"GET /api/v3/ticker/24hr?symbols=%5B%22ETHUSDT%22%5D "));
+        assert!(
+            construct_request(&symbols, &tickers.quotes, 2..3, &["ETHBTC".into()])
+                .unwrap()
+                .is_none()
+        );
+
+        let bitcoin = currencies(&["BTC"]);
+        let tickers = Tickers::<4>::new(&symbols[..1], &bitcoin).unwrap();
+        assert!(construct_request(&symbols[..1], &tickers.quotes, 0..0, &[])
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn unknown_pairs_are_left_out() {
+        const REJECTED: &[u8] = b"HTTP/1.1 400 Bad Request\r\nContent-Length: 38\r\n\r\n\
+                                  {\"code\":-1121,\"msg\":\"Invalid symbol.\"}";
+        let btc = ok_response::<128>(br#"[{"symbol":"BTCUSDT","lastPrice":"43127.00"}]"#);
+        let eth = ok_response::<128>(br#"[{"symbol":"ETHUSDT","lastPrice":"2291.50"}]"#);
+        let both = ok_response::<128>(
+            br#"[{"symbol":"BTCUSDT","lastPrice":"43130.00"},{"symbol":"ETHUSDT","lastPrice":"2290.00"}]"#,
+        );
+        //the batch is split in BTC and GERO with ETH, which is split again
+        let scripts: [&[Step]; 1] = [&[
+            Step::Respond(REJECTED),
+            Step::Respond(&btc),
+            Step::Respond(REJECTED),
+            Step::Respond(REJECTED),
+            Step::Respond(&eth),
+            Step::Respond(&both),
+        ]];
+        let mut stack = MockStack::new(&scripts);
+        let mut client = BinanceApiClient::new();
+        let usd = currencies(&["USD"]);
+
+        let prices = client
+            .get_current_prices(&mut stack, &symbols(&["BTC", "GERO", "ETH"]), &usd)
+            .unwrap();
+        assert_eq!(price(&prices, "BTC", "USD"), Some(43127.0));
+        assert_eq!(price(&prices, "ETH", "USD"), Some(2291.5));
+        assert_eq!(price(&prices, "GERO", "USD"), None);
+        assert_eq!(client.unknown_pairs(), ["GEROUSDT"]);
+        assert_eq!(stack.written_count("GET /api/v3/ticker/24hr"), 5);
+
+        //the unknown pair is left out from then on
+        let prices = client
+            .get_current_prices(&mut stack, &symbols(&["BTC", "GERO", "ETH"]), &usd)
+            .unwrap();
+        assert_eq!(price(&prices, "ETH", "USD"), Some(2290.0));
+        assert_eq!(
+            stack.written_count("?symbols=%5B%22BTCUSDT%22%2C%22ETHUSDT%22%5D "),
+            1
+        );
+        assert_eq!(
+            client.get_current_prices(&mut stack, &symbols(&["GERO"]), &usd),
+            Err(CryptoApiError::UnknownSymbol)
+        );
+        assert_eq!(stack.written_count("GET /api/v3/ticker/24hr"), 6);
+    }
+
+    #[test]
+    fn history() {
+        let mut candles = Candles::new(Interval::Hour);
+        read_fixture(KLINES, |path, token| candles.visit(path, token)).unwrap();
+        let history = candles.reader.finish(24);
+
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].time, 1705392000);
+        assert_eq!(history[0].open, 43401.2);
+        assert_eq!(history[0].high, 43483.9);
+        assert_eq!(history[0].low, 43270.4);
+        assert_eq!(history[2].close, 43127.0);
+
+        let mut candles = Candles::new(Interval::Hour);
+        read_fixture(INVALID_SYMBOL_ERROR, |path, token| {
+            candles.visit(path, token)
+        })
+        .unwrap();
+        let status = CryptoApiError::Status {
+            status: 400,
+            retry_after: None,
+        };
+        assert_eq!(
+            check_error(candles.error_code.as_deref(), Err(status)),
+            Err(CryptoApiError::UnknownSymbol)
+        );
+    }
+
+    #[test]
+    fn history_requests() {
+        let request = construct_history_request("eth", "USDT", Interval::Hour, 24)
+            .unwrap()
+            .serialize::<512>()
+            .unwrap();
+
+        assert!(request
+            .starts_with(b"GET /api/v3/klines?symbol=ETHUSDT&interval=1h&limit=24 HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn listed_symbols() {
+        let mut symbols = Vec::<String<16>, 4>::new();
+        read_fixture(TICKER_PRICE, |path, token| {
+            visit_listed(
+                &mut |symbol| symbols.push(String::from(symbol)).unwrap(),
+                "USDT",
+                path,
+                token,
+            );
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(symbols, ["BTC", "ETH", "1INCH"]);
+    }
+}
@@ -0,0 +1,282 @@
+//! Decoder of `Transfer-Encoding: chunked` response bodies.
+//! The body may be fed in arbitrary pieces, as they come from the socket.
+//! Chunk extensions are ignored, trailer fields are collected as headers.
+
+use crate::headers::HttpHeaders;
+use heapless::Vec;
+
+/// Longest accepted chunk-size or trailer line
+const MAX_LINE_LENGTH: usize = 256;
+
+#[derive(Debug, PartialEq)]
+pub enum ChunkedError {
+    InvalidChunkSize,
+    ChunkSizeOverflow,
+    MissingCrlf,
+    LineTooLong,
+    InvalidTrailer,
+}
+
+#[derive(Debug, PartialEq)]
+enum State {
+    Size,
+    //Chunk extensions or whitespace after the chunk size
+    SizeSuffix,
+    SizeLf,
+    Data,
+    DataCr,
+    DataLf,
+    Trailer,
+    Finished,
+}
+
+pub struct ChunkedDecoder {
+    state: State,
+    chunk_size: usize,
+    size_digits: usize,
+    line_length: usize,
+    trailer_line: Vec<u8, MAX_LINE_LENGTH>,
+    trailers: HttpHeaders,
+}
+
+impl Default for ChunkedDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChunkedDecoder {
+    pub fn new() -> Self {
+        ChunkedDecoder {
+            state: State::Size,
+            chunk_size: 0,
+            size_digits: 0,
+            line_length: 0,
+            trailer_line: Vec::new(),
+            trailers: HttpHeaders::new(),
+        }
+    }
+
+    /// Decodes a piece of the body, passing decoded data to `output`.
+    /// Returns the number of bytes consumed, which is less than `input.len()` only if the
+    /// body ends before the end of `input`.
+    /// # Arguments
+    /// * `input` - Next piece of the encoded body
+    /// * `output` - Called with every run of decoded data
+    pub fn feed(
+        &mut self,
+        input: &[u8],
+        mut output: impl FnMut(&[u8]),
+    ) -> Result<usize, ChunkedError> {
+        let mut position = 0;
+
+        while position < input.len() && self.state != State::Finished {
+            if self.state == State::Data {
+                let count = self.chunk_size.min(input.len() - position);
+                output(&input[position..position + count]);
+
+                position += count;
+                self.chunk_size -= count;
+
+                if self.chunk_size == 0 {
+                    self.state = State::DataCr;
+                }
+                continue;
+            }
+
+            let byte = input[position];
+            position += 1;
+
+            match self.state {
+                State::Size => self.size_byte(byte)?,
+                State::SizeSuffix => match byte {
+                    b'\r' => self.state = State::SizeLf,
+                    _ => self.count_line_byte()?,
+                },
+                State::SizeLf => {
+                    if byte != b'\n' {
+                        return Err(ChunkedError::MissingCrlf);
+                    }
+                    self.size_digits = 0;
+                    self.line_length = 0;
+                    self.state = if self.chunk_size == 0 {
+                        State::Trailer
+                    } else {
+                        State::Data
+                    };
+                }
+                State::DataCr => match byte {
+                    b'\r' => self.state = State::DataLf,
+                    _ => return Err(ChunkedError::MissingCrlf),
+                },
+                State::DataLf => match byte {
+                    b'\n' => self.state = State::Size,
+                    _ => return Err(ChunkedError::MissingCrlf),
+                },
+                State::Trailer => self.trailer_byte(byte)?,
+                State::Data | State::Finished => unreachable!(),
+            }
+        }
+
+        Ok(position)
+    }
+
+    /// True once the terminating chunk and the trailer section have been decoded
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Finished
+    }
+
+    /// Trailer fields sent after the last chunk
+    pub fn trailers(&self) -> &HttpHeaders {
+        &self.trailers
+    }
+
+    pub fn into_trailers(self) -> HttpHeaders {
+        self.trailers
+    }
+
+    fn size_byte(&mut self, byte: u8) -> Result<(), ChunkedError> {
+        let digit = match byte {
+            b'0'..=b'9' => byte - b'0',
+            b'a'..=b'f' => byte - b'a' + 10,
+            b'A'..=b'F' => byte - b'A' + 10,
+            b';' | b' ' | b'\t' if self.size_digits > 0 => {
+                self.state = State::SizeSuffix;
+                return Ok(());
+            }
+            b'\r' if self.size_digits > 0 => {
+                self.state = State::SizeLf;
+                return Ok(());
+            }
+            _ => return Err(ChunkedError::InvalidChunkSize),
+        };
+
+        self.chunk_size = self
+            .chunk_size
+            .checked_mul(16)
+            .and_then(|size| size.checked_add(digit as usize))
+            .ok_or(ChunkedError::ChunkSizeOverflow)?;
+        self.size_digits += 1;
+
+        self.count_line_byte()
+    }
+
+    fn count_line_byte(&mut self) -> Result<(), ChunkedError> {
+        self.line_length += 1;
+        if self.line_length > MAX_LINE_LENGTH {
+            return Err(ChunkedError::LineTooLong);
+        }
+        Ok(())
+    }
+
+    fn trailer_byte(&mut self, byte: u8) -> Result<(), ChunkedError> {
+        if byte != b'\n' {
+            return self
+                .trailer_line
+                .push(byte)
+                .map_err(|_| ChunkedError::LineTooLong);
+        }
+
+        let line = match self.trailer_line.split_last() {
+            Some((&b'\r', line)) => line,
+            _ => return Err(ChunkedError::MissingCrlf),
+        };
+
+        if line.is_empty() {
+            self.state = State::Finished;
+            return Ok(());
+        }
+
+        let line = core::str::from_utf8(line).map_err(|_| ChunkedError::InvalidTrailer)?;
+        let colon = line.find(':').ok_or(ChunkedError::InvalidTrailer)?;
+        let (name, value) = (&line[..colon], &line[colon + 1..]);
+
+        if name.is_empty() {
+            return Err(ChunkedError::InvalidTrailer);
+        }
+
+        self.trailers.push(name, value.as_bytes());
+        self.trailer_line.clear();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODED: &[u8] =
+        b"7\r\n{\"BTC\":\r\n1a;name=value\r\n{\"USD\":35000.1},\"ETH\":{\"US\r\n9\r\nD\":2000}}\r\n0\r\nExpires: never\r\nX-Count:  3\r\n\r\n";
+    const DECODED: &[u8] = b"{\"BTC\":{\"USD\":35000.1},\"ETH\":{\"USD\":2000}}";
+
+    fn decode_in_pieces(input: &[u8], piece_size: usize) -> (Vec<u8, 128>, ChunkedDecoder) {
+        let mut decoder = ChunkedDecoder::new();
+        let mut body = Vec::new();
+
+        for piece in input.chunks(piece_size) {
+            let consumed = decoder
+                .feed(piece, |data| body.extend_from_slice(data).unwrap())
+                .unwrap();
+            assert_eq!(consumed, piece.len());
+        }
+
+        (body, decoder)
+    }
+
+    #[test]
+    fn decode_with_every_piece_size() {
+        for piece_size in 1..=ENCODED.len() {
+            let (body, decoder) = decode_in_pieces(ENCODED, piece_size);
+
+            assert_eq!(&body[..], DECODED);
+            assert!(decoder.is_finished());
+            assert_eq!(decoder.trailers().len(), 2);
+            assert_eq!(decoder.trailers().get("expires"), Some("never"));
+            assert_eq!(decoder.trailers().get("X-Count"), Some("3"));
+        }
+    }
+
+    #[test]
+    fn data_after_last_chunk_is_not_consumed() {
+        let mut decoder = ChunkedDecoder::new();
+        let mut body = Vec::<u8, 16>::new();
+
+        let input = b"3\r\nabc\r\n0\r\n\r\nHTTP/1.1 200 OK";
+        let consumed = decoder
+            .feed(input, |data| body.extend_from_slice(data).unwrap())
+            .unwrap();
+
+        assert_eq!(&input[consumed..], b"HTTP/1.1 200 OK");
+        assert_eq!(&body[..], b"abc");
+        assert!(decoder.is_finished());
+    }
+
+    #[test]
+    fn unfinished_body() {
+        let (body, decoder) = decode_in_pieces(b"3\r\nabc\r\n", 4);
+
+        assert_eq!(&body[..], b"abc");
+        assert!(!decoder.is_finished());
+    }
+
+    #[test]
+    fn malformed_bodies_are_rejected() {
+        let cases: [(&[u8], ChunkedError); 6] = [
+            (b"\r\n", ChunkedError::InvalidChunkSize),
+            (b"x\r\n", ChunkedError::InvalidChunkSize),
+            (b"3\r\nabcd\r\n", ChunkedError::MissingCrlf),
+            (b"3\r\nabc\n", ChunkedError::MissingCrlf),
+            (
+                b"fffffffffffffffffffff\r\n",
+                ChunkedError::ChunkSizeOverflow,
+            ),
+            (b"0\r\nno colon\r\n\r\n", ChunkedError::InvalidTrailer),
+        ];
+
+        for (input, error) in cases.iter() {
+            let mut decoder = ChunkedDecoder::new();
+            assert_eq!(decoder.feed(input, |_| {}).unwrap_err(), *error);
+        }
+    }
+}
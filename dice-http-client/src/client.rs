@@ -0,0 +1,433 @@
+//! HTTP client keeping connections open between requests.
+
+use crate::http_client::{
+    self, BodySink, BodyWriter, HttpError, HttpResponse, StreamingBody, REQUEST_BUFFER_SIZE,
+};
+use crate::inflate::Format;
+use crate::request::{Method, Request};
+use crate::resolver::Resolver;
+use crate::url::Url;
+use drogue_network::tcp::TcpStack;
+use heapless::{String, Vec};
+
+/// Longest host name a connection can be kept open for
+const MAX_HOST_LENGTH: usize = 64;
+
+struct Connection<SocketT> {
+    host: String<MAX_HOST_LENGTH>,
+    port: u16,
+    socket: SocketT,
+}
+
+/// Client keeping up to `MAX_CONNECTIONS` idle connections open with `Connection: keep-alive`,
+/// one per host. If the server has closed an idle connection, the request is sent again on
+/// a new one, unless it is a POST or part of the response has already been received.
+pub struct HttpClient<StackT: TcpStack, ResolverT: Resolver, const MAX_CONNECTIONS: usize> {
+    resolver: ResolverT,
+    max_redirects: u8,
+    connections: Vec<Connection<StackT::TcpSocket>, MAX_CONNECTIONS>,
+}
+
+impl<StackT: TcpStack, ResolverT: Resolver, const MAX_CONNECTIONS: usize>
+    HttpClient<StackT, ResolverT, MAX_CONNECTIONS>
+{
+    pub fn new(resolver: ResolverT) -> Self {
+        HttpClient {
+            resolver,
+            max_redirects: 0,
+            connections: Vec::new(),
+        }
+    }
+
+    /// Follows up to `max_redirects` redirects. By default redirects are returned as they are.
+    pub fn max_redirects(mut self, max_redirects: u8) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    pub fn send<const MAX_RESPONSE_LENGTH: usize>(
+        &mut self,
+        stack: &mut StackT,
+        request: &Request,
+    ) -> Result<HttpResponse<MAX_RESPONSE_LENGTH>, HttpError> {
+        let max_redirects = self.max_redirects;
+
+        http_client::follow_redirects(request, max_redirects, |request| {
+            let mut body = BodyWriter::new();
+            let response = self.exchange(stack, request, &mut body)?;
+            body.into_response(response)
+        })
+    }
+
+    /// Sends the request, passing the body of the response to `sink` piece by piece instead of
+    /// collecting it. The response returned has an empty body.
+    /// Compressed bodies are inflated as they arrive, which takes a window of
+    /// `inflate::WINDOW_SIZE` bytes on the stack. If `sink` returns an error, reading stops
+    /// with `BodyRejected`.
+    pub fn send_streaming(
+        &mut self,
+        stack: &mut StackT,
+        request: &Request,
+        sink: impl FnMut(&[u8]) -> Result<(), ()>,
+    ) -> Result<HttpResponse<0>, HttpError> {
+        let max_redirects = self.max_redirects;
+        let mut body = StreamingBody::new(sink, max_redirects > 0);
+
+        let response = http_client::follow_redirects(request, max_redirects, |request| {
+            self.exchange(stack, request, &mut body)
+        })?;
+        body.finish()?;
+
+        Ok(response)
+    }
+
+    /// Closes all idle connections
+    pub fn close_all(&mut self, stack: &mut StackT) {
+        while let Some(connection) = self.connections.pop() {
+            stack.close(connection.socket).ok();
+        }
+    }
+
+    fn exchange<SinkT: BodySink>(
+        &mut self,
+        stack: &mut StackT,
+        request: &Request,
+        body: &mut SinkT,
+    ) -> Result<HttpResponse<0>, HttpError> {
+        let keep_alive_request;
+        let request = if request.has_header("Connection") {
+            request
+        } else {
+            keep_alive_request = request.clone().header("Connection", "keep-alive");
+            &keep_alive_request
+        };
+
+        let data: Vec<u8, REQUEST_BUFFER_SIZE> = request.serialize()?;
+        let url = request.url();
+        let head = request.method() == Method::Head;
+
+        if let Some(socket) = self.take_connection(stack, url) {
+            let mut tracked = Tracked {
+                body,
+                started: false,
+            };
+
+            match self.exchange_on(stack, socket, url, &data, head, &mut tracked) {
+                //The server may have closed the connection while it was idle
+                Err(HttpError::WriteError(_))
+                | Err(HttpError::ReadError(_))
+                | Err(HttpError::ConnectionClosed)
+                    if request.method() != Method::Post && !tracked.started => {}
+                result => return result,
+            }
+        }
+
+        let socket = http_client::connect(stack, &self.resolver, url)?;
+        self.exchange_on(stack, socket, url, &data, head, body)
+    }
+
+    fn exchange_on<SinkT: BodySink>(
+        &mut self,
+        stack: &mut StackT,
+        mut socket: StackT::TcpSocket,
+        url: &Url,
+        data: &[u8],
+        head: bool,
+        body: &mut SinkT,
+    ) -> Result<HttpResponse<0>, HttpError> {
+        let response = http_client::write_all(stack, &mut socket, data)
+            .and_then(|_| http_client::read_response_into(stack, &mut socket, head, body));
+
+        match &response {
+            Ok(response) if response.keep_alive() => self.keep_connection(stack, url, socket),
+            _ => {
+                stack.close(socket).ok();
+            }
+        }
+
+        response
+    }
+
+    /// Removes the idle connection to the host of `url` from the pool, if it is still open
+    fn take_connection(&mut self, stack: &mut StackT, url: &Url) -> Option<StackT::TcpSocket> {
+        let index = self.connections.iter().position(|connection| {
+            connection.port == url.port && connection.host.eq_ignore_ascii_case(url.host)
+        })?;
+        let connection = self.connections.swap_remove(index);
+
+        match stack.is_connected(&connection.socket) {
+            Ok(true) => Some(connection.socket),
+            _ => {
+                stack.close(connection.socket).ok();
+                None
+            }
+        }
+    }
+
+    /// Puts the connection in the pool, closing another idle connection if there is no room
+    fn keep_connection(&mut self, stack: &mut StackT, url: &Url, socket: StackT::TcpSocket) {
+        let mut host = String::new();
+        if host.push_str(url.host).is_err() {
+            stack.close(socket).ok();
+            return;
+        }
+
+        if self.connections.is_full() && !self.connections.is_empty() {
+            let evicted = self.connections.swap_remove(0);
+            stack.close(evicted.socket).ok();
+        }
+
+        let connection = Connection {
+            host,
+            port: url.port,
+            socket,
+        };
+        if let Err(connection) = self.connections.push(connection) {
+            stack.close(connection.socket).ok();
+        }
+    }
+}
+
+/// Records whether the response has started to arrive
+struct Tracked<'a, SinkT> {
+    body: &'a mut SinkT,
+    started: bool,
+}
+
+impl<'a, SinkT: BodySink> BodySink for Tracked<'a, SinkT> {
+    fn start(&mut self, status: u16, coding: Option<Format>) -> Result<(), HttpError> {
+        self.started = true;
+        self.body.start(status, coding)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), HttpError> {
+        self.body.write(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::StaticResolver;
+    use crate::test_support::{MockStack, Step};
+    use drogue_network::addr::Ipv4Addr;
+
+    const HOSTS: [(&str, Ipv4Addr); 2] = [
+        ("api.example.com", Ipv4Addr::new(10, 0, 0, 1)),
+        ("other.example.com", Ipv4Addr::new(10, 0, 0, 2)),
+    ];
+
+    type TestClient<'a> = HttpClient<MockStack<'a>, StaticResolver<'static>, 2>;
+
+    fn get<'a>(
+        client: &mut TestClient<'a>,
+        stack: &mut MockStack<'a>,
+        url: &str,
+    ) -> Result<HttpResponse<64>, HttpError> {
+        client.send(stack, &Request::get(url).unwrap())
+    }
+
+    const OK_A: Step = Step::Respond(b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\na");
+    const OK_B: Step =
+        Step::Respond(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n1\r\nb\r\n0\r\n\r\n");
+
+    #[test]
+    fn connection_is_reused() {
+        let connections: [&[Step]; 1] = [&[OK_A, OK_B, OK_A]];
+        let mut stack = MockStack::new(&connections);
+        let mut client = TestClient::new(StaticResolver::new(&HOSTS));
+
+        for expected in ["a", "b", "a"].iter() {
+            let response = get(&mut client, &mut stack, "http://api.example.com/").unwrap();
+            assert_eq!(response.body_str(), Ok(*expected));
+            assert!(response.keep_alive());
+        }
+
+        assert_eq!(stack.opened(), 1);
+        assert_eq!(stack.closed(), 0);
+        assert_eq!(stack.written_count("Connection: keep-alive\r\n"), 3);
+
+        client.close_all(&mut stack);
+        assert_eq!(stack.closed(), 1);
+    }
+
+    #[test]
+    fn reconnect_after_server_closed_idle_connection() {
+        let connections: [&[Step]; 2] = [&[OK_A], &[OK_B]];
+        let mut stack = MockStack::new(&connections);
+        let mut client = TestClient::new(StaticResolver::new(&HOSTS));
+
+        let first = get(&mut client, &mut stack, "http://api.example.com/").unwrap();
+        let second = get(&mut client, &mut stack, "http://api.example.com/").unwrap();
+
+        assert_eq!(first.body_str(), Ok("a"));
+        assert_eq!(second.body_str(), Ok("b"));
+        assert_eq!(stack.opened(), 2);
+        assert_eq!(stack.closed(), 1);
+    }
+
+    #[test]
+    fn post_is_not_sent_again() {
+        let connections: [&[Step]; 2] = [&[OK_A], &[OK_B]];
+        let mut stack = MockStack::new(&connections);
+        let mut client = TestClient::new(StaticResolver::new(&HOSTS));
+
+        get(&mut client, &mut stack, "http://api.example.com/").unwrap();
+        let request = Request::post("http://api.example.com/", b"x=1").unwrap();
+
+        assert_eq!(
+            client.send::<64>(&mut stack, &request).unwrap_err(),
+            HttpError::ConnectionClosed
+        );
+        assert_eq!(stack.opened(), 1);
+    }
+
+    #[test]
+    fn connections_are_kept_per_host() {
+        let connections: [&[Step]; 2] = [&[OK_A, OK_A], &[OK_B, OK_B]];
+        let mut stack = MockStack::new(&connections);
+        let mut client = TestClient::new(StaticResolver::new(&HOSTS));
+
+        let urls = [
+            ("http://api.example.com/", "a"),
+            ("http://other.example.com/", "b"),
+            ("http://API.example.com/x", "a"),
+            ("http://other.example.com/y", "b"),
+        ];
+        for (url, expected) in urls.iter() {
+            let response = get(&mut client, &mut stack, url).unwrap();
+            assert_eq!(response.body_str(), Ok(*expected), "{}", url);
+        }
+
+        assert_eq!(stack.opened(), 2);
+    }
+
+    #[test]
+    fn non_persistent_connections_are_closed() {
+        let responses: [&[u8]; 3] = [
+            b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 1\r\n\r\na",
+            b"HTTP/1.0 200 OK\r\nContent-Length: 1\r\n\r\na",
+            b"HTTP/1.1 200 OK\r\n\r\na",
+        ];
+
+        //The server closes these connections after the response
+        for response in responses.iter() {
+            let connections: [&[Step]; 2] = [&[Step::Respond(response)], &[OK_B]];
+            let mut stack = MockStack::new(&connections);
+            let mut client = TestClient::new(StaticResolver::new(&HOSTS));
+
+            let first = get(&mut client, &mut stack, "http://api.example.com/").unwrap();
+            assert!(!first.keep_alive());
+            assert_eq!(stack.closed(), 1);
+
+            get(&mut client, &mut stack, "http://api.example.com/").unwrap();
+            assert_eq!(stack.opened(), 2);
+        }
+    }
+
+    #[test]
+    fn streamed_body() {
+        let connections: [&[Step]; 2] = [
+            &[
+                Step::Respond(b"HTTP/1.1 302 Found\r\nLocation: /b\r\nContent-Length: 8\r\n\r\nredirect"),
+                Step::Respond(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\n{\"a\r\n4\r\n\":1}\r\n0\r\n\r\n"),
+                Step::Respond(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}"),
+            ],
+            &[OK_A],
+        ];
+        let mut stack = MockStack::new(&connections);
+        let mut client = TestClient::new(StaticResolver::new(&HOSTS)).max_redirects(1);
+        let request = Request::get("http://api.example.com/a").unwrap();
+
+        let mut body = Vec::<u8, 64>::new();
+        let response = client
+            .send_streaming(&mut stack, &request, |data| {
+                body.extend_from_slice(data).map_err(|_| ())
+            })
+            .unwrap();
+
+        assert_eq!(response.status, 200);
+        assert!(response.body_bytes().is_empty());
+        assert_eq!(&body[..], b"{\"a\":1}");
+        assert_eq!(stack.written_count("Accept-Encoding: gzip, deflate\r\n"), 2);
+
+        assert_eq!(
+            client
+                .send_streaming(&mut stack, &request, |_| Err(()))
+                .unwrap_err(),
+            HttpError::BodyRejected
+        );
+
+        //The connection was closed after the body was rejected
+        let response = get(&mut client, &mut stack, "http://api.example.com/").unwrap();
+        assert_eq!(response.body_str(), Ok("a"));
+        assert_eq!(stack.opened(), 2);
+    }
+
+    #[test]
+    fn compressed_body_is_streamed() {
+        use crate::inflate::tests::{DECOMPRESSED, GZIP};
+        use crate::inflate::InflateError;
+
+        let response = |head: &[u8], body: &[u8]| {
+            let mut response = Vec::<u8, 256>::new();
+            response.extend_from_slice(head).unwrap();
+            response.extend_from_slice(body).unwrap();
+            response
+        };
+        let whole = response(
+            b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: 86\r\n\r\n",
+            GZIP,
+        );
+        let truncated = response(
+            b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: 60\r\n\r\n",
+            &GZIP[..60],
+        );
+        let connections: [&[Step]; 1] = [&[Step::Respond(&whole), Step::Respond(&truncated)]];
+        let mut stack = MockStack::new(&connections).read_size(7);
+        let mut client = TestClient::new(StaticResolver::new(&HOSTS));
+        let request = Request::get("http://api.example.com/").unwrap();
+
+        let mut body = Vec::<u8, 256>::new();
+        client
+            .send_streaming(&mut stack, &request, |data| {
+                body.extend_from_slice(data).map_err(|_| ())
+            })
+            .unwrap();
+        assert_eq!(&body[..], DECOMPRESSED);
+
+        assert_eq!(
+            client
+                .send_streaming(&mut stack, &request, |_| Ok(()))
+                .unwrap_err(),
+            HttpError::InvalidCompressedBody(InflateError::Truncated)
+        );
+    }
+
+    #[test]
+    fn responses_without_body() {
+        let connections: [&[Step]; 1] = [&[
+            Step::Respond(b"HTTP/1.1 204 No Content\r\n\r\n"),
+            Step::Respond(b"HTTP/1.1 304 Not Modified\r\nContent-Length: 10\r\n\r\n"),
+            Step::Respond(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n"),
+            OK_A,
+        ]];
+        let mut stack = MockStack::new(&connections);
+        let mut client = TestClient::new(StaticResolver::new(&HOSTS));
+
+        for status in [204, 304].iter() {
+            let response = get(&mut client, &mut stack, "http://api.example.com/").unwrap();
+            assert_eq!(response.status, *status);
+            assert!(response.body_bytes().is_empty());
+        }
+
+        let head = Request::new(Method::Head, "http://api.example.com/").unwrap();
+        let response = client.send::<64>(&mut stack, &head).unwrap();
+        assert_eq!(response.headers.content_length(), Ok(Some(5)));
+        assert!(response.body_bytes().is_empty());
+
+        let response = get(&mut client, &mut stack, "http://api.example.com/").unwrap();
+        assert_eq!(response.body_str(), Ok("a"));
+        assert_eq!(stack.opened(), 1);
+    }
+}
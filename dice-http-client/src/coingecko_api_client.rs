@@ -0,0 +1,653 @@
+use crate::{
+    client::HttpClient,
+    crypto_api_client::{
+        self, find_currency, insert_price, insert_quote, parse_number, parse_time, update_stats,
+        ApiKey, CandleReader, CryptoApiClient, CryptoApiError, History, Interval, MarketStatsMap,
+        ParseErrorKind, Prices, MAX_CANDLES,
+    },
+    json::{Path, Token},
+    request::Request,
+    resolver::StaticResolver,
+};
+use drogue_network::{addr::Ipv4Addr, tcp::TcpStack};
+use heapless::{String, Vec};
+
+const NAME: &str = "CoinGecko";
+
+/// There is no DNS on the device, the API host is resolved from this table.
+/// The host is behind a CDN, its address may change.
+const HOSTS: [(&str, Ipv4Addr); 1] = [("api.coingecko.com", Ipv4Addr::new(104, 18, 3, 110))];
+const RESOLVER: StaticResolver = StaticResolver::new(&HOSTS);
+/// Redirects followed when the API moves an endpoint
+const MAX_REDIRECTS: u8 = 3;
+/// Longest key or string kept while parsing responses, longer ones are skipped
+const MAX_TOKEN_LENGTH: usize = 32;
+/// Deepest values read from responses: `<ID>.<CURRENCY>`
+const MAX_DEPTH: usize = 2;
+
+type JsonPath = Path<MAX_TOKEN_LENGTH, MAX_DEPTH>;
+
+/// CoinGecko identifies coins by IDs rather than by their ticker symbols.
+/// Symbols missing from this table are left out of requests.
+const COIN_IDS: [(&str, &str); 121] = [
+    ("ETH", "ethereum"),
+    ("BTC", "bitcoin"),
+    ("BNB", "binancecoin"),
+    ("XRP", "ripple"),
+    ("MATIC", "matic-network"),
+    ("DOGE", "dogecoin"),
+    ("ETC", "ethereum-classic"),
+    ("ADA", "cardano"),
+    ("LTC", "litecoin"),
+    ("DOT", "polkadot"),
+    ("BCH", "bitcoin-cash"),
+    ("EOS", "eos"),
+    ("LINK", "chainlink"),
+    ("FIL", "filecoin"),
+    ("UNI", "uniswap"),
+    ("XLM", "stellar"),
+    ("VET", "vechain"),
+    ("TRX", "tron"),
+    ("SOL", "solana"),
+    ("LUNA", "terra-luna"),
+    ("OMG", "omisego"),
+    ("CAKE", "pancakeswap-token"),
+    ("YFI", "yearn-finance"),
+    ("HT", "huobi-token"),
+    ("QTUM", "qtum"),
+    ("NEO", "neo"),
+    ("SUSHI", "sushi"),
+    ("OKB", "okb"),
+    ("BSV", "bitcoin-cash-sv"),
+    ("AAVE", "aave"),
+    ("THETA", "theta-token"),
+    ("ONT", "ontology"),
+    ("ZEC", "zcash"),
+    ("KSM", "kusama"),
+    ("XVS", "venus"),
+    ("RUNE", "thorchain"),
+    ("DASH", "dash"),
+    ("CHZ", "chiliz"),
+    ("MKR", "maker"),
+    ("ATOM", "cosmos"),
+    ("SXP", "swipe"),
+    ("BAKE", "bakerytoken"),
+    ("ENJ", "enjincoin"),
+    ("WAVES", "waves"),
+    ("XMR", "monero"),
+    ("MANA", "decentraland"),
+    ("ONE", "harmony"),
+    ("WRX", "wazirx"),
+    ("XTZ", "tezos"),
+    ("CRV", "curve-dao-token"),
+    ("FTT", "ftx-token"),
+    ("HOT", "holotoken"),
+    ("IOST", "iostoken"),
+    ("AVAX", "avalanche-2"),
+    ("HBAR", "hedera-hashgraph"),
+    ("FTM", "fantom"),
+    ("ZEN", "zencash"),
+    ("MIOTA", "iota"),
+    ("ZIL", "zilliqa"),
+    ("CHR", "chromaway"),
+    ("GRT", "the-graph"),
+    ("KAVA", "kava"),
+    ("SC", "siacoin"),
+    ("ALGO", "algorand"),
+    ("WBTC", "wrapped-bitcoin"),
+    ("1INCH", "1inch"),
+    ("COMP", "compound-governance-token"),
+    ("LSK", "lisk"),
+    ("ZRX", "0x"),
+    ("SRM", "serum"),
+    ("FLOW", "flow"),
+    ("BAT", "basic-attention-token"),
+    ("NANO", "nano"),
+    ("SNX", "havven"),
+    ("XEM", "nem"),
+    ("ICX", "icon"),
+    ("RSR", "reserve-rights-token"),
+    ("BURGER", "burger-swap"),
+    ("RLC", "iexec-rlc"),
+    ("TRB", "tellor"),
+    ("KLAY", "klay-token"),
+    ("EGLD", "elrond-erd-2"),
+    ("NEAR", "near"),
+    ("DENT", "dent"),
+    ("RVN", "ravencoin"),
+    ("LRC", "loopring"),
+    ("VTHO", "vethor-token"),
+    ("KNC", "kyber-network-crystal"),
+    ("WIN", "wink"),
+    ("ANKR", "ankr"),
+    ("BAND", "band-protocol"),
+    ("JST", "just"),
+    ("BTG", "bitcoin-gold"),
+    ("BNT", "bancor"),
+    ("REP", "augur"),
+    ("CRO", "crypto-com-chain"),
+    ("ALPHA", "alpha-finance"),
+    ("OCEAN", "ocean-protocol"),
+    ("HIVE", "hive"),
+    ("TFUEL", "theta-fuel"),
+    ("STORJ", "storj"),
+    ("YFII", "yfii-finance"),
+    ("OGN", "origin-protocol"),
+    ("STMX", "storm"),
+    ("COTI", "coti"),
+    ("GT", "gatechain-token"),
+    ("MTL", "metal"),
+    ("MLK", "milk-alliance"),
+    ("MONA", "monacoin"),
+    ("SNT", "status"),
+    ("DGB", "digibyte"),
+    ("QKC", "quark-chain"),
+    ("CELR", "celer-network"),
+    ("INJ", "injective-protocol"),
+    ("PAXG", "pax-gold"),
+    ("REN", "republic-protocol"),
+    ("UNFI", "unifi-protocol-dao"),
+    ("NKN", "nkn"),
+    ("CELO", "celo"),
+    ("BAL", "balancer"),
+    ("STEEM", "steem"),
+];
+
+pub struct CoinGeckoApiClient<StackT: TcpStack> {
+    //All requests go to the same host, a single connection is kept open
+    http: HttpClient<StackT, StaticResolver<'static>, 1>,
+    //sent with every request if not empty
+    api_key: ApiKey,
+}
+
+impl<StackT: TcpStack> CoinGeckoApiClient<StackT> {
+    pub fn new() -> Self {
+        CoinGeckoApiClient {
+            http: HttpClient::new(RESOLVER).max_redirects(MAX_REDIRECTS),
+            api_key: ApiKey::new(),
+        }
+    }
+
+    /// Reads the price of every symbol in `currencies`, and the values `extras` turn on,
+    /// e.g. `include_24hr_change`
+    fn get_simple_prices<'a, const MAX_CURRENCIES: usize>(
+        &mut self,
+        network: &mut StackT,
+        symbols: &[String<16>],
+        currencies: &'a [String<8>],
+        extras: &[&str],
+    ) -> Result<SimplePrices<'a, MAX_CURRENCIES>, CryptoApiError> {
+        let mut prices = SimplePrices::new(currencies);
+        let request = construct_request(symbols, currencies, extras)?;
+        //Keys of the free plan, paid plans use another host
+        let request = crypto_api_client::with_api_key(request, "x_cg_demo_api_key", &self.api_key);
+
+        crypto_api_client::read_json(&mut self.http, network, &request, |path, token| {
+            prices.visit(path, token)
+        })?;
+
+        Ok(prices)
+    }
+}
+
+impl<StackT: TcpStack> Default for CoinGeckoApiClient<StackT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<StackT: TcpStack, const MAX_CURRENCIES: usize> CryptoApiClient<StackT, MAX_CURRENCIES>
+    for CoinGeckoApiClient<StackT>
+{
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn disconnect(&mut self, network: &mut StackT) {
+        self.http.close_all(network);
+    }
+
+    fn set_api_key(&mut self, provider: &str, api_key: &str) -> Result<(), CryptoApiError> {
+        crypto_api_client::update_api_key(&mut self.api_key, NAME, provider, api_key)
+    }
+
+    fn get_openday_price(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currencies: &[String<8>],
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError> {
+        //There is no endpoint for the price at a given time without an API key,
+        //it's worked out from the current price and its change
+        let prices =
+            self.get_simple_prices(network, symbols, currencies, &["include_24hr_change"])?;
+        Ok(prices.openday_prices())
+    }
+
+    fn get_current_prices(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currencies: &[String<8>],
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError> {
+        let prices = self.get_simple_prices(network, symbols, currencies, &[])?;
+        Ok(prices.prices)
+    }
+
+    fn get_market_stats(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currency: &String<8>,
+    ) -> Result<MarketStatsMap<MAX_CURRENCIES>, CryptoApiError> {
+        //Highs and lows are only on the endpoints of single coins, the rest comes with prices
+        let prices = self.get_simple_prices(
+            network,
+            symbols,
+            core::slice::from_ref(currency),
+            &["include_market_cap", "include_24hr_vol"],
+        )?;
+        Ok(prices.market_stats())
+    }
+
+    fn get_history(
+        &mut self,
+        network: &mut StackT,
+        symbol: &String<16>,
+        currency: &String<8>,
+        interval: Interval,
+        count: usize,
+    ) -> Result<History, CryptoApiError> {
+        let count = count.min(MAX_CANDLES);
+        let id = id_of(symbol).ok_or(CryptoApiError::UnknownSymbol)?;
+        let mut url: String<128> = String::new();
+        core::fmt::write(
+            &mut url,
+            format_args!("https://api.coingecko.com/api/v3/coins/{}/ohlc", id),
+        )
+        .map_err(|_| CryptoApiError::RequestError)?;
+
+        let request = construct_history_request(&url, currency, interval, count)?;
+        let request = crypto_api_client::with_api_key(request, "x_cg_demo_api_key", &self.api_key);
+        let mut candles = CandleReader::new(interval);
+
+        crypto_api_client::read_json(&mut self.http, network, &request, |path, token| {
+            visit_candle(&mut candles, path, token)
+        })?;
+
+        Ok(candles.finish(count))
+    }
+
+    fn list_symbols(
+        &mut self,
+        _network: &mut StackT,
+        _currency: &String<8>,
+        on_symbol: &mut dyn FnMut(&str),
+    ) -> Result<(), CryptoApiError> {
+        //The API lists thousands of coins, but only those with a known ID can be asked for
+        for (symbol, _id) in COIN_IDS.iter() {
+            on_symbol(symbol);
+        }
+        Ok(())
+    }
+}
+
+/// Values read from a `/simple/price` response, by symbol:
+/// `{"bitcoin":{"usd":35000.1,"usd_24h_change":-1.5,"btc":1.0,"btc_24h_change":0.0}}`
+struct SimplePrices<'a, const MAX_CURRENCIES: usize> {
+    //currencies asked for, they are lowercase in the API
+    currencies: &'a [String<8>],
+    prices: Prices<MAX_CURRENCIES>,
+    //in percent
+    changes: Prices<MAX_CURRENCIES>,
+    //in the first currency, with the volume as its value rather than the amount traded
+    stats: MarketStatsMap<MAX_CURRENCIES>,
+}
+
+impl<'a, const MAX_CURRENCIES: usize> SimplePrices<'a, MAX_CURRENCIES> {
+    fn new(currencies: &'a [String<8>]) -> Self {
+        SimplePrices {
+            currencies,
+            prices: Prices::new(),
+            changes: Prices::new(),
+            stats: MarketStatsMap::new(),
+        }
+    }
+
+    fn visit(&mut self, path: &JsonPath, token: Token) -> Result<(), ParseErrorKind> {
+        let (id, field) = match (path.len(), path.key(0), path.key(1)) {
+            (2, Some(id), Some(field)) => (id, field),
+            _ => return Ok(()),
+        };
+        let symbol = match symbol_of(id) {
+            Some(symbol) => symbol,
+            None => return Ok(()),
+        };
+
+        if let Some(currency) = find_currency(self.currencies, field) {
+            insert_price(&mut self.prices, Some(symbol), currency, token)
+        } else if let Some(currency) = field
+            .strip_suffix("_24h_change")
+            .and_then(|name| find_currency(self.currencies, name))
+        {
+            //The change is null for coins that weren't traded in the last 24 hours
+            if token == Token::Null {
+                return Ok(());
+            }
+            insert_price(&mut self.changes, Some(symbol), currency, token)
+        } else if let Some(market_cap) = field.strip_suffix("_market_cap") {
+            if !self.is_main_currency(market_cap) || token == Token::Null {
+                return Ok(());
+            }
+            let market_cap = parse_number(token)?;
+            update_stats(&mut self.stats, symbol, |stats| {
+                stats.market_cap = Some(market_cap)
+            })
+        } else if let Some(volume) = field.strip_suffix("_24h_vol") {
+            if !self.is_main_currency(volume) || token == Token::Null {
+                return Ok(());
+            }
+            let volume = parse_number(token)?;
+            update_stats(&mut self.stats, symbol, |stats| {
+                stats.volume_24h = Some(volume)
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_main_currency(&self, name: &str) -> bool {
+        find_currency(&self.currencies[..1], name).is_some()
+    }
+
+    /// Market stats in the first currency, with the volume turned into the amount traded.
+    /// Volumes of symbols with no price are left out.
+    fn market_stats(&self) -> MarketStatsMap<MAX_CURRENCIES> {
+        let mut stats = self.stats.clone();
+        let main = &self.currencies[0];
+
+        for (symbol, stats) in stats.iter_mut() {
+            let price = self
+                .prices
+                .get(symbol)
+                .and_then(|quotes| quotes.get(main))
+                .copied();
+            stats.volume_24h = match (stats.volume_24h, price) {
+                (Some(volume), Some(price)) if price > 0.0 => Some(volume / price),
+                _ => None,
+            };
+        }
+
+        stats
+    }
+
+    /// Prices 24 hours ago, of the symbols with the price known. Prices with no change
+    /// reported stayed the same.
+    fn openday_prices(&self) -> Prices<MAX_CURRENCIES> {
+        let mut openday = Prices::new();
+
+        for (symbol, quotes) in self.prices.iter() {
+            for (currency, price) in quotes.iter() {
+                let change = self
+                    .changes
+                    .get(symbol)
+                    .and_then(|changes| changes.get(currency))
+                    .copied()
+                    .unwrap_or(0.0);
+                insert_quote(
+                    &mut openday,
+                    symbol,
+                    currency,
+                    price / (1.0 + change / 100.0),
+                )
+                .ok();
+            }
+        }
+
+        openday
+    }
+}
+
+/// Reads a candle from an `/ohlc` response, every candle is an array:
+/// `[[1705393800000,43401.2,43483.9,43270.4,43307.8]]`
+fn visit_candle(
+    candles: &mut CandleReader,
+    path: &JsonPath,
+    token: Token,
+) -> Result<(), ParseErrorKind> {
+    let (index, column) = match (path.len(), path.index(0), path.index(1)) {
+        (2, Some(index), Some(column)) => (index, column),
+        _ => return Ok(()),
+    };
+
+    match column {
+        //the time is when the candle closes, a second earlier it's still in its interval
+        0 => {
+            let time = parse_time(token, 1000)?.saturating_sub(1);
+            candles.update(index, |candle| candle.time = time);
+        }
+        1..=4 => {
+            let price = parse_number(token)?;
+            candles.update(index, |candle| match column {
+                1 => candle.open = price,
+                2 => candle.high = price,
+                3 => candle.low = price,
+                _ => candle.close = price,
+            });
+        }
+        _ => return Err(ParseErrorKind::UnexpectedValue),
+    }
+
+    Ok(())
+}
+
+fn id_of(symbol: &str) -> Option<&'static str> {
+    COIN_IDS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(symbol))
+        .map(|&(_, id)| id)
+}
+
+fn symbol_of(id: &str) -> Option<&'static str> {
+    COIN_IDS
+        .iter()
+        .find(|(_, known)| *known == id)
+        .map(|&(symbol, _)| symbol)
+}
+
+/// Builds a GET request with ids - the selected cryptos, and vs_currencies - the currencies to convert to
+fn construct_request(
+    symbols: &[String<16>],
+    currencies: &[String<8>],
+    extras: &[&str],
+) -> Result<Request<'static>, CryptoApiError> {
+    let mut joined_ids: String<512> = String::new();
+
+    for id in symbols.iter().filter_map(|symbol| id_of(symbol)) {
+        if !joined_ids.is_empty() {
+            joined_ids
+                .push(',')
+                .map_err(|_| CryptoApiError::RequestError)?;
+        }
+        joined_ids
+            .push_str(id)
+            .map_err(|_| CryptoApiError::RequestError)?;
+    }
+
+    let mut lowercase_currencies: String<64> = String::new();
+    for (i, currency) in currencies.iter().enumerate() {
+        if i > 0 {
+            lowercase_currencies
+                .push(',')
+                .map_err(|_| CryptoApiError::RequestError)?;
+        }
+        for c in currency.chars() {
+            lowercase_currencies
+                .push(c.to_ascii_lowercase())
+                .map_err(|_| CryptoApiError::RequestError)?;
+        }
+    }
+
+    let mut request = Request::get("https://api.coingecko.com/api/v3/simple/price")
+        .map_err(|_| CryptoApiError::RequestError)?
+        .query("ids", &joined_ids)
+        .query("vs_currencies", &lowercase_currencies);
+    for extra in extras.iter() {
+        request = request.query(extra, "true");
+    }
+
+    Ok(request)
+}
+
+/// Builds a GET request for the candles at `url`. The length of the candles depends on
+/// the number of days asked for: 30 minutes for a day, 4 hours for up to 30 days.
+fn construct_history_request<'a>(
+    url: &'a str,
+    currency: &str,
+    interval: Interval,
+    count: usize,
+) -> Result<Request<'a>, CryptoApiError> {
+    let days = match interval {
+        Interval::Hour => "1",
+        Interval::Day if count <= 7 => "7",
+        Interval::Day if count <= 14 => "14",
+        Interval::Day => "30",
+    };
+    let mut lowercase_currency: String<8> = String::new();
+    for c in currency.chars() {
+        lowercase_currency
+            .push(c.to_ascii_lowercase())
+            .map_err(|_| CryptoApiError::RequestError)?;
+    }
+
+    let request = Request::get(url)
+        .map_err(|_| CryptoApiError::RequestError)?
+        .query("vs_currency", &lowercase_currency)
+        .query("days", days);
+
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto_api_client::tests::{currencies, price, read_fixture};
+
+    const SIMPLE_PRICE: &[u8] = include_bytes!("../fixtures/coingecko/simple_price.json");
+    const OHLC: &[u8] = include_bytes!("../fixtures/coingecko/ohlc.json");
+
+    fn read_prices<'a>(body: &[u8], currencies: &'a [String<8>]) -> SimplePrices<'a, 4> {
+        let mut prices = SimplePrices::new(currencies);
+        read_fixture(body, |path, token| prices.visit(path, token)).unwrap();
+        prices
+    }
+
+    #[test]
+    fn current_prices() {
+        let usd_btc = currencies(&["USD", "BTC"]);
+        let prices = read_prices(SIMPLE_PRICE, &usd_btc).prices;
+
+        assert_eq!(prices.len(), 3);
+        assert_eq!(price(&prices, "BTC", "USD"), Some(43127.0));
+        assert_eq!(price(&prices, "ETH", "USD"), Some(2291.5));
+        assert_eq!(price(&prices, "DOGE", "USD"), Some(0.08237));
+        assert_eq!(price(&prices, "ETH", "BTC"), Some(0.05313));
+        assert_eq!(price(&prices, "DOGE", "BTC"), Some(0.00000191));
+
+        //a currency that wasn't asked for
+        assert!(read_prices(SIMPLE_PRICE, &currencies(&["EUR"]))
+            .prices
+            .is_empty());
+    }
+
+    #[test]
+    fn openday_prices() {
+        let currencies = currencies(&["usd", "btc"]);
+        let prices = read_prices(SIMPLE_PRICE, &currencies).openday_prices();
+
+        let btc = price(&prices, "BTC", "usd").unwrap();
+        assert!((btc - 43718.0).abs() < 1.0, "{}", btc);
+        let eth = price(&prices, "ETH", "usd").unwrap();
+        assert!((eth - 2245.7).abs() < 0.1, "{}", eth);
+        let eth = price(&prices, "ETH", "btc").unwrap();
+        assert!((eth - 0.05137).abs() < 0.00001, "{}", eth);
+        //no change reported, the price stayed the same
+        assert_eq!(price(&prices, "DOGE", "usd"), Some(0.08237));
+    }
+
+    #[test]
+    fn market_stats() {
+        let usd_btc = currencies(&["USD", "BTC"]);
+        let stats = read_prices(SIMPLE_PRICE, &usd_btc).market_stats();
+
+        let btc = stats.get(&String::from("BTC")).unwrap();
+        assert_eq!(btc.market_cap, Some(845134512345.6));
+        assert_eq!(btc.high_24h, None);
+        let volume = btc.volume_24h.unwrap();
+        assert!((volume - 23512.4).abs() < 0.1, "{}", volume);
+        //no trades in the last 24 hours
+        assert_eq!(stats.get(&String::from("DOGE")).unwrap().volume_24h, None);
+    }
+
+    #[test]
+    fn unexpected_values() {
+        let currencies = currencies(&["usd"]);
+        let mut prices = SimplePrices::<4>::new(&currencies);
+        let result = read_fixture(br#"{"bitcoin":{"usd":"43127"}}"#, |path, token| {
+            prices.visit(path, token)
+        });
+
+        assert_eq!(result, Err(ParseErrorKind::UnexpectedValue));
+    }
+
+    #[test]
+    fn symbols_are_sent_as_ids() {
+        let symbols: [String<16>; 3] = ["btc".into(), "GERO".into(), "ETH".into()];
+        let request = construct_request(
+            &symbols,
+            &currencies(&["USD", "BTC"]),
+            &["include_24hr_change"],
+        )
+        .unwrap()
+        .serialize::<512>()
+        .unwrap();
+
+        assert!(request.starts_with(
+            b"GET /api/v3/simple/price?ids=bitcoin%2Cethereum&vs_currencies=usd%2Cbtc\
+              &include_24hr_change=true HTTP/1.1\r\n"
+        ));
+    }
+
+    #[test]
+    fn history() {
+        let mut candles = CandleReader::new(Interval::Hour);
+        read_fixture(OHLC, |path, token| visit_candle(&mut candles, path, token)).unwrap();
+        let history = candles.finish(24);
+
+        //half hour candles are merged into hours
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].time, 1705392000);
+        assert_eq!(history[0].open, 43401.2);
+        assert_eq!(history[0].high, 43483.9);
+        assert_eq!(history[0].low, 43270.4);
+        assert_eq!(history[0].close, 43307.8);
+        assert_eq!(history[1].time, 1705395600);
+        assert_eq!(history[1].close, 43127.0);
+    }
+
+    #[test]
+    fn history_requests() {
+        let request = construct_history_request(
+            "https://api.coingecko.com/api/v3/coins/bitcoin/ohlc",
+            "EUR",
+            Interval::Day,
+            10,
+        )
+        .unwrap()
+        .serialize::<512>()
+        .unwrap();
+
+        assert!(request
+            .starts_with(b"GET /api/v3/coins/bitcoin/ohlc?vs_currency=eur&days=14 HTTP/1.1\r\n"));
+    }
+}
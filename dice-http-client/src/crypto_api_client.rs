@@ -1,26 +1,742 @@
+use crate::client::HttpClient;
+use crate::http_client::{HttpError, TransportError};
+use crate::json::{JsonError, JsonReader, Path, Token};
+use crate::request::Request;
+use crate::resolver::Resolver;
+use core::ops::Range;
 use drogue_network::tcp::TcpStack;
-use heapless::{FnvIndexMap,String, Vec};
+use heapless::{FnvIndexMap, LinearMap, String, Vec};
 
-#[derive(Debug)]
-pub enum CryptoApiError{
-    NoConnection,
-    ParseError,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CryptoApiError {
+    /// The address of the API host isn't known
+    UnknownHost,
+    /// The request couldn't be built, e.g. the symbols don't fit in the URL
     RequestError,
-    WriteError,
-    ReadError,
+    /// Connecting failed, including the TLS handshake
+    ConnectError(TransportError),
+    WriteError(TransportError),
+    ReadError(TransportError),
+    /// The server closed the connection before the whole response was received
+    ConnectionClosed,
+    /// The response isn't valid HTTP, or its body can't be decoded
+    InvalidResponse,
+    /// The server answered with a status other than 200. `retry_after` is the number of
+    /// seconds the server asked to wait before trying again.
+    Status {
+        status: u16,
+        retry_after: Option<u32>,
+    },
+    /// The body isn't what the API returns. `offset` is the position in the body where
+    /// parsing stopped.
+    ParseError {
+        offset: usize,
+        kind: ParseErrorKind,
+    },
+    /// The API has no price for one of the symbols in the currency asked for
+    UnknownSymbol,
 }
 
-pub trait CryptoApiClient<StackT: TcpStack, const MAX_CURRENCIES: usize>
-{
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseErrorKind {
+    Json(JsonError),
+    /// A value has a different type than expected
+    UnexpectedValue,
+    /// The API answered with an error message instead of data
+    ErrorMessage,
+}
+
+impl From<JsonError> for ParseErrorKind {
+    fn from(error: JsonError) -> Self {
+        ParseErrorKind::Json(error)
+    }
+}
+
+impl CryptoApiError {
+    /// True if the request may succeed when sent again later, e.g. after a network failure,
+    /// a server error or hitting the rate limit
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            CryptoApiError::ConnectError(_)
+            | CryptoApiError::WriteError(_)
+            | CryptoApiError::ReadError(_)
+            | CryptoApiError::ConnectionClosed => true,
+            CryptoApiError::Status { status, .. } => {
+                matches!(status, 408 | 429 | 500 | 502 | 503 | 504)
+            }
+            CryptoApiError::UnknownHost
+            | CryptoApiError::RequestError
+            | CryptoApiError::InvalidResponse
+            | CryptoApiError::ParseError { .. }
+            | CryptoApiError::UnknownSymbol => false,
+        }
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(self, CryptoApiError::Status { status: 429, .. })
+    }
+
+    /// Seconds to wait before trying again, if the server said so with `Retry-After`
+    pub fn retry_after(&self) -> Option<u32> {
+        match self {
+            CryptoApiError::Status { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl From<HttpError> for CryptoApiError {
+    fn from(error: HttpError) -> Self {
+        match error {
+            HttpError::Request(_) => CryptoApiError::RequestError,
+            HttpError::UnknownHost => CryptoApiError::UnknownHost,
+            HttpError::ConnectError(kind) => CryptoApiError::ConnectError(kind),
+            HttpError::WriteError(kind) => CryptoApiError::WriteError(kind),
+            HttpError::ReadError(kind) => CryptoApiError::ReadError(kind),
+            HttpError::ConnectionClosed => CryptoApiError::ConnectionClosed,
+            _ => CryptoApiError::InvalidResponse,
+        }
+    }
+}
+
+/// Short description, fitting on the display
+impl core::fmt::Display for CryptoApiError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            CryptoApiError::UnknownHost => write!(f, "UNKNOWN HOST"),
+            CryptoApiError::RequestError => write!(f, "BAD REQUEST"),
+            CryptoApiError::ConnectError(_) => write!(f, "NO CONNECTION"),
+            CryptoApiError::WriteError(_) => write!(f, "SEND FAILED"),
+            CryptoApiError::ReadError(_) | CryptoApiError::ConnectionClosed => {
+                write!(f, "RECEIVE FAILED")
+            }
+            CryptoApiError::InvalidResponse => write!(f, "BAD RESPONSE"),
+            CryptoApiError::Status { status: 429, .. } => write!(f, "RATE LIMITED"),
+            CryptoApiError::Status { status, .. } => write!(f, "HTTP {}", status),
+            CryptoApiError::ParseError {
+                kind: ParseErrorKind::ErrorMessage,
+                ..
+            } => write!(f, "API ERROR"),
+            CryptoApiError::ParseError { offset, .. } => write!(f, "BAD DATA AT {}", offset),
+            CryptoApiError::UnknownSymbol => write!(f, "UNKNOWN SYMBOL"),
+        }
+    }
+}
+
+/// Most quote currencies prices are asked in at once
+pub const MAX_QUOTES: usize = 2;
+
+/// Prices of a symbol by quote currency, spelled as the caller asked for it
+pub type Quotes = LinearMap<String<8>, f32, MAX_QUOTES>;
+
+/// Prices by symbol, in every quote currency the provider has them in
+pub type Prices<const MAX_CURRENCIES: usize> = FnvIndexMap<String<16>, Quotes, MAX_CURRENCIES>;
+
+/// Market data of a symbol over the last 24 hours, in the quote currency asked for.
+/// Values the provider doesn't report are left out.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MarketStats {
+    pub high_24h: Option<f32>,
+    pub low_24h: Option<f32>,
+    /// Amount of the symbol itself traded, not its value
+    pub volume_24h: Option<f32>,
+    pub market_cap: Option<f32>,
+}
+
+/// Market data by symbol
+pub type MarketStatsMap<const MAX_CURRENCIES: usize> =
+    FnvIndexMap<String<16>, MarketStats, MAX_CURRENCIES>;
+
+/// Time a candle of the price history spans
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interval {
+    Hour,
+    Day,
+}
+
+impl Interval {
+    pub fn seconds(&self) -> u32 {
+        match self {
+            Interval::Hour => 60 * 60,
+            Interval::Day => 24 * 60 * 60,
+        }
+    }
+}
+
+/// Prices of a symbol over one interval
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Candle {
+    /// Start of the interval, in seconds since the Unix epoch
+    pub time: u32,
+    pub open: f32,
+    pub high: f32,
+    pub low: f32,
+    pub close: f32,
+}
+
+/// Most candles kept of a history, enough for a day of hours
+pub const MAX_CANDLES: usize = 24;
+
+/// Candles from the oldest to the latest
+pub type History = Vec<Candle, MAX_CANDLES>;
+
+pub trait CryptoApiClient<StackT: TcpStack, const MAX_CURRENCIES: usize> {
+    /// Short name of the provider, to show where prices come from
+    fn name(&self) -> &'static str;
+
+    /// Closes the connections kept open between requests
+    fn disconnect(&mut self, network: &mut StackT);
+
+    /// Sets the API key used with `provider`, keys of other providers are ignored.
+    /// An empty key removes it.
+    fn set_api_key(&mut self, provider: &str, api_key: &str) -> Result<(), CryptoApiError>;
+
+    /// Prices of `symbols` at the start of the day, in each of `currencies`
     fn get_openday_price(
+        &mut self,
         network: &mut StackT,
         symbols: &Vec<String<16>, MAX_CURRENCIES>,
-        currency: &str,
-    ) -> Result<FnvIndexMap<String<16>, f32, MAX_CURRENCIES>, CryptoApiError>;
+        currencies: &[String<8>],
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError>;
 
+    /// Current prices of `symbols`, in each of `currencies`
     fn get_current_prices(
+        &mut self,
         network: &mut StackT,
         symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currencies: &[String<8>],
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError>;
+
+    /// Highs, lows, volumes and market caps of `symbols` over the last 24 hours, in `currency`
+    fn get_market_stats(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currency: &String<8>,
+    ) -> Result<MarketStatsMap<MAX_CURRENCIES>, CryptoApiError>;
+
+    /// The latest `count` candles of `interval` of `symbol` in `currency`, up to `MAX_CANDLES`
+    fn get_history(
+        &mut self,
+        network: &mut StackT,
+        symbol: &String<16>,
+        currency: &String<8>,
+        interval: Interval,
+        count: usize,
+    ) -> Result<History, CryptoApiError>;
+
+    /// Calls `on_symbol` with every symbol the provider has prices of in `currency`,
+    /// the most traded ones first where the provider tells. A symbol may come more than once.
+    fn list_symbols(
+        &mut self,
+        network: &mut StackT,
+        currency: &String<8>,
+        on_symbol: &mut dyn FnMut(&str),
+    ) -> Result<(), CryptoApiError>;
+}
+
+/// Longest API key the providers accept
+pub const MAX_API_KEY_LENGTH: usize = 64;
+
+pub type ApiKey = String<MAX_API_KEY_LENGTH>;
+
+/// Replaces `key` if `provider` is `name`, the provider the key is kept for
+pub(crate) fn update_api_key(
+    key: &mut ApiKey,
+    name: &str,
+    provider: &str,
+    api_key: &str,
+) -> Result<(), CryptoApiError> {
+    if provider == name {
+        *key = api_key.parse().map_err(|_| CryptoApiError::RequestError)?;
+    }
+    Ok(())
+}
+
+/// Adds the API key to the query as `param`, unless there is no key
+pub(crate) fn with_api_key<'a>(request: Request<'a>, param: &str, api_key: &ApiKey) -> Request<'a> {
+    if api_key.is_empty() {
+        request
+    } else {
+        request.query(param, api_key)
+    }
+}
+
+/// Sends the request and parses the JSON response as it arrives, without buffering it.
+/// `on_value` is called with every value in the response.
+pub(crate) fn read_json<
+    StackT: TcpStack,
+    ResolverT: Resolver,
+    const MAX_CONNECTIONS: usize,
+    const MAX_TOKEN_LENGTH: usize,
+    const MAX_DEPTH: usize,
+>(
+    http: &mut HttpClient<StackT, ResolverT, MAX_CONNECTIONS>,
+    network: &mut StackT,
+    request: &Request,
+    mut on_value: impl FnMut(&Path<MAX_TOKEN_LENGTH, MAX_DEPTH>, Token) -> Result<(), ParseErrorKind>,
+) -> Result<(), CryptoApiError> {
+    let mut reader = JsonReader::<MAX_TOKEN_LENGTH, MAX_DEPTH>::new();
+    let mut stopped = None;
+
+    //After a parse error the rest of the body is still read, so that the status is known
+    //and the connection can be used again
+    let response = http.send_streaming(network, request, |data| {
+        if stopped.is_none() {
+            stopped = reader.feed(data, &mut on_value).err();
+        }
+        Ok(())
+    })?;
+
+    if response.status != 200 {
+        return Err(CryptoApiError::Status {
+            status: response.status,
+            retry_after: response.headers.retry_after_seconds(),
+        });
+    }
+
+    match stopped.map_or_else(|| reader.finish(&mut on_value), Err) {
+        Ok(()) => Ok(()),
+        Err(kind) => Err(CryptoApiError::ParseError {
+            offset: reader.offset(),
+            kind,
+        }),
+    }
+}
+
+/// Longest name of a trading pair, e.g. `BTCUSDT`
+pub const MAX_PAIR_LENGTH: usize = 24;
+/// Pairs an exchange doesn't have that are remembered and left out of later requests
+pub const MAX_UNKNOWN_PAIRS: usize = 8;
+
+pub type PairName = String<MAX_PAIR_LENGTH>;
+pub type UnknownPairs = Vec<PairName, MAX_UNKNOWN_PAIRS>;
+
+/// Reads the tickers of a batch of `count` trading pairs, for exchanges that reject the whole
+/// batch with `UnknownSymbol` if they don't have one of the pairs. `read` requests the pairs
+/// with the indices in the range, leaving out those in the unknown pairs. A rejected batch is
+/// split in halves until the pair is found, which is added to `unknown`, so the other pairs
+/// still get prices. `pair_name` is the name of the pair at an index.
+pub(crate) fn read_known_pairs<NameT, ReadT>(
+    count: usize,
+    unknown: &mut UnknownPairs,
+    pair_name: &NameT,
+    read: &mut ReadT,
+) -> Result<(), CryptoApiError>
+where
+    NameT: Fn(usize) -> Option<PairName>,
+    ReadT: FnMut(Range<usize>, &UnknownPairs) -> Result<(), CryptoApiError>,
+{
+    read_pairs(0..count, unknown, pair_name, read)?;
+
+    //There is nothing to show if the exchange has none of the pairs
+    let any_known =
+        (0..count).any(|index| matches!(pair_name(index), Some(name) if !unknown.contains(&name)));
+    if count > 0 && !any_known {
+        return Err(CryptoApiError::UnknownSymbol);
+    }
+    Ok(())
+}
+
+fn read_pairs<NameT, ReadT>(
+    pairs: Range<usize>,
+    unknown: &mut UnknownPairs,
+    pair_name: &NameT,
+    read: &mut ReadT,
+) -> Result<(), CryptoApiError>
+where
+    NameT: Fn(usize) -> Option<PairName>,
+    ReadT: FnMut(Range<usize>, &UnknownPairs) -> Result<(), CryptoApiError>,
+{
+    match read(pairs.clone(), unknown) {
+        Err(CryptoApiError::UnknownSymbol) if pairs.len() > 1 => {
+            let middle = pairs.start + pairs.len() / 2;
+            read_pairs(pairs.start..middle, unknown, pair_name, read)?;
+            read_pairs(middle..pairs.end, unknown, pair_name, read)
+        }
+        Err(CryptoApiError::UnknownSymbol) => {
+            let name = pair_name(pairs.start).ok_or(CryptoApiError::RequestError)?;
+            //Once the list is full, the pair is looked for again in every batch
+            unknown.push(name).ok();
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Adds the price of `symbol` in `currency`. Symbols that weren't asked for don't fit in
+/// the map and are left out.
+pub(crate) fn insert_price<const MAX_CURRENCIES: usize>(
+    prices: &mut Prices<MAX_CURRENCIES>,
+    symbol: Option<&str>,
+    currency: &str,
+    token: Token,
+) -> Result<(), ParseErrorKind> {
+    let price = parse_number(token)?;
+    insert_quote(
+        prices,
+        symbol.ok_or(ParseErrorKind::UnexpectedValue)?,
+        currency,
+        price,
+    )
+}
+
+/// Adds an already parsed price of `symbol` in `currency`
+pub(crate) fn insert_quote<const MAX_CURRENCIES: usize>(
+    prices: &mut Prices<MAX_CURRENCIES>,
+    symbol: &str,
+    currency: &str,
+    price: f32,
+) -> Result<(), ParseErrorKind> {
+    let symbol: String<16> = symbol
+        .parse()
+        .map_err(|_| ParseErrorKind::UnexpectedValue)?;
+    let currency: String<8> = currency
+        .parse()
+        .map_err(|_| ParseErrorKind::UnexpectedValue)?;
+
+    if !prices.contains_key(&symbol) && prices.insert(symbol.clone(), Quotes::new()).is_err() {
+        return Ok(());
+    }
+    if let Some(quotes) = prices.get_mut(&symbol) {
+        quotes.insert(currency, price).ok();
+    }
+
+    Ok(())
+}
+
+/// Changes the market stats of `symbol`, adding them if it has none yet. Symbols that
+/// weren't asked for don't fit in the map and are left out.
+pub(crate) fn update_stats<const MAX_CURRENCIES: usize>(
+    stats: &mut MarketStatsMap<MAX_CURRENCIES>,
+    symbol: &str,
+    update: impl FnOnce(&mut MarketStats),
+) -> Result<(), ParseErrorKind> {
+    let symbol: String<16> = symbol
+        .parse()
+        .map_err(|_| ParseErrorKind::UnexpectedValue)?;
+
+    if !stats.contains_key(&symbol)
+        && stats
+            .insert(symbol.clone(), MarketStats::default())
+            .is_err()
+    {
+        return Ok(());
+    }
+    if let Some(stats) = stats.get_mut(&symbol) {
+        update(stats);
+    }
+
+    Ok(())
+}
+
+/// Collects candles sent value by value, a candle is complete when the values of the next
+/// one begin. Shorter candles than the interval asked for are merged.
+pub(crate) struct CandleReader {
+    interval: Interval,
+    history: History,
+    //position of the candle being read in the response, and its values so far
+    current: Option<(usize, Candle)>,
+}
+
+impl CandleReader {
+    pub(crate) fn new(interval: Interval) -> Self {
+        CandleReader {
+            interval,
+            history: History::new(),
+            current: None,
+        }
+    }
+
+    /// Sets a value of the candle at `index` in the response
+    pub(crate) fn update(&mut self, index: usize, update: impl FnOnce(&mut Candle)) {
+        match &mut self.current {
+            Some((current, candle)) if *current == index => update(candle),
+            _ => {
+                self.complete();
+                let mut candle = Candle::default();
+                update(&mut candle);
+                self.current = Some((index, candle));
+            }
+        }
+    }
+
+    /// The latest `count` candles read
+    pub(crate) fn finish(mut self, count: usize) -> History {
+        self.complete();
+
+        let skipped = self.history.len().saturating_sub(count);
+        self.history.iter().skip(skipped).copied().collect()
+    }
+
+    fn complete(&mut self) {
+        let candle = match self.current.take() {
+            Some((_, candle)) => candle,
+            None => return,
+        };
+        let start = candle.time - candle.time % self.interval.seconds();
+
+        match self.history.last_mut() {
+            Some(last) if last.time == start => {
+                last.high = last.high.max(candle.high);
+                last.low = last.low.min(candle.low);
+                last.close = candle.close;
+            }
+            _ => {
+                //Responses may have more candles than asked for, the oldest are dropped
+                if self.history.is_full() {
+                    self.history.remove(0);
+                }
+                self.history
+                    .push(Candle {
+                        time: start,
+                        ..candle
+                    })
+                    .ok();
+            }
+        }
+    }
+}
+
+/// Currency asked for that the API calls `name`, APIs don't keep the case of currencies
+pub(crate) fn find_currency<'a>(currencies: &'a [String<8>], name: &str) -> Option<&'a str> {
+    currencies
+        .iter()
+        .find(|currency| currency.eq_ignore_ascii_case(name))
+        .map(|currency| currency.as_str())
+}
+
+/// Checks if `text` is made of `parts`, ignoring case
+pub(crate) fn is_concatenation(text: &str, parts: &[&str]) -> bool {
+    let mut rest = text;
+
+    for part in parts {
+        match rest.get(..part.len()) {
+            Some(start) if start.eq_ignore_ascii_case(part) => rest = &rest[part.len()..],
+            _ => return false,
+        }
+    }
+
+    rest.is_empty()
+}
+
+pub(crate) fn parse_number(token: Token) -> Result<f32, ParseErrorKind> {
+    match token {
+        Token::Number(number) => number.parse().map_err(|_| ParseErrorKind::UnexpectedValue),
+        _ => Err(ParseErrorKind::UnexpectedValue),
+    }
+}
+
+/// Reads a time in seconds since the Unix epoch, APIs give it in `unit`s of a second
+pub(crate) fn parse_time(token: Token, unit: u64) -> Result<u32, ParseErrorKind> {
+    match token {
+        Token::Number(number) => number
+            .parse::<u64>()
+            .map(|time| (time / unit) as u32)
+            .map_err(|_| ParseErrorKind::UnexpectedValue),
+        _ => Err(ParseErrorKind::UnexpectedValue),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Parses a recorded response the way `read_json` does, in pieces of a few bytes
+    pub(crate) fn read_fixture<const MAX_TOKEN_LENGTH: usize, const MAX_DEPTH: usize>(
+        body: &[u8],
+        mut on_value: impl FnMut(
+            &Path<MAX_TOKEN_LENGTH, MAX_DEPTH>,
+            Token,
+        ) -> Result<(), ParseErrorKind>,
+    ) -> Result<(), ParseErrorKind> {
+        let mut reader = JsonReader::<MAX_TOKEN_LENGTH, MAX_DEPTH>::new();
+
+        for piece in body.chunks(7) {
+            reader.feed(piece, &mut on_value)?;
+        }
+        reader.finish(&mut on_value)
+    }
+
+    pub(crate) fn currencies(currencies: &[&str]) -> Vec<String<8>, MAX_QUOTES> {
+        currencies
+            .iter()
+            .map(|&currency| String::from(currency))
+            .collect()
+    }
+
+    pub(crate) fn price<const MAX_CURRENCIES: usize>(
+        prices: &Prices<MAX_CURRENCIES>,
+        symbol: &str,
         currency: &str,
-    ) -> Result<FnvIndexMap<String<16>, f32, MAX_CURRENCIES>, CryptoApiError>;
+    ) -> Option<f32> {
+        prices
+            .get(&String::from(symbol))
+            .and_then(|quotes| quotes.get(&String::from(currency)))
+            .copied()
+    }
+
+    fn status(status: u16) -> CryptoApiError {
+        CryptoApiError::Status {
+            status,
+            retry_after: None,
+        }
+    }
+
+    #[test]
+    fn retryable_errors() {
+        let retryable = [
+            CryptoApiError::ConnectError(TransportError::ConnectionRefused),
+            CryptoApiError::ReadError(TransportError::Timeout),
+            CryptoApiError::ConnectionClosed,
+            status(429),
+            status(503),
+        ];
+        let permanent = [
+            CryptoApiError::UnknownHost,
+            status(404),
+            status(501),
+            CryptoApiError::InvalidResponse,
+            CryptoApiError::UnknownSymbol,
+            CryptoApiError::ParseError {
+                offset: 10,
+                kind: ParseErrorKind::ErrorMessage,
+            },
+        ];
+
+        assert!(retryable.iter().all(CryptoApiError::is_retryable));
+        assert!(!permanent.iter().any(CryptoApiError::is_retryable));
+        assert!(status(429).is_rate_limited());
+        assert!(!status(503).is_rate_limited());
+    }
+
+    #[test]
+    fn retry_after() {
+        let error = CryptoApiError::Status {
+            status: 503,
+            retry_after: Some(30),
+        };
+        assert_eq!(error.retry_after(), Some(30));
+        assert_eq!(status(429).retry_after(), None);
+        assert_eq!(CryptoApiError::UnknownHost.retry_after(), None);
+    }
+
+    #[test]
+    fn http_errors_keep_their_details() {
+        assert_eq!(
+            CryptoApiError::from(HttpError::ConnectError(TransportError::Timeout)),
+            CryptoApiError::ConnectError(TransportError::Timeout)
+        );
+        assert_eq!(
+            CryptoApiError::from(HttpError::UnknownHost),
+            CryptoApiError::UnknownHost
+        );
+        assert_eq!(
+            CryptoApiError::from(HttpError::TooManyRedirects),
+            CryptoApiError::InvalidResponse
+        );
+
+        let mut text = String::<32>::new();
+        core::fmt::write(
+            &mut text,
+            format_args!(
+                "{}",
+                CryptoApiError::ParseError {
+                    offset: 812,
+                    kind: ParseErrorKind::UnexpectedValue
+                }
+            ),
+        )
+        .unwrap();
+        assert_eq!(text, "BAD DATA AT 812");
+    }
+
+    fn candle(time: u32, open: f32, high: f32, low: f32, close: f32) -> Candle {
+        Candle {
+            time,
+            open,
+            high,
+            low,
+            close,
+        }
+    }
+
+    #[test]
+    fn candles() {
+        let mut reader = CandleReader::new(Interval::Hour);
+        //half hour candles, the first one read value by value
+        reader.update(0, |value| value.time = 7200);
+        reader.update(0, |value| {
+            *value = candle(value.time, 10.0, 12.0, 9.0, 11.0)
+        });
+        reader.update(1, |value| *value = candle(9000, 11.0, 14.0, 10.0, 13.0));
+        reader.update(2, |value| *value = candle(10800, 13.0, 13.5, 8.0, 8.5));
+
+        assert_eq!(
+            reader.finish(4),
+            [
+                candle(7200, 10.0, 14.0, 9.0, 13.0),
+                candle(10800, 13.0, 13.5, 8.0, 8.5),
+            ]
+        );
+
+        let mut reader = CandleReader::new(Interval::Day);
+        for day in 0..(MAX_CANDLES as u32 + 5) {
+            reader.update(day as usize, |value| value.time = day * 86400);
+        }
+        let history = reader.finish(3);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[2].time, (MAX_CANDLES as u32 + 4) * 86400);
+    }
+
+    #[test]
+    fn api_keys() {
+        let mut key = ApiKey::new();
+        update_api_key(&mut key, "CoinGecko", "CoinGecko", "CG-abc123").unwrap();
+        //keys of other providers are left alone
+        update_api_key(&mut key, "CoinGecko", "CryptoCompare", "other").unwrap();
+        assert_eq!(key, "CG-abc123");
+
+        let too_long = [b'k'; MAX_API_KEY_LENGTH + 1];
+        assert_eq!(
+            update_api_key(
+                &mut key,
+                "CoinGecko",
+                "CoinGecko",
+                core::str::from_utf8(&too_long).unwrap()
+            ),
+            Err(CryptoApiError::RequestError)
+        );
+        assert_eq!(key, "CG-abc123");
+
+        let request = Request::get("https://api.coingecko.com/ping").unwrap();
+        let request = with_api_key(request, "x_cg_demo_api_key", &key)
+            .serialize::<256>()
+            .unwrap();
+        assert!(request.starts_with(b"GET /ping?x_cg_demo_api_key=CG-abc123 HTTP/1.1\r\n"));
+
+        let request = Request::get("https://api.coingecko.com/ping").unwrap();
+        let request = with_api_key(request, "x_cg_demo_api_key", &ApiKey::new())
+            .serialize::<256>()
+            .unwrap();
+        assert!(request.starts_with(b"GET /ping HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn quotes_by_symbol() {
+        let mut prices = Prices::<4>::new();
+        insert_quote(&mut prices, "BTC", "USD", 43127.0).unwrap();
+        insert_quote(&mut prices, "BTC", "EUR", 39800.0).unwrap();
+        insert_price(&mut prices, Some("ETH"), "USD", Token::Number("2291.5")).unwrap();
+
+        assert_eq!(prices.len(), 2);
+        assert_eq!(price(&prices, "BTC", "EUR"), Some(39800.0));
+        assert_eq!(price(&prices, "ETH", "USD"), Some(2291.5));
+        assert_eq!(price(&prices, "ETH", "EUR"), None);
+
+        let currencies = currencies(&["USD", "btc"]);
+        assert_eq!(find_currency(&currencies, "BTC"), Some("btc"));
+        assert_eq!(find_currency(&currencies, "usd"), Some("USD"));
+        assert_eq!(find_currency(&currencies, "EUR"), None);
+    }
 }
@@ -1,282 +1,698 @@
-//Some structs here use NONSNAKECASENAMES because they match the names of objects in parsed json
-//We *could* change those names to match rust style guide and use serde macros to match rust name with json name
-//But honestly, why bother?
-#![allow(non_snake_case)]
-
 use crate::{
-    crypto_api_client::{CryptoApiClient, CryptoApiError},
-    http_client::HttpResponse,
-};
-use drogue_network::{
-    addr::{HostAddr, HostSocketAddr, IpAddr, Ipv4Addr},
-    tcp::{Mode, TcpStack},
+    client::HttpClient,
+    crypto_api_client::{
+        self, find_currency, insert_price, parse_number, parse_time, update_stats, ApiKey,
+        CandleReader, CryptoApiClient, CryptoApiError, History, Interval, MarketStatsMap,
+        ParseErrorKind, Prices, MAX_CANDLES,
+    },
+    json::{Path, Token},
+    request::Request,
+    resolver::StaticResolver,
 };
-use heapless::{FnvIndexMap, LinearMap};
+use core::ops::Range;
+use drogue_network::{addr::Ipv4Addr, tcp::TcpStack};
 use heapless::{String, Vec};
-use serde::{Deserialize, Serialize};
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct CryptoSelectedData {
-    OPENDAY: f32,
-}
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct CryptoFullData {
-    RAW: FnvIndexMap<String<16>, LinearMap<String<16>, CryptoSelectedData, 1>, 8>,
+const NAME: &str = "CryptoCompare";
+
+/// There is no DNS on the device, the API host is resolved from this table
+const HOSTS: [(&str, Ipv4Addr); 1] =
+    [("min-api.cryptocompare.com", Ipv4Addr::new(40, 115, 22, 134))];
+const RESOLVER: StaticResolver = StaticResolver::new(&HOSTS);
+const API_URL: &str = "https://min-api.cryptocompare.com";
+/// Longest base URL and path of an endpoint
+const MAX_URL_LENGTH: usize = 128;
+/// Redirects followed when the API moves an endpoint
+const MAX_REDIRECTS: u8 = 3;
+/// Longest `fsyms` list the API takes, longer lists of symbols are asked for in batches
+const MAX_FSYMS_LENGTH: usize = 300;
+/// Longest key or string kept while parsing responses, longer ones are skipped
+const MAX_TOKEN_LENGTH: usize = 32;
+/// Deepest values read from responses: `RAW.<SYMBOL>.<CURRENCY>.OPENDAY`
+const MAX_DEPTH: usize = 4;
+/// `Type` of the errors the API answers with when the rate limit is exceeded
+const RATE_LIMIT_TYPE: &str = "99";
+/// Pages of the toplist read when listing symbols, with the most valuable 100 on each
+const LIST_PAGES: usize = 3;
+
+type JsonPath = Path<MAX_TOKEN_LENGTH, MAX_DEPTH>;
+
+pub struct CryptoCompareApiClient<StackT: TcpStack> {
+    //All requests go to the same host, a single connection is kept open
+    http: HttpClient<StackT, StaticResolver<'static>, 1>,
+    //scheme, host and port the endpoint paths are appended to
+    base_url: &'static str,
+    //sent with every request if not empty
+    api_key: ApiKey,
 }
 
-pub struct CryptoCompareApiClient;
-
-impl<StackT: TcpStack, const MAX_CURRENCIES: usize> CryptoApiClient<StackT, MAX_CURRENCIES>
-    for CryptoCompareApiClient
-{
-    fn get_openday_price(
-        network: &mut StackT,
-        symbols: &Vec<String<16>, MAX_CURRENCIES>,
-        currency: &str,
-    ) -> Result<FnvIndexMap<String<16>, f32, MAX_CURRENCIES>, CryptoApiError> {
-        //Too much data to get in single request. Divide to chunks 6 cryptos each
-        //Make single request for 6 cryptos
-        let divided_symbols = symbols.chunks(6);
-        let mut changes: FnvIndexMap<String<16>, f32, MAX_CURRENCIES> = FnvIndexMap::new();
-
-        for chunk in divided_symbols {
-            let request = construct_24_request(chunk, currency).unwrap();
-            let mut i = 0;
-
-            let result: Result<HttpResponse<16384>, CryptoApiError> = loop {
-                if i > 3 {
-                    break Err(CryptoApiError::NoConnection);
-                }
-
-                let socket = connect(network);
+impl<StackT: TcpStack> CryptoCompareApiClient<StackT> {
+    pub fn new() -> Self {
+        Self::with_endpoint(API_URL, RESOLVER)
+    }
 
-                if socket.is_err() {
-                    break Err(CryptoApiError::NoConnection);
-                }
+    /// Client of a server answering like the API at `base_url`, e.g. `http://10.0.0.2:8080`
+    /// for `dice-mock-server`. Host names in the URL are resolved with `resolver`.
+    pub fn with_endpoint(base_url: &'static str, resolver: StaticResolver<'static>) -> Self {
+        CryptoCompareApiClient {
+            http: HttpClient::new(resolver).max_redirects(MAX_REDIRECTS),
+            base_url,
+            api_key: ApiKey::new(),
+        }
+    }
 
-                let mut socket = socket.unwrap();
+    /// URL of the endpoint at `path`
+    fn url(&self, path: &str) -> Result<String<MAX_URL_LENGTH>, CryptoApiError> {
+        let mut url = String::new();
+        url.push_str(self.base_url)
+            .and_then(|_| url.push_str(path))
+            .map_err(|_| CryptoApiError::RequestError)?;
+        Ok(url)
+    }
 
-                let result = try_send_24_request(network, &mut socket, request.as_bytes());
+    fn with_api_key<'a>(&self, request: Request<'a>) -> Request<'a> {
+        crypto_api_client::with_api_key(request, "api_key", &self.api_key)
+    }
 
-                if result.is_err() {
-                    network.close(socket).unwrap();
-                    i += 1;
-                    continue;
-                }
+    /// Reads the response to a `pricemultifull` or `pricemulti` request for every batch of
+    /// `symbols`, passing all of their values to `on_value`
+    fn read_batches(
+        &mut self,
+        network: &mut StackT,
+        path: &str,
+        symbols: &[String<16>],
+        currencies: &[String<8>],
+        mut on_value: impl FnMut(&JsonPath, Token) -> Result<(), ParseErrorKind>,
+    ) -> Result<(), CryptoApiError> {
+        let url = self.url(path)?;
+
+        for batch in fsyms_batches(symbols) {
+            let request = self.with_api_key(construct_request(&url, &symbols[batch], currencies)?);
+            self.read_json(network, &request, &mut on_value)?;
+        }
 
-                let response = try_receive_24_request(network, &mut socket);
+        Ok(())
+    }
 
-                if response.is_err() {
-                    network.close(socket).unwrap();
-                    i += 1;
-                    continue;
+    fn read_json(
+        &mut self,
+        network: &mut StackT,
+        request: &Request,
+        mut on_value: impl FnMut(&JsonPath, Token) -> Result<(), ParseErrorKind>,
+    ) -> Result<(), CryptoApiError> {
+        let mut error = false;
+        let mut rate_limited = false;
+
+        //The API reports errors, rate limits included, with status 200 as
+        //{"Response":"Error","Message":"...","HasWarning":false,"Type":99,...}.
+        //The type tells rate limits apart, it is read before stopping.
+        let result =
+            crypto_api_client::read_json(&mut self.http, network, request, |path, token| {
+                if path.matches(&["Response"]) && token == Token::String(Some("Error")) {
+                    error = true;
+                } else if !error {
+                    return on_value(path, token);
+                } else if !path.matches(&["Message"]) && !path.matches(&["HasWarning"]) {
+                    rate_limited =
+                        path.matches(&["Type"]) && token == Token::Number(RATE_LIMIT_TYPE);
+                    return Err(ParseErrorKind::ErrorMessage);
                 }
+                Ok(())
+            });
+
+        match result {
+            Err(_) if rate_limited => Err(CryptoApiError::Status {
+                status: 429,
+                retry_after: None,
+            }),
+            //An error without its type isn't something the API sends
+            Ok(()) if error => Err(CryptoApiError::InvalidResponse),
+            result => result,
+        }
+    }
+}
 
-                break response;
-            };
-
-            if result.is_err() {
-                return Err(CryptoApiError::NoConnection);
-            }
+impl<StackT: TcpStack> Default for CryptoCompareApiClient<StackT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-            let response = result.unwrap();
+impl<StackT: TcpStack, const MAX_CURRENCIES: usize> CryptoApiClient<StackT, MAX_CURRENCIES>
+    for CryptoCompareApiClient<StackT>
+{
+    fn name(&self) -> &'static str {
+        NAME
+    }
 
-            let content = trim_response(response.content.as_str())?;
+    fn disconnect(&mut self, network: &mut StackT) {
+        self.http.close_all(network);
+    }
 
-            let parse_result: Result<(CryptoFullData, usize), _> =
-                serde_json_core::from_str(content);
+    fn set_api_key(&mut self, provider: &str, api_key: &str) -> Result<(), CryptoApiError> {
+        crypto_api_client::update_api_key(&mut self.api_key, NAME, provider, api_key)
+    }
 
-            match parse_result {
-                Ok(res) => {
-                    for (key, val) in res.0.RAW.iter() {
-                        changes
-                            .insert(key.clone(), val.values().next().unwrap().OPENDAY)
-                            .unwrap();
+    fn get_openday_price(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currencies: &[String<8>],
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError> {
+        //The response has dozens of fields for every symbol, but is parsed as it arrives,
+        //so only the length of the fsyms list limits the symbols of a request
+        let mut changes = Prices::new();
+
+        //Failed requests aren't retried here, the caller schedules the next attempt
+        self.read_batches(
+            network,
+            "/data/pricemultifull",
+            symbols,
+            currencies,
+            |path, token| {
+                if path.matches(&["RAW", "*", "*", "OPENDAY"]) {
+                    let currency = path.key(2).and_then(|name| find_currency(currencies, name));
+                    if let Some(currency) = currency {
+                        insert_price(&mut changes, path.key(1), currency, token)?;
                     }
                 }
-                Err(_) => {
-                    return Err(CryptoApiError::ParseError);
-                }
-            }
-        }
+                Ok(())
+            },
+        )?;
 
-        return Ok(changes);
+        Ok(changes)
     }
 
     fn get_current_prices(
+        &mut self,
         network: &mut StackT,
         symbols: &Vec<String<16>, MAX_CURRENCIES>,
-        currency: &str,
-    ) -> Result<FnvIndexMap<String<16>, f32, MAX_CURRENCIES>, CryptoApiError> {
-        let request = construct_price_request(symbols, currency)?;
+        currencies: &[String<8>],
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError> {
+        let mut prices = Prices::new();
+
+        //{"BTC":{"USD":35000.1,"EUR":32000},"ETH":{"USD":2000,"EUR":1800}}
+        self.read_batches(
+            network,
+            "/data/pricemulti",
+            symbols,
+            currencies,
+            |path, token| {
+                if path.matches(&["*", "*"]) {
+                    let currency = path.key(1).and_then(|name| find_currency(currencies, name));
+                    if let Some(currency) = currency {
+                        insert_price(&mut prices, path.key(0), currency, token)?;
+                    }
+                }
+                Ok(())
+            },
+        )?;
+
+        Ok(prices)
+    }
 
-        let mut socket = connect(network)?;
+    fn get_market_stats(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currency: &String<8>,
+    ) -> Result<MarketStatsMap<MAX_CURRENCIES>, CryptoApiError> {
+        let currencies = core::slice::from_ref(currency);
+        let mut stats = MarketStatsMap::new();
+
+        self.read_batches(
+            network,
+            "/data/pricemultifull",
+            symbols,
+            currencies,
+            |path, token| visit_stats(&mut stats, currencies, path, token),
+        )?;
+
+        Ok(stats)
+    }
 
-        let result = network.write(&mut socket, request.as_bytes());
+    fn get_history(
+        &mut self,
+        network: &mut StackT,
+        symbol: &String<16>,
+        currency: &String<8>,
+        interval: Interval,
+        count: usize,
+    ) -> Result<History, CryptoApiError> {
+        let count = count.min(MAX_CANDLES);
+        let url = self.url(match interval {
+            Interval::Hour => "/data/v2/histohour",
+            Interval::Day => "/data/v2/histoday",
+        })?;
+        let request = self.with_api_key(construct_history_request(&url, symbol, currency, count)?);
+        let mut candles = CandleReader::new(interval);
+
+        self.read_json(network, &request, |path, token| {
+            visit_candle(&mut candles, path, token)
+        })?;
+
+        Ok(candles.finish(count))
+    }
 
-        if result.is_err() {
-            network.close(socket).unwrap();
-            return Err(CryptoApiError::WriteError);
+    fn list_symbols(
+        &mut self,
+        network: &mut StackT,
+        currency: &String<8>,
+        on_symbol: &mut dyn FnMut(&str),
+    ) -> Result<(), CryptoApiError> {
+        //There is no list of everything priced, the toplist by market cap is the useful part
+        for page in 0..LIST_PAGES {
+            let url = self.url("/data/top/mktcapfull")?;
+            let request = self.with_api_key(construct_toplist_request(&url, currency, page)?);
+
+            self.read_json(network, &request, |path, token| {
+                visit_listed(on_symbol, path, token);
+                Ok(())
+            })?;
         }
 
-        let response = crate::http_client::read_response::<_, 8192>(network, &mut socket);
+        Ok(())
+    }
+}
 
-        network.close(socket).unwrap();
+/// Reads a symbol from a `top/mktcapfull` response:
+/// `{"Data":[{"CoinInfo":{"Name":"BTC","FullName":"Bitcoin"},"RAW":{...}}]}`
+fn visit_listed(on_symbol: &mut dyn FnMut(&str), path: &JsonPath, token: Token) {
+    if let Token::String(Some(symbol)) = token {
+        if path.matches(&["Data", "*", "CoinInfo", "Name"]) {
+            on_symbol(symbol);
+        }
+    }
+}
 
-        match response {
-            Some(res) => {
-                let content = trim_response(res.content.as_str())?;
+/// Reads a candle from a `histohour` or `histoday` response:
+/// `{"Data":{"Data":[{"time":1705312800,"high":43840,"low":43600,"open":43718,"close":43700}]}}`
+fn visit_candle(
+    candles: &mut CandleReader,
+    path: &JsonPath,
+    token: Token,
+) -> Result<(), ParseErrorKind> {
+    if !path.matches(&["Data", "Data", "*", "*"]) {
+        return Ok(());
+    }
+    let index = path.index(2).ok_or(ParseErrorKind::UnexpectedValue)?;
+
+    match path.key(3) {
+        Some("time") => {
+            let time = parse_time(token, 1)?;
+            candles.update(index, |candle| candle.time = time);
+        }
+        Some(field) if ["open", "high", "low", "close"].contains(&field) => {
+            let price = parse_number(token)?;
+            candles.update(index, |candle| match field {
+                "open" => candle.open = price,
+                "high" => candle.high = price,
+                "low" => candle.low = price,
+                _ => candle.close = price,
+            });
+        }
+        _ => (),
+    }
 
-                let result: Result<
-                    (
-                        FnvIndexMap<String<16>, LinearMap<String<16>, f32, 1>, MAX_CURRENCIES>,
-                        usize,
-                    ),
-                    _,
-                > = serde_json_core::from_str(content);
+    Ok(())
+}
 
-                match result {
-                    Ok(res) => {
-                        let mut result: FnvIndexMap<String<16>, f32, MAX_CURRENCIES> =
-                            FnvIndexMap::new();
-                        for (key, val) in res.0.iter() {
-                            let price = val.values().next().unwrap();
-                            result.insert(key.clone(), price.clone()).unwrap();
-                        }
+/// Reads the market stats of every symbol in the currency asked for from a `pricemultifull`
+/// response: `{"RAW":{"BTC":{"USD":{"HIGH24HOUR":43840,"LOW24HOUR":42821,...}}}}`
+fn visit_stats<const MAX_CURRENCIES: usize>(
+    stats: &mut MarketStatsMap<MAX_CURRENCIES>,
+    currency: &[String<8>],
+    path: &JsonPath,
+    token: Token,
+) -> Result<(), ParseErrorKind> {
+    if !path.matches(&["RAW", "*", "*", "*"])
+        || path
+            .key(2)
+            .and_then(|name| find_currency(currency, name))
+            .is_none()
+    {
+        return Ok(());
+    }
+    let symbol = path.key(1).ok_or(ParseErrorKind::UnexpectedValue)?;
 
-                        Ok(result)
-                    }
-                    Err(_) => Err(CryptoApiError::ParseError),
-                }
-            }
-            None => Err(CryptoApiError::ReadError),
+    //VOLUME24HOUR is the amount of the symbol traded, VOLUME24HOURTO its value
+    match path.key(3) {
+        Some("HIGH24HOUR") => {
+            let high = parse_number(token)?;
+            update_stats(stats, symbol, |stats| stats.high_24h = Some(high))
+        }
+        Some("LOW24HOUR") => {
+            let low = parse_number(token)?;
+            update_stats(stats, symbol, |stats| stats.low_24h = Some(low))
+        }
+        Some("VOLUME24HOUR") => {
+            let volume = parse_number(token)?;
+            update_stats(stats, symbol, |stats| stats.volume_24h = Some(volume))
         }
+        Some("MKTCAP") => {
+            let market_cap = parse_number(token)?;
+            update_stats(stats, symbol, |stats| stats.market_cap = Some(market_cap))
+        }
+        _ => Ok(()),
     }
 }
 
-fn connect<StackT: TcpStack>(network: &mut StackT) -> Result<StackT::TcpSocket, CryptoApiError> {
-    let ip = IpAddr::V4(Ipv4Addr::new(40, 115, 22, 134));
-    let remote = HostSocketAddr::new(HostAddr::new(ip, None), 443);
+/// Builds a GET request for the candles of a symbol. `limit` is the number of candles
+/// before the current one, so a spare one comes back.
+fn construct_history_request<'a>(
+    url: &'a str,
+    symbol: &str,
+    currency: &str,
+    count: usize,
+) -> Result<Request<'a>, CryptoApiError> {
+    let mut limit = String::<8>::new();
+    core::fmt::write(&mut limit, format_args!("{}", count.max(1)))
+        .map_err(|_| CryptoApiError::RequestError)?;
 
-    let socket = network.open(Mode::NonBlocking);
+    let request = Request::get(url)
+        .map_err(|_| CryptoApiError::RequestError)?
+        .query("fsym", symbol)
+        .query("tsym", currency)
+        .query("limit", &limit);
 
-    if socket.is_err() {
-        return Err(CryptoApiError::NoConnection);
-    }
+    Ok(request)
+}
 
-    let connection_result = network.connect(socket.unwrap(), remote);
+/// Builds a GET request for a page of the symbols with the largest market cap in `currency`
+fn construct_toplist_request<'a>(
+    url: &'a str,
+    currency: &str,
+    page: usize,
+) -> Result<Request<'a>, CryptoApiError> {
+    let mut page_number = String::<4>::new();
+    core::fmt::write(&mut page_number, format_args!("{}", page))
+        .map_err(|_| CryptoApiError::RequestError)?;
 
-    if connection_result.is_err() {
-        return Err(CryptoApiError::NoConnection);
-    }
+    let request = Request::get(url)
+        .map_err(|_| CryptoApiError::RequestError)?
+        .query("limit", "100")
+        .query("page", &page_number)
+        .query("tsym", currency);
 
-    return Ok(connection_result.unwrap());
+    Ok(request)
 }
 
-fn construct_24_request(
+/// Builds a GET request with fsyms - the selected cryptos, and tsyms - the currencies to convert to
+fn construct_request<'a>(
+    url: &'a str,
     symbols: &[String<16>],
-    currency: &str,
-) -> Result<String<1024>, CryptoApiError> {
-    //Start building the get request
-    let mut request: String<1024> = String::from("GET /data/pricemultifull?fsyms=");
-
-    //Append symbols of selected cryptos
-    for symbol in symbols {
-        request
-            .push_str(symbol.as_str())
-            .map_err(|_| CryptoApiError::RequestError)?;
-        request
-            .push(',')
-            .map_err(|_| CryptoApiError::RequestError)?;
-    }
+    currencies: &[String<8>],
+) -> Result<Request<'a>, CryptoApiError> {
+    let joined_symbols = join::<MAX_FSYMS_LENGTH, 16>(symbols)?;
+    let joined_currencies = join::<64, 8>(currencies)?;
 
-    //Add tsyms param - the currency to convert to
-    request
-        .push_str("&tsyms=")
-        .map_err(|_| CryptoApiError::RequestError)?;
-    request
-        .push_str(currency)
-        .map_err(|_| CryptoApiError::RequestError)?;
-
-    //Finish by adding http version and Host header
-    request
-        .push_str(" HTTP/1.1\r\nHost: min-api.cryptocompare.com\r\n\r\n")
-        .map_err(|_| CryptoApiError::RequestError)?;
+    let request = Request::get(url)
+        .map_err(|_| CryptoApiError::RequestError)?
+        .query("fsyms", &joined_symbols)
+        .query("tsyms", &joined_currencies);
 
     Ok(request)
 }
 
-fn try_send_24_request<StackT: TcpStack>(
-    network: &mut StackT,
-    socket: &mut StackT::TcpSocket,
-    data: &[u8],
-) -> Result<usize, CryptoApiError> {
-    let result = network.write(socket, data);
+/// Splits `symbols` into consecutive batches whose `fsyms` lists fit in `MAX_FSYMS_LENGTH`
+fn fsyms_batches(symbols: &[String<16>]) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut start = 0;
 
-    if result.is_err() {
-        return Err(CryptoApiError::WriteError);
-    } else {
-        return Ok(result.unwrap());
-    }
+    core::iter::from_fn(move || {
+        if start == symbols.len() {
+            return None;
+        }
+
+        let mut length = symbols[start].len();
+        let mut end = start + 1;
+        while end < symbols.len() && length + 1 + symbols[end].len() <= MAX_FSYMS_LENGTH {
+            length += 1 + symbols[end].len();
+            end += 1;
+        }
+
+        let batch = start..end;
+        start = end;
+        Some(batch)
+    })
 }
 
-fn try_receive_24_request<StackT: TcpStack>(
-    network: &mut StackT,
-    socket: &mut StackT::TcpSocket,
-) -> Result<HttpResponse<16384>, CryptoApiError> {
-    let response = crate::http_client::read_response::<_, 16384>(network, socket);
-    if response.is_none() {
-        return Err(CryptoApiError::ReadError);
-    } else {
-        return Ok(response.unwrap());
+fn join<const SIZE: usize, const ITEM_SIZE: usize>(
+    items: &[String<ITEM_SIZE>],
+) -> Result<String<SIZE>, CryptoApiError> {
+    let mut joined = String::new();
+
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            joined.push(',').map_err(|_| CryptoApiError::RequestError)?;
+        }
+        joined
+            .push_str(item)
+            .map_err(|_| CryptoApiError::RequestError)?;
     }
+
+    Ok(joined)
 }
 
-fn construct_price_request<const MAX_CURRENCIES: usize>(
-    symbols: &Vec<String<16>, MAX_CURRENCIES>,
-    currency: &str,
-) -> Result<String<1024>, CryptoApiError> {
-    //Start building the get request
-    let mut request: String<1024> = String::from("GET /data/pricemulti?fsyms=");
-
-    //Append symbols of selected cryptos
-    for symbol in symbols {
-        request
-            .push_str(symbol.as_str())
-            .map_err(|_| CryptoApiError::RequestError)?;
-        request
-            .push(',')
-            .map_err(|_| CryptoApiError::RequestError)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto_api_client::tests::{currencies, price, read_fixture};
+    use crate::crypto_api_client::{Candle, MarketStats};
+    use crate::test_support::{ok_response, MockStack, Step};
+    use drogue_network::addr::IpAddr;
+
+    const PRICEMULTIFULL: &[u8] = include_bytes!("../fixtures/cryptocompare/pricemultifull.json");
+    const HISTOHOUR: &[u8] = include_bytes!("../fixtures/cryptocompare/histohour.json");
+    const MKTCAPFULL: &[u8] = include_bytes!("../fixtures/cryptocompare/mktcapfull.json");
+
+    #[test]
+    fn market_stats() {
+        let mut stats = MarketStatsMap::<4>::new();
+        read_fixture(PRICEMULTIFULL, |path, token| {
+            visit_stats(&mut stats, &currencies(&["usd"]), path, token)
+        })
+        .unwrap();
+
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            stats.get(&String::from("BTC")),
+            Some(&MarketStats {
+                high_24h: Some(43840.0),
+                low_24h: Some(42821.1),
+                volume_24h: Some(23512.648),
+                market_cap: Some(845_134_500_000.0),
+            })
+        );
+        assert_eq!(
+            stats
+                .get(&String::from("ETH"))
+                .and_then(|stats| stats.market_cap),
+            Some(275_337_100_000.0)
+        );
     }
 
-    //Add tsyms param - the currency to convert to
-    request
-        .push_str("&tsyms=")
-        .map_err(|_| CryptoApiError::RequestError)?;
-    request
-        .push_str(currency)
-        .map_err(|_| CryptoApiError::RequestError)?;
+    #[test]
+    fn history() {
+        let mut candles = CandleReader::new(Interval::Hour);
+        read_fixture(HISTOHOUR, |path, token| {
+            visit_candle(&mut candles, path, token)
+        })
+        .unwrap();
+        let history = candles.finish(3);
+
+        assert_eq!(history.len(), 3);
+        assert_eq!(
+            history[0],
+            Candle {
+                time: 1705392000,
+                open: 43401.2,
+                high: 43483.9,
+                low: 43270.4,
+                close: 43307.8,
+            }
+        );
+        assert_eq!(history[2].time, 1705399200);
+        assert_eq!(history[2].close, 43127.0);
+    }
 
-    //Finish by adding http version and Host header
-    request
-        .push_str(" HTTP/1.1\r\nHost: min-api.cryptocompare.com\r\n\r\n")
-        .map_err(|_| CryptoApiError::RequestError)?;
+    #[test]
+    fn history_requests() {
+        let url = "https://min-api.cryptocompare.com/data/v2/histoday";
+        let request = construct_history_request(url, "BTC", "USD", 7)
+            .unwrap()
+            .serialize::<512>()
+            .unwrap();
+
+        assert!(
+            request.starts_with(b"GET /data/v2/histoday?fsym=BTC&tsym=USD&limit=7 HTTP/1.1\r\n")
+        );
+    }
 
-    Ok(request)
-}
+    #[test]
+    fn listed_symbols() {
+        let mut symbols = Vec::<String<16>, 4>::new();
+        read_fixture(MKTCAPFULL, |path, token| {
+            visit_listed(
+                &mut |symbol| symbols.push(String::from(symbol)).unwrap(),
+                path,
+                token,
+            );
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(symbols, ["BTC", "ETH", "USDT"]);
+
+        let url = "https://min-api.cryptocompare.com/data/top/mktcapfull";
+        let request = construct_toplist_request(url, "EUR", 2)
+            .unwrap()
+            .serialize::<512>()
+            .unwrap();
+        assert!(
+            request.starts_with(b"GET /data/top/mktcapfull?limit=100&page=2&tsym=EUR HTTP/1.1\r\n")
+        );
+    }
+
+    fn symbols(symbols: &[&str]) -> Vec<String<16>, 4> {
+        symbols.iter().map(|&symbol| String::from(symbol)).collect()
+    }
+
+    fn get_btc_price<'a>(
+        client: &mut CryptoCompareApiClient<MockStack<'a>>,
+        stack: &mut MockStack<'a>,
+    ) -> Result<Prices<4>, CryptoApiError> {
+        client.get_current_prices(stack, &symbols(&["BTC"]), &currencies(&["USD"]))
+    }
+
+    /// `count` symbols of 15 characters, `SYMBOL000000000`, `SYMBOL000000001` and so on
+    fn long_symbols<const COUNT: usize>(count: usize) -> Vec<String<16>, COUNT> {
+        (0..count)
+            .map(|index| {
+                let mut symbol = String::new();
+                core::fmt::write(&mut symbol, format_args!("SYMBOL{:09}", index)).unwrap();
+                symbol
+            })
+            .collect()
+    }
 
-//For some reason cryptocompare returns body with some numbers inserted before and after braces.
-//The easy solution is to just trim the string to opening end closing braces of the JSON
-fn trim_response(content: &str) -> Result<&str, CryptoApiError> {
-    let trim_start = content.find('{');
-    let trim_end = content.rfind('}');
+    #[test]
+    fn fsyms_fit_in_the_limit() {
+        //18 symbols with commas take 287 characters, the 19th would take them over 300
+        let symbols = long_symbols::<40>(40);
+        let batches: Vec<Range<usize>, 4> = fsyms_batches(&symbols).collect();
+        assert_eq!(batches, [0..18, 18..36, 36..40]);
 
-    if !(trim_start.is_some() && trim_end.is_some()) {
-        return Err(CryptoApiError::ParseError);
+        assert_eq!(fsyms_batches(&[]).count(), 0);
     }
 
-    let content = &content[trim_start.unwrap()..trim_end.unwrap() + 1];
+    #[test]
+    fn prices_of_batches_are_merged() {
+        let first = ok_response::<128>(br#"{"SYMBOL000000000":{"USD":1}}"#);
+        let second = ok_response::<128>(br#"{"SYMBOL000000019":{"USD":2}}"#);
+        let scripts: [&[Step]; 1] = [&[Step::Respond(&first), Step::Respond(&second)]];
+        let mut stack = MockStack::new(&scripts);
+        let mut client = CryptoCompareApiClient::new();
+
+        let symbols = long_symbols::<32>(20);
+        let prices = client
+            .get_current_prices(&mut stack, &symbols, &currencies(&["USD"]))
+            .unwrap();
+
+        assert_eq!(prices.len(), 2);
+        assert_eq!(price(&prices, "SYMBOL000000000", "USD"), Some(1.0));
+        assert_eq!(price(&prices, "SYMBOL000000019", "USD"), Some(2.0));
+        assert_eq!(stack.written_count("GET /data/pricemulti?"), 2);
+        assert_eq!(
+            stack.written_count(
+                "GET /data/pricemulti?fsyms=SYMBOL000000018%2CSYMBOL000000019&tsyms=USD HTTP/1.1\r\n"
+            ),
+            1
+        );
+    }
 
-    Ok(content)
+    #[test]
+    fn openday_prices_over_the_network() {
+        let response = ok_response::<2200>(PRICEMULTIFULL);
+        let scripts: [&[Step]; 1] = [&[Step::Respond(&response)]];
+        let mut stack = MockStack::new(&scripts).read_size(100);
+        let mut client = CryptoCompareApiClient::new();
+
+        let prices = client
+            .get_openday_price(&mut stack, &symbols(&["BTC", "ETH"]), &currencies(&["USD"]))
+            .unwrap();
+
+        assert_eq!(price(&prices, "BTC", "USD"), Some(43718.0));
+        assert_eq!(price(&prices, "ETH", "USD"), Some(2245.68));
+        assert!(stack
+            .written()
+            .starts_with("GET /data/pricemultifull?fsyms=BTC%2CETH&tsyms=USD HTTP/1.1\r\n"));
+        assert_eq!(
+            &stack.remotes()[..],
+            &[(IpAddr::V4(Ipv4Addr::new(40, 115, 22, 134)), 443)]
+        );
+        assert_eq!(&stack.hostnames()[..], ["min-api.cryptocompare.com"]);
+    }
+
+    #[test]
+    fn errors_over_the_network() {
+        let message = ok_response::<160>(
+            br#"{"Response":"Error","Message":"fsyms param is empty","HasWarning":false,"Type":2}"#,
+        );
+        let limited = ok_response::<256>(
+            br#"{"Response":"Error","Message":"You are over your rate limit please upgrade your account!","HasWarning":false,"Type":99,"RateLimit":{"calls_made":{"second":31}}}"#,
+        );
+        let scripts: [&[Step]; 4] = [
+            &[Step::Respond(&message)],
+            &[Step::Respond(&limited)],
+            &[Step::Respond(
+                b"HTTP/1.1 429 Too Many Requests\r\nRetry-After: 30\r\nContent-Length: 2\r\n\r\n{}",
+            )],
+            &[Step::Fail],
+        ];
+        let mut stack = MockStack::new(&scripts);
+        let mut client = CryptoCompareApiClient::new();
+        assert!(matches!(
+            get_btc_price(&mut client, &mut stack),
+            Err(CryptoApiError::ParseError {
+                kind: ParseErrorKind::ErrorMessage,
+                ..
+            })
+        ));
+        let limited = get_btc_price(&mut client, &mut stack);
+        assert_eq!(
+            limited,
+            Err(CryptoApiError::Status {
+                status: 429,
+                retry_after: None,
+            })
+        );
+        assert!(limited.unwrap_err().is_retryable());
+        assert_eq!(
+            get_btc_price(&mut client, &mut stack),
+            Err(CryptoApiError::Status {
+                status: 429,
+                retry_after: Some(30),
+            })
+        );
+        let broken = get_btc_price(&mut client, &mut stack).unwrap_err();
+        assert!(broken.is_retryable());
+        assert_eq!(stack.opened(), 4);
+    }
+
+    #[test]
+    fn request_is_sent_again_when_the_connection_was_closed() {
+        let response = ok_response::<64>(br#"{"BTC":{"USD":43127}}"#);
+        //The server closes the kept connection before the second request
+        let scripts: [&[Step]; 2] = [&[Step::Respond(&response)], &[Step::Respond(&response)]];
+        let mut stack = MockStack::new(&scripts).read_size(5);
+        let mut client = CryptoCompareApiClient::new();
+
+        for _ in 0..2 {
+            let prices = get_btc_price(&mut client, &mut stack).unwrap();
+            assert_eq!(price(&prices, "BTC", "USD"), Some(43127.0));
+        }
+
+        assert_eq!(stack.opened(), 2);
+        assert_eq!(stack.written_count("GET /data/pricemulti?"), 3);
+    }
 }
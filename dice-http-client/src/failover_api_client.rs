@@ -0,0 +1,475 @@
+use crate::crypto_api_client::{
+    CryptoApiClient, CryptoApiError, History, Interval, MarketStatsMap, Prices, Quotes,
+};
+use drogue_network::tcp::TcpStack;
+use heapless::FnvIndexMap;
+use heapless::{String, Vec};
+
+/// Provider asked for prices, as the client it is, so that clients of any type can be chained
+pub type Provider<'a, StackT, const MAX_CURRENCIES: usize> =
+    &'a mut dyn CryptoApiClient<StackT, MAX_CURRENCIES>;
+
+/// Asks the providers in turn until every symbol has a price in every currency. Symbols the
+/// first provider fails to price, because it is down or doesn't know them, are asked from
+/// the next one.
+/// Only one provider keeps a connection open at a time.
+pub struct FailoverApiClient<
+    'a,
+    StackT: TcpStack,
+    const MAX_CURRENCIES: usize,
+    const MAX_PROVIDERS: usize,
+> {
+    providers: [Provider<'a, StackT, MAX_CURRENCIES>; MAX_PROVIDERS],
+    //names of the providers the current prices came from, by symbol
+    sources: FnvIndexMap<String<16>, &'static str, MAX_CURRENCIES>,
+}
+
+impl<'a, StackT: TcpStack, const MAX_CURRENCIES: usize, const MAX_PROVIDERS: usize>
+    FailoverApiClient<'a, StackT, MAX_CURRENCIES, MAX_PROVIDERS>
+{
+    /// `providers` are asked in the order given
+    pub fn new(providers: [Provider<'a, StackT, MAX_CURRENCIES>; MAX_PROVIDERS]) -> Self {
+        FailoverApiClient {
+            providers,
+            sources: FnvIndexMap::new(),
+        }
+    }
+
+    /// Name of the provider the last current price of `symbol` came from
+    pub fn source_of(&self, symbol: &String<16>) -> Option<&'static str> {
+        self.sources.get(symbol).copied()
+    }
+
+    /// Collects prices from the providers, calling `fetch` for every provider until all
+    /// symbols have a price in each of `currencies`. The error of the last provider asked
+    /// is returned if no symbol got a price.
+    fn fetch(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currencies: &[String<8>],
+        mut fetch: impl FnMut(
+            &mut dyn CryptoApiClient<StackT, MAX_CURRENCIES>,
+            &mut StackT,
+            &Vec<String<16>, MAX_CURRENCIES>,
+        ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError>,
+        mut on_price: impl FnMut(&String<16>, &'static str),
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError> {
+        let mut prices = Prices::new();
+        let mut missing = symbols.clone();
+        let mut last_error = None;
+
+        for index in 0..MAX_PROVIDERS {
+            if missing.is_empty() {
+                break;
+            }
+
+            self.disconnect_others(network, index);
+
+            let provider = &mut *self.providers[index];
+            match fetch(provider, network, &missing) {
+                Ok(found) => {
+                    for (symbol, found_quotes) in found.iter() {
+                        if !missing.contains(symbol) {
+                            continue;
+                        }
+                        if !prices.contains_key(symbol) {
+                            prices.insert(symbol.clone(), Quotes::new()).ok();
+                            on_price(symbol, provider.name());
+                        }
+
+                        //Prices from the providers asked before are kept
+                        if let Some(quotes) = prices.get_mut(symbol) {
+                            for (currency, price) in found_quotes.iter() {
+                                if !quotes.contains_key(currency) {
+                                    quotes.insert(currency.clone(), *price).ok();
+                                }
+                            }
+                        }
+                    }
+
+                    missing = missing
+                        .iter()
+                        .filter(|symbol| match prices.get(symbol) {
+                            Some(quotes) => !currencies
+                                .iter()
+                                .all(|currency| quotes.contains_key(currency)),
+                            None => true,
+                        })
+                        .cloned()
+                        .collect();
+                }
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        match last_error {
+            Some(error) if prices.is_empty() => Err(error),
+            _ => Ok(prices),
+        }
+    }
+
+    /// Disconnects all providers but the one at `index`, the network may allow only
+    /// a single connection
+    fn disconnect_others(&mut self, network: &mut StackT, index: usize) {
+        for (other, provider) in self.providers.iter_mut().enumerate() {
+            if other != index {
+                provider.disconnect(network);
+            }
+        }
+    }
+}
+
+impl<'a, StackT: TcpStack, const MAX_CURRENCIES: usize, const MAX_PROVIDERS: usize>
+    CryptoApiClient<StackT, MAX_CURRENCIES>
+    for FailoverApiClient<'a, StackT, MAX_CURRENCIES, MAX_PROVIDERS>
+{
+    fn name(&self) -> &'static str {
+        self.providers
+            .first()
+            .map_or("", |provider| provider.name())
+    }
+
+    fn disconnect(&mut self, network: &mut StackT) {
+        for provider in self.providers.iter_mut() {
+            provider.disconnect(network);
+        }
+    }
+
+    fn set_api_key(&mut self, provider: &str, api_key: &str) -> Result<(), CryptoApiError> {
+        for client in self.providers.iter_mut() {
+            client.set_api_key(provider, api_key)?;
+        }
+        Ok(())
+    }
+
+    fn get_openday_price(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currencies: &[String<8>],
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError> {
+        self.fetch(
+            network,
+            symbols,
+            currencies,
+            |provider, network, symbols| provider.get_openday_price(network, symbols, currencies),
+            |_, _| (),
+        )
+    }
+
+    fn get_current_prices(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currencies: &[String<8>],
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError> {
+        let mut sources = FnvIndexMap::new();
+
+        let prices = self.fetch(
+            network,
+            symbols,
+            currencies,
+            |provider, network, symbols| provider.get_current_prices(network, symbols, currencies),
+            |symbol, source| {
+                sources.insert(symbol.clone(), source).ok();
+            },
+        );
+
+        if prices.is_ok() {
+            self.sources = sources;
+        }
+        prices
+    }
+
+    /// Stats of each symbol come from the first provider that has them, as they are
+    fn get_market_stats(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currency: &String<8>,
+    ) -> Result<MarketStatsMap<MAX_CURRENCIES>, CryptoApiError> {
+        let mut stats = MarketStatsMap::new();
+        let mut missing = symbols.clone();
+        let mut last_error = None;
+
+        for index in 0..MAX_PROVIDERS {
+            if missing.is_empty() {
+                break;
+            }
+
+            self.disconnect_others(network, index);
+
+            match self.providers[index].get_market_stats(network, &missing, currency) {
+                Ok(found) => {
+                    for (symbol, found_stats) in found.iter() {
+                        if missing.contains(symbol) {
+                            stats.insert(symbol.clone(), *found_stats).ok();
+                        }
+                    }
+                    missing = missing
+                        .iter()
+                        .filter(|symbol| !stats.contains_key(symbol))
+                        .cloned()
+                        .collect();
+                }
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        match last_error {
+            Some(error) if stats.is_empty() => Err(error),
+            _ => Ok(stats),
+        }
+    }
+
+    /// The history comes from the first provider that answers
+    fn get_history(
+        &mut self,
+        network: &mut StackT,
+        symbol: &String<16>,
+        currency: &String<8>,
+        interval: Interval,
+        count: usize,
+    ) -> Result<History, CryptoApiError> {
+        let mut last_error = CryptoApiError::UnknownSymbol;
+
+        for index in 0..MAX_PROVIDERS {
+            self.disconnect_others(network, index);
+
+            match self.providers[index].get_history(network, symbol, currency, interval, count) {
+                Ok(history) => return Ok(history),
+                Err(error) => last_error = error,
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// Symbols of the first provider answering. One failing halfway may have listed some.
+    fn list_symbols(
+        &mut self,
+        network: &mut StackT,
+        currency: &String<8>,
+        on_symbol: &mut dyn FnMut(&str),
+    ) -> Result<(), CryptoApiError> {
+        let mut last_error = CryptoApiError::RequestError;
+
+        for index in 0..MAX_PROVIDERS {
+            self.disconnect_others(network, index);
+
+            match self.providers[index].list_symbols(network, currency, on_symbol) {
+                Ok(()) => return Ok(()),
+                Err(error) => last_error = error,
+            }
+        }
+
+        Err(last_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto_api_client::tests::{currencies, price};
+    use crate::test_support::{MockApiClient, MockStack};
+
+    fn symbols(symbols: &[&str]) -> Vec<String<16>, 4> {
+        symbols.iter().map(|&symbol| String::from(symbol)).collect()
+    }
+
+    #[test]
+    fn missing_symbols_come_from_the_next_provider() {
+        let mut primary =
+            MockApiClient::new("A", &[("BTC", "USD", 43127.0), ("ETH", "USD", 2291.5)]);
+        let mut fallback =
+            MockApiClient::new("B", &[("BTC", "USD", 43000.0), ("GERO", "USD", 0.5)]);
+        let mut client =
+            FailoverApiClient::new([&mut primary as Provider<MockStack, 4>, &mut fallback]);
+
+        let prices = client
+            .get_current_prices(
+                &mut MockStack::new(&[]),
+                &symbols(&["BTC", "ETH", "GERO"]),
+                &currencies(&["USD"]),
+            )
+            .unwrap();
+
+        assert_eq!(prices.len(), 3);
+        assert_eq!(price(&prices, "BTC", "USD"), Some(43127.0));
+        assert_eq!(price(&prices, "GERO", "USD"), Some(0.5));
+        assert_eq!(client.source_of(&String::from("ETH")), Some("A"));
+        assert_eq!(client.source_of(&String::from("GERO")), Some("B"));
+        assert_eq!(client.name(), "A");
+
+        drop(client);
+        assert_eq!(fallback.requested, [symbols(&["GERO"])]);
+        //the primary was disconnected before the fallback was asked
+        assert!(!primary.connected);
+    }
+
+    #[test]
+    fn failed_provider_is_skipped() {
+        let mut primary = MockApiClient::failing("A", CryptoApiError::UnknownHost);
+        let mut fallback = MockApiClient::new("B", &[("BTC", "USD", 43000.0)]);
+        let mut unused = MockApiClient::new("C", &[("BTC", "USD", 42000.0)]);
+        let mut client = FailoverApiClient::new([
+            &mut primary as Provider<MockStack, 4>,
+            &mut fallback,
+            &mut unused,
+        ]);
+
+        let prices = client
+            .get_openday_price(
+                &mut MockStack::new(&[]),
+                &symbols(&["BTC"]),
+                &currencies(&["USD"]),
+            )
+            .unwrap();
+        assert_eq!(price(&prices, "BTC", "USD"), Some(43000.0));
+
+        drop(client);
+        assert!(unused.requested.is_empty());
+    }
+
+    #[test]
+    fn error_when_no_provider_has_prices() {
+        let mut primary = MockApiClient::failing("A", CryptoApiError::UnknownHost);
+        let mut fallback = MockApiClient::failing("B", CryptoApiError::UnknownSymbol);
+        let mut client =
+            FailoverApiClient::new([&mut primary as Provider<MockStack, 4>, &mut fallback]);
+
+        assert_eq!(
+            client
+                .get_current_prices(
+                    &mut MockStack::new(&[]),
+                    &symbols(&["BTC"]),
+                    &currencies(&["USD"])
+                )
+                .err(),
+            Some(CryptoApiError::UnknownSymbol)
+        );
+    }
+
+    #[test]
+    fn api_keys_reach_their_provider() {
+        let mut primary = MockApiClient::new("A", &[]);
+        let mut fallback = MockApiClient::new("B", &[]);
+        let mut client =
+            FailoverApiClient::new([&mut primary as Provider<MockStack, 4>, &mut fallback]);
+
+        client.set_api_key("B", "secret").unwrap();
+
+        drop(client);
+        assert_eq!(primary.api_key, None);
+        assert_eq!(fallback.api_key, Some(String::from("secret")));
+    }
+
+    #[test]
+    fn missing_currencies_come_from_the_next_provider() {
+        let mut primary = MockApiClient::new("A", &[("ETH", "USD", 2291.5)]);
+        let mut fallback =
+            MockApiClient::new("B", &[("ETH", "USD", 2290.0), ("ETH", "BTC", 0.053)]);
+        let mut client =
+            FailoverApiClient::new([&mut primary as Provider<MockStack, 4>, &mut fallback]);
+
+        let prices = client
+            .get_current_prices(
+                &mut MockStack::new(&[]),
+                &symbols(&["ETH"]),
+                &currencies(&["USD", "BTC"]),
+            )
+            .unwrap();
+
+        assert_eq!(price(&prices, "ETH", "USD"), Some(2291.5));
+        assert_eq!(price(&prices, "ETH", "BTC"), Some(0.053));
+        assert_eq!(client.source_of(&String::from("ETH")), Some("A"));
+    }
+
+    #[test]
+    fn missing_market_stats_come_from_the_next_provider() {
+        let mut primary = MockApiClient::new("A", &[("BTC", "USD", 43840.0)]);
+        let mut fallback =
+            MockApiClient::new("B", &[("BTC", "USD", 43900.0), ("GERO", "USD", 0.6)]);
+        let mut client =
+            FailoverApiClient::new([&mut primary as Provider<MockStack, 4>, &mut fallback]);
+
+        let stats = client
+            .get_market_stats(
+                &mut MockStack::new(&[]),
+                &symbols(&["BTC", "GERO"]),
+                &"USD".into(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            stats.get(&String::from("BTC")).unwrap().high_24h,
+            Some(43840.0)
+        );
+        assert_eq!(
+            stats.get(&String::from("GERO")).unwrap().high_24h,
+            Some(0.6)
+        );
+
+        drop(client);
+        assert_eq!(fallback.requested, [symbols(&["GERO"])]);
+    }
+
+    #[test]
+    fn history_comes_from_the_first_provider_answering() {
+        let mut primary = MockApiClient::new("A", &[("ETH", "USD", 2291.5)]);
+        let mut fallback = MockApiClient::new("B", &[("GERO", "USD", 0.5)]);
+        let mut client =
+            FailoverApiClient::new([&mut primary as Provider<MockStack, 4>, &mut fallback]);
+
+        let history = client
+            .get_history(
+                &mut MockStack::new(&[]),
+                &"GERO".into(),
+                &"USD".into(),
+                Interval::Hour,
+                24,
+            )
+            .unwrap();
+        assert_eq!(history[0].close, 0.5);
+
+        assert_eq!(
+            client
+                .get_history(
+                    &mut MockStack::new(&[]),
+                    &"NOPE".into(),
+                    &"USD".into(),
+                    Interval::Day,
+                    7
+                )
+                .err(),
+            Some(CryptoApiError::UnknownSymbol)
+        );
+        assert!(!primary.connected);
+    }
+
+    #[test]
+    fn symbols_are_listed_by_the_first_provider_answering() {
+        let mut primary = MockApiClient::failing(
+            "A",
+            CryptoApiError::Status {
+                status: 503,
+                retry_after: None,
+            },
+        );
+        let mut fallback =
+            MockApiClient::new("B", &[("BTC", "USD", 43000.0), ("ETH", "EUR", 2100.0)]);
+        let mut client =
+            FailoverApiClient::new([&mut primary as Provider<MockStack, 4>, &mut fallback]);
+
+        let mut listed = Vec::<String<16>, 4>::new();
+        client
+            .list_symbols(&mut MockStack::new(&[]), &"USD".into(), &mut |symbol| {
+                listed.push(symbol.into()).unwrap()
+            })
+            .unwrap();
+
+        assert_eq!(listed, ["BTC"]);
+        assert!(!primary.connected);
+    }
+}
@@ -0,0 +1,408 @@
+//! Response headers with case-insensitive lookup and typed accessors.
+
+use heapless::{String, Vec};
+
+pub const MAX_HEADERS: usize = 32;
+pub const MAX_NAME_LENGTH: usize = 64;
+pub const MAX_VALUE_LENGTH: usize = 128;
+
+#[derive(Clone, Debug)]
+pub struct HttpHeader {
+    pub name: String<MAX_NAME_LENGTH>,
+    pub value: String<MAX_VALUE_LENGTH>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum HeaderError {
+    InvalidContentLength,
+}
+
+/// Point in time given by an HTTP-date (IMF-fixdate, always in GMT)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HttpDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RetryAfter {
+    Seconds(u32),
+    Date(HttpDate),
+}
+
+#[derive(Debug, Default)]
+pub struct HttpHeaders {
+    headers: Vec<HttpHeader, MAX_HEADERS>,
+}
+
+impl HttpHeaders {
+    pub fn new() -> Self {
+        HttpHeaders {
+            headers: Vec::new(),
+        }
+    }
+
+    /// Adds a header. Headers that aren't valid UTF-8, or don't fit in `HttpHeader`, are dropped.
+    /// Returns false if there is no room left for another header.
+    pub fn push(&mut self, name: &str, value: &[u8]) -> bool {
+        if self.headers.is_full() {
+            return false;
+        }
+
+        let value = match core::str::from_utf8(value) {
+            Ok(value) => value.trim(),
+            Err(_) => return true,
+        };
+
+        if name.len() <= MAX_NAME_LENGTH && value.len() <= MAX_VALUE_LENGTH {
+            let header = HttpHeader {
+                name: String::from(name),
+                value: String::from(value),
+            };
+            self.headers.push(header).ok();
+        }
+
+        true
+    }
+
+    /// Value of the first header called `name`, compared case-insensitively
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|header| header.name.eq_ignore_ascii_case(name))
+            .map(|header| header.value.as_str())
+    }
+
+    /// Values of all headers called `name`, compared case-insensitively
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers
+            .iter()
+            .filter(move |header| header.name.eq_ignore_ascii_case(name))
+            .map(|header| header.value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HttpHeader> {
+        self.headers.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    /// Length of the body. Repeated `Content-Length` headers must agree.
+    pub fn content_length(&self) -> Result<Option<usize>, HeaderError> {
+        parse_content_length(self.get_all("Content-Length"))
+    }
+
+    /// Media type of the body, without parameters such as the charset
+    pub fn content_type(&self) -> Option<&str> {
+        self.get("Content-Type")
+            .and_then(|value| value.split(';').next())
+            .map(str::trim)
+    }
+
+    pub fn retry_after(&self) -> Option<RetryAfter> {
+        let value = self.get("Retry-After")?;
+
+        match value.parse::<u32>() {
+            Ok(seconds) => Some(RetryAfter::Seconds(seconds)),
+            Err(_) => HttpDate::parse(value).map(RetryAfter::Date),
+        }
+    }
+
+    pub fn date(&self) -> Option<HttpDate> {
+        self.get("Date").and_then(HttpDate::parse)
+    }
+
+    /// Seconds to wait before retrying, from `Retry-After`. A date is compared with the
+    /// `Date` of the response, so the device's own clock isn't needed.
+    pub fn retry_after_seconds(&self) -> Option<u32> {
+        match self.retry_after()? {
+            RetryAfter::Seconds(seconds) => Some(seconds),
+            RetryAfter::Date(date) => {
+                let seconds = date.unix_time().saturating_sub(self.date()?.unix_time());
+                Some(seconds.min(u32::MAX as u64) as u32)
+            }
+        }
+    }
+}
+
+/// Parses the values of `Content-Length` headers, which must all be equal
+pub(crate) fn parse_content_length<'a>(
+    values: impl Iterator<Item = &'a str>,
+) -> Result<Option<usize>, HeaderError> {
+    let mut content_length = None;
+
+    for value in values {
+        let value = value.trim();
+
+        if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(HeaderError::InvalidContentLength);
+        }
+
+        let length = value
+            .parse::<usize>()
+            .map_err(|_| HeaderError::InvalidContentLength)?;
+
+        if matches!(content_length, Some(previous) if previous != length) {
+            return Err(HeaderError::InvalidContentLength);
+        }
+        content_length = Some(length);
+    }
+
+    Ok(content_length)
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+impl HttpDate {
+    /// Parses an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let bytes = text.as_bytes();
+
+        if bytes.len() != 29 || &bytes[3..5] != b", " || !text.ends_with(" GMT") {
+            return None;
+        }
+
+        let number = |range: core::ops::Range<usize>| -> Option<u16> {
+            let digits = text.get(range)?;
+            if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+                return None;
+            }
+            digits.parse().ok()
+        };
+
+        let separators = [(7, b' '), (11, b' '), (16, b' '), (19, b':'), (22, b':')];
+        if separators.iter().any(|&(index, byte)| bytes[index] != byte) {
+            return None;
+        }
+
+        let month = MONTHS
+            .iter()
+            .position(|&month| Some(month) == text.get(8..11))? as u8
+            + 1;
+
+        let date = HttpDate {
+            year: number(12..16)?,
+            month,
+            day: number(5..7)? as u8,
+            hour: number(17..19)? as u8,
+            minute: number(20..22)? as u8,
+            second: number(23..25)? as u8,
+        };
+
+        let valid = date.day >= 1
+            && date.day <= days_in_month(date.year, date.month)
+            && date.hour < 24
+            && date.minute < 60
+            && date.second < 61;
+
+        if valid {
+            Some(date)
+        } else {
+            None
+        }
+    }
+
+    /// Seconds since 1970-01-01 00:00:00 UTC, or 0 for earlier dates
+    pub fn unix_time(&self) -> u64 {
+        //Days since the epoch, see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let year = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year =
+            (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+
+        let seconds =
+            days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+
+        if seconds < 0 {
+            0
+        } else {
+            seconds as u64
+        }
+    }
+}
+
+/// Every fourth year is a leap year, but of the centuries only every fourth one
+//`is_multiple_of` is newer than the toolchain of the firmware
+#[allow(unknown_lints, clippy::manual_is_multiple_of)]
+fn is_leap_year(year: u16) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HttpHeaders {
+        let mut headers = HttpHeaders::new();
+        for (name, value) in pairs {
+            assert!(headers.push(name, value.as_bytes()));
+        }
+        headers
+    }
+
+    #[test]
+    fn case_insensitive_lookup() {
+        let headers = headers(&[
+            ("content-type", "application/json; charset=UTF-8"),
+            ("Set-Cookie", "a=1"),
+            ("SET-COOKIE", "b=2"),
+        ]);
+
+        assert_eq!(
+            headers.get("Content-Type"),
+            Some("application/json; charset=UTF-8")
+        );
+        assert_eq!(headers.content_type(), Some("application/json"));
+        assert_eq!(headers.get_all("set-cookie").count(), 2);
+        assert_eq!(headers.get("Location"), None);
+    }
+
+    #[test]
+    fn content_length() {
+        assert_eq!(headers(&[]).content_length(), Ok(None));
+        assert_eq!(
+            headers(&[("content-length", " 42 ")]).content_length(),
+            Ok(Some(42))
+        );
+        assert_eq!(
+            headers(&[("Content-Length", "42"), ("Content-Length", "42")]).content_length(),
+            Ok(Some(42))
+        );
+
+        for invalid in ["", "-1", "+5", "4 2", "0x10", "99999999999999999999999"].iter() {
+            assert_eq!(
+                headers(&[("Content-Length", invalid)]).content_length(),
+                Err(HeaderError::InvalidContentLength)
+            );
+        }
+        assert_eq!(
+            headers(&[("Content-Length", "1"), ("Content-Length", "2")]).content_length(),
+            Err(HeaderError::InvalidContentLength)
+        );
+    }
+
+    #[test]
+    fn dates() {
+        let date = HttpDate::parse("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+
+        assert_eq!(
+            date,
+            HttpDate {
+                year: 1994,
+                month: 11,
+                day: 6,
+                hour: 8,
+                minute: 49,
+                second: 37
+            }
+        );
+        assert_eq!(date.unix_time(), 784111777);
+        assert_eq!(
+            HttpDate::parse("Thu, 29 Feb 2024 00:00:00 GMT")
+                .unwrap()
+                .unix_time(),
+            1709164800
+        );
+        assert!(HttpDate::parse("Tue, 29 Feb 2000 00:00:00 GMT").is_some());
+
+        let invalid = [
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Thu, 29 Feb 2023 00:00:00 GMT",
+            "Thu, 29 Feb 1900 00:00:00 GMT",
+            "Sun, 06 Nov 1994 24:49:37 GMT",
+            "Sun, 6 Nov 1994 08:49:37 GMT ",
+        ];
+        for text in invalid.iter() {
+            assert_eq!(HttpDate::parse(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn retry_after() {
+        assert_eq!(
+            headers(&[("Retry-After", "120")]).retry_after(),
+            Some(RetryAfter::Seconds(120))
+        );
+        assert_eq!(
+            headers(&[("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT")]).retry_after(),
+            Some(RetryAfter::Date(
+                HttpDate::parse("Wed, 21 Oct 2015 07:28:00 GMT").unwrap()
+            ))
+        );
+        assert_eq!(headers(&[("Retry-After", "soon")]).retry_after(), None);
+        assert_eq!(headers(&[]).date(), None);
+    }
+
+    #[test]
+    fn retry_after_seconds() {
+        assert_eq!(
+            headers(&[("Retry-After", "120")]).retry_after_seconds(),
+            Some(120)
+        );
+        assert_eq!(
+            headers(&[
+                ("Date", "Wed, 21 Oct 2015 07:27:30 GMT"),
+                ("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT")
+            ])
+            .retry_after_seconds(),
+            Some(30)
+        );
+        assert_eq!(
+            headers(&[
+                ("Date", "Wed, 21 Oct 2015 07:29:00 GMT"),
+                ("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT")
+            ])
+            .retry_after_seconds(),
+            Some(0)
+        );
+        assert_eq!(
+            headers(&[("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT")]).retry_after_seconds(),
+            None
+        );
+    }
+
+    #[test]
+    fn unusable_headers_are_dropped() {
+        let mut headers = HttpHeaders::new();
+        let long_value = [b'a'; MAX_VALUE_LENGTH + 1];
+
+        assert!(headers.push("X-Long", &long_value));
+        assert!(headers.push("X-Binary", b"\xff\xfe"));
+        assert!(headers.is_empty());
+
+        for _ in 0..MAX_HEADERS {
+            assert!(headers.push("X-Filler", b"1"));
+        }
+        assert!(!headers.push("X-Filler", b"1"));
+        assert_eq!(headers.len(), MAX_HEADERS);
+    }
+}
@@ -1,132 +1,1142 @@
-//Adding this because rust won't stop complaining about some variables being mutable, while in fact,
-//they need to be in order for program to compile
-#![allow(unused_assignments)]
-
-use drogue_network::tcp::TcpStack;
+use crate::chunked::{ChunkedDecoder, ChunkedError};
+use crate::headers::{self, HeaderError, HttpHeaders, MAX_HEADERS};
+use crate::inflate::{Format, InflateError, Inflater, StreamInflater};
+use crate::request::{Method, Request, RequestError};
+use crate::resolver::Resolver;
+use crate::url::Url;
+use drogue_network::addr::{HostAddr, HostSocketAddr, IpAddr};
+use drogue_network::tcp::{Mode, TcpError, TcpStack};
 use heapless::{String, Vec};
-use httparse::{Response, EMPTY_HEADER};
+use httparse::{Header, Response, Status, EMPTY_HEADER};
 
-#[derive(Debug)]
-pub struct HttpHeader {
-    pub name: String<64>,
-    pub value: String<128>,
+pub use crate::headers::HttpHeader;
+
+/// Size of the buffer the response is read into. The whole response header must fit in it.
+const READ_BUFFER_SIZE: usize = 2048;
+/// Size of the buffer the request is serialized into
+pub(crate) const REQUEST_BUFFER_SIZE: usize = 1024;
+/// Longest URL a redirect may point to
+const MAX_URL_LENGTH: usize = 512;
+/// Longest name a host may have in DNS
+const MAX_HOSTNAME_LENGTH: usize = 253;
+
+#[derive(Debug, PartialEq)]
+pub enum HttpError {
+    Request(RequestError),
+    /// The resolver doesn't know the address of the host
+    UnknownHost,
+    /// Opening a socket or connecting it failed
+    ConnectError(TransportError),
+    WriteError(TransportError),
+    /// Reading from the socket failed
+    ReadError(TransportError),
+    /// The connection was closed before the whole response was received
+    ConnectionClosed,
+    MalformedResponse,
+    HeaderTooLarge,
+    TooManyHeaders,
+    InvalidContentLength,
+    BodyTooLarge,
+    InvalidChunkedBody(ChunkedError),
+    /// The body is in a content coding other than `gzip` or `deflate`
+    UnsupportedContentEncoding,
+    InvalidCompressedBody(InflateError),
+    /// The redirect has no `Location` header, it isn't a valid URL or it is longer
+    /// than `MAX_URL_LENGTH`
+    InvalidRedirect,
+    TooManyRedirects,
+    /// The receiver of a streamed body stopped reading it
+    BodyRejected,
+}
+
+/// Failure reported by the network stack, which includes TLS if the stack provides it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransportError {
+    NoAvailableSockets,
+    ConnectionRefused,
+    SocketNotOpen,
+    Write,
+    Read,
+    Timeout,
+    /// The stack can't take the operation at the moment
+    Busy,
+    Other,
+}
+
+impl From<TcpError> for TransportError {
+    fn from(error: TcpError) -> Self {
+        match error {
+            TcpError::NoAvailableSockets => TransportError::NoAvailableSockets,
+            TcpError::ConnectionRefused => TransportError::ConnectionRefused,
+            TcpError::SocketNotOpen => TransportError::SocketNotOpen,
+            TcpError::WriteError => TransportError::Write,
+            TcpError::ReadError => TransportError::Read,
+            TcpError::Timeout => TransportError::Timeout,
+            TcpError::Busy => TransportError::Busy,
+            TcpError::Impl(_) => TransportError::Other,
+        }
+    }
+}
+
+/// Kind of an error of the network stack
+fn transport_error<ErrorT: Into<TcpError>>(error: ErrorT) -> TransportError {
+    error.into().into()
+}
+
+impl From<RequestError> for HttpError {
+    fn from(error: RequestError) -> Self {
+        HttpError::Request(error)
+    }
+}
+
+impl From<HeaderError> for HttpError {
+    fn from(error: HeaderError) -> Self {
+        match error {
+            HeaderError::InvalidContentLength => HttpError::InvalidContentLength,
+        }
+    }
+}
+
+impl From<ChunkedError> for HttpError {
+    fn from(error: ChunkedError) -> Self {
+        HttpError::InvalidChunkedBody(error)
+    }
+}
+
+impl From<InflateError> for HttpError {
+    fn from(error: InflateError) -> Self {
+        match error {
+            InflateError::OutputTooSmall => HttpError::BodyTooLarge,
+            error => HttpError::InvalidCompressedBody(error),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct HttpResponse<const MAX_RESPONSE_LENGTH: usize> {
     pub status: u16,
-    pub headers: Vec<HttpHeader, 32>,
-    pub content: String<MAX_RESPONSE_LENGTH>,
+    pub headers: HttpHeaders,
+    body: Vec<u8, MAX_RESPONSE_LENGTH>,
+    //`Location` of a redirect, kept apart since it may be too long for `headers`
+    location: Option<String<MAX_URL_LENGTH>>,
+    keep_alive: bool,
+}
+
+impl<const MAX_RESPONSE_LENGTH: usize> HttpResponse<MAX_RESPONSE_LENGTH> {
+    /// Body of the response, with the transfer coding and the content coding removed.
+    /// The `Content-Encoding` header is left as the server sent it.
+    pub fn body_bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Body of the response as text, if it is valid UTF-8
+    pub fn body_str(&self) -> Result<&str, core::str::Utf8Error> {
+        core::str::from_utf8(&self.body)
+    }
+
+    /// True if the connection the response came on can be used for another request
+    pub fn keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+}
+
+/// How the end of the body is determined
+enum Framing {
+    /// Responses to HEAD, and 1xx, 204 and 304 responses, never have a body
+    Empty,
+    Chunked,
+    ContentLength(usize),
+    UntilClosed,
+}
+
+/// Sends the request over a new connection and reads the response.
+/// # Arguments
+/// * `stack` - Network stack to connect through
+/// * `resolver` - Gives the addresses of the hosts, including the ones redirected to
+/// * `request` - Request to send
+/// * `max_redirects` - How many redirects to follow. With 0, redirects are returned as they are.
+pub fn send_request<StackT, ResolverT, const MAX_RESPONSE_LENGTH: usize>(
+    stack: &mut StackT,
+    resolver: &ResolverT,
+    request: &Request,
+    max_redirects: u8,
+) -> Result<HttpResponse<MAX_RESPONSE_LENGTH>, HttpError>
+where
+    StackT: TcpStack,
+    ResolverT: Resolver,
+{
+    follow_redirects(request, max_redirects, |request| {
+        exchange(stack, resolver, request)
+    })
+}
+
+/// Sends the request with `exchange`, and then the requests to the URLs it is redirected to
+pub(crate) fn follow_redirects<const MAX_RESPONSE_LENGTH: usize>(
+    request: &Request,
+    max_redirects: u8,
+    mut exchange: impl FnMut(&Request) -> Result<HttpResponse<MAX_RESPONSE_LENGTH>, HttpError>,
+) -> Result<HttpResponse<MAX_RESPONSE_LENGTH>, HttpError> {
+    let mut location: String<MAX_URL_LENGTH> = String::new();
+    let mut method = request.method();
+    let mut keep_body = true;
+
+    for hop in 0..=max_redirects {
+        //The redirected request borrows `location`, so it must be gone before that is replaced
+        let next: String<MAX_URL_LENGTH> = {
+            let redirected;
+            let current = if hop == 0 {
+                request
+            } else {
+                redirected = request.with_url(&location, method, keep_body)?;
+                &redirected
+            };
+
+            let response = exchange(current)?;
+
+            if max_redirects == 0 || !is_redirect(response.status) {
+                return Ok(response);
+            }
+
+            //307 and 308 require the method and body to stay the same. After 303 the target is
+            //fetched with GET, and so is a POST redirected with 301 or 302, as browsers do.
+            match response.status {
+                303 if method != Method::Head => {
+                    method = Method::Get;
+                    keep_body = false;
+                }
+                301 | 302 if method == Method::Post => {
+                    method = Method::Get;
+                    keep_body = false;
+                }
+                _ => {}
+            }
+
+            let target = response
+                .location
+                .as_deref()
+                .ok_or(HttpError::InvalidRedirect)?;
+            current
+                .url()
+                .join(target)
+                .map_err(|_| HttpError::InvalidRedirect)?
+        };
+
+        location = next;
+    }
+
+    Err(HttpError::TooManyRedirects)
+}
+
+fn is_redirect(status: u16) -> bool {
+    matches!(status, 301 | 302 | 303 | 307 | 308)
+}
+
+/// Sends a single request on its own connection, which is closed afterwards
+fn exchange<StackT: TcpStack, ResolverT: Resolver, const MAX_RESPONSE_LENGTH: usize>(
+    stack: &mut StackT,
+    resolver: &ResolverT,
+    request: &Request,
+) -> Result<HttpResponse<MAX_RESPONSE_LENGTH>, HttpError> {
+    let data: Vec<u8, REQUEST_BUFFER_SIZE> = request.serialize()?;
+    let mut socket = connect(stack, resolver, request.url())?;
+
+    let head = request.method() == Method::Head;
+    let response = write_all(stack, &mut socket, &data)
+        .and_then(|_| read_response_to(stack, &mut socket, head));
+
+    stack.close(socket).ok();
+
+    response
+}
+
+/// Opens a socket connected to the host and port of the URL
+pub(crate) fn connect<StackT: TcpStack, ResolverT: Resolver>(
+    stack: &mut StackT,
+    resolver: &ResolverT,
+    url: &Url,
+) -> Result<StackT::TcpSocket, HttpError> {
+    let ip = resolver.resolve(url.host).ok_or(HttpError::UnknownHost)?;
+    //TLS sends the name along, servers sharing an address choose the certificate by it
+    let hostname = Some(url.host)
+        .filter(|host| is_hostname(host))
+        .map(Into::into);
+    let remote = HostSocketAddr::new(HostAddr::new(IpAddr::V4(ip), hostname), url.port);
+
+    let socket = stack
+        .open(Mode::NonBlocking)
+        .map_err(|error| HttpError::ConnectError(transport_error(error)))?;
+    stack
+        .connect(socket, remote)
+        .map_err(|error| HttpError::ConnectError(transport_error(error)))
+}
+
+/// True if `host` is a name rather than an address, short enough to be sent with TLS
+fn is_hostname(host: &str) -> bool {
+    host.len() <= MAX_HOSTNAME_LENGTH && !host.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+}
+
+pub(crate) fn write_all<StackT: TcpStack>(
+    stack: &mut StackT,
+    socket: &mut StackT::TcpSocket,
+    mut data: &[u8],
+) -> Result<(), HttpError> {
+    while !data.is_empty() {
+        match stack.write(socket, data) {
+            Ok(0) => return Err(HttpError::WriteError(TransportError::Write)),
+            Ok(count) => data = &data[count..],
+            Err(nb::Error::WouldBlock) => continue,
+            Err(nb::Error::Other(error)) => {
+                return Err(HttpError::WriteError(transport_error(error)))
+            }
+        }
+    }
+
+    Ok(())
 }
 
 pub fn read_response<StackT: TcpStack, const MAX_RESPONSE_LENGTH: usize>(
     stack: &mut StackT,
     socket: &mut StackT::TcpSocket,
-) -> Option<HttpResponse<MAX_RESPONSE_LENGTH>> {
-    let mut buffer = [0; 2048];
+) -> Result<HttpResponse<MAX_RESPONSE_LENGTH>, HttpError> {
+    read_response_to(stack, socket, false)
+}
+
+/// Reads the response to a request, which has no body if the request was HEAD
+fn read_response_to<StackT: TcpStack, const MAX_RESPONSE_LENGTH: usize>(
+    stack: &mut StackT,
+    socket: &mut StackT::TcpSocket,
+    head: bool,
+) -> Result<HttpResponse<MAX_RESPONSE_LENGTH>, HttpError> {
+    let mut body = BodyWriter::new();
+    let response = read_response_into(stack, socket, head, &mut body)?;
+    body.into_response(response)
+}
+
+/// Reads the response to a request, passing its body to `body`.
+/// The response returned has the status and the headers, including trailer fields.
+pub(crate) fn read_response_into<StackT: TcpStack, SinkT: BodySink>(
+    stack: &mut StackT,
+    socket: &mut StackT::TcpSocket,
+    head: bool,
+    body: &mut SinkT,
+) -> Result<HttpResponse<0>, HttpError> {
+    let mut buffer = [0; READ_BUFFER_SIZE];
+    let mut bytes_read: usize = 0;
+
+    //Read until the whole response header is in the buffer
+    let (status, mut headers, location, framing, coding, persistent, offset) = loop {
+        if bytes_read == buffer.len() {
+            return Err(HttpError::HeaderTooLarge);
+        }
+
+        bytes_read += read_some(stack, socket, &mut buffer[bytes_read..])?;
+
+        let mut res_headers = [EMPTY_HEADER; MAX_HEADERS];
+        let mut response = Response::new(&mut res_headers);
+
+        let offset = match response.parse(&buffer[..bytes_read]) {
+            Ok(Status::Complete(offset)) => offset,
+            Ok(Status::Partial) => continue,
+            Err(httparse::Error::TooManyHeaders) => return Err(HttpError::TooManyHeaders),
+            Err(_) => return Err(HttpError::MalformedResponse),
+        };
+
+        let status = response.code.ok_or(HttpError::MalformedResponse)?;
 
-    let mut full_response = String::<MAX_RESPONSE_LENGTH>::new();
-    let mut headers = Vec::new();
+        //After parsing, only the headers present in the response are left in the slice
+        let framing = if head || matches!(status, 100..=199 | 204 | 304) {
+            Framing::Empty
+        } else {
+            framing(response.headers)?
+        };
+        let coding = match framing {
+            Framing::Empty => None,
+            _ => content_coding(response.headers)?,
+        };
+        let persistent = is_persistent(response.version, response.headers);
+        let location = if is_redirect(status) {
+            redirect_location(response.headers)
+        } else {
+            None
+        };
 
-    let mut offset: usize = 0;
-    let mut status: u16 = 0;
+        let mut headers = HttpHeaders::new();
+        for header in response.headers.iter() {
+            headers.push(header.name, header.value);
+        }
 
-    let mut content_bytes_read: usize = 0;
-    let mut content_length: usize = 0;
+        break (
+            status, headers, location, framing, coding, persistent, offset,
+        );
+    };
 
-    //Parse first read to get content length. We assume the entire response header will fit in first read
+    body.start(status, coding)?;
 
-    let read_result = stack.read(socket, &mut buffer);
+    //Data left after the body means the server and the client disagree about the framing
+    let data_after_body = match framing {
+        Framing::Empty => offset < bytes_read,
+        Framing::Chunked => {
+            let (trailers, data_after_body) =
+                read_chunked_body(stack, socket, &mut buffer, offset, bytes_read, body)?;
 
-    if read_result.is_err() {
-        return None;
+            for trailer in trailers.iter() {
+                headers.push(&trailer.name, trailer.value.as_bytes());
+            }
+            data_after_body
+        }
+        Framing::ContentLength(length) => read_body(
+            stack,
+            socket,
+            &mut buffer,
+            offset,
+            bytes_read,
+            Some(length),
+            body,
+        )?,
+        Framing::UntilClosed => {
+            read_body(stack, socket, &mut buffer, offset, bytes_read, None, body)?
+        }
+    };
+
+    let keep_alive = persistent && !data_after_body && !matches!(framing, Framing::UntilClosed);
+
+    Ok(HttpResponse {
+        status,
+        headers,
+        body: Vec::new(),
+        location,
+        keep_alive,
+    })
+}
+
+/// Reads at least one byte from the socket
+fn read_some<StackT: TcpStack>(
+    stack: &mut StackT,
+    socket: &mut StackT::TcpSocket,
+    buffer: &mut [u8],
+) -> Result<usize, HttpError> {
+    match stack.read(socket, buffer) {
+        Ok(0) => Err(HttpError::ConnectionClosed),
+        Ok(count) => Ok(count),
+        Err(nb::Error::WouldBlock) => Err(HttpError::ReadError(TransportError::Busy)),
+        Err(nb::Error::Other(error)) => Err(HttpError::ReadError(transport_error(error))),
     }
+}
 
-    let mut res_headers = [EMPTY_HEADER; 32];
-    let mut response = Response::new(&mut res_headers);
+/// The first `Location` header, `None` if there is none or it doesn't fit in a URL
+fn redirect_location(raw_headers: &[Header]) -> Option<String<MAX_URL_LENGTH>> {
+    let location = raw_headers
+        .iter()
+        .find(|header| header.name.eq_ignore_ascii_case("Location"))?;
+    let location = core::str::from_utf8(location.value).ok()?.trim();
 
-    let parse_result = response.parse(&buffer);
+    let mut url = String::new();
+    url.push_str(location).ok()?;
+    Some(url)
+}
+
+/// Chunked transfer coding takes precedence over `Content-Length`
+fn framing(raw_headers: &[Header]) -> Result<Framing, HttpError> {
+    let named = |name: &'static str| {
+        raw_headers
+            .iter()
+            .filter(move |header| header.name.eq_ignore_ascii_case(name))
+            .map(|header| header.value)
+    };
 
-    if parse_result.is_err() {
-        return None;
+    if named("Transfer-Encoding").any(is_chunked) {
+        return Ok(Framing::Chunked);
     }
 
-    offset = parse_result.unwrap().unwrap();
-    status = response.code.unwrap();
+    let mut lengths = Vec::<&str, MAX_HEADERS>::new();
+    for value in named("Content-Length") {
+        let value = core::str::from_utf8(value).map_err(|_| HttpError::InvalidContentLength)?;
+        lengths.push(value).ok();
+    }
+
+    match headers::parse_content_length(lengths.iter().copied())? {
+        Some(length) => Ok(Framing::ContentLength(length)),
+        None => Ok(Framing::UntilClosed),
+    }
+}
+
+/// Checks if chunked is the final transfer coding applied to the body
+fn is_chunked(transfer_encoding: &[u8]) -> bool {
+    let coding = transfer_encoding
+        .rsplit(|&byte| byte == b',')
+        .next()
+        .and_then(|coding| core::str::from_utf8(coding).ok());
+
+    matches!(coding, Some(coding) if coding.trim().eq_ignore_ascii_case("chunked"))
+}
+
+/// Format of the content coding of the body, or `None` if it isn't compressed
+fn content_coding(raw_headers: &[Header]) -> Result<Option<Format>, HttpError> {
+    let mut format = None;
+
+    let codings = raw_headers
+        .iter()
+        .filter(|header| header.name.eq_ignore_ascii_case("Content-Encoding"))
+        .flat_map(|header| header.value.split(|&byte| byte == b','));
+
+    for coding in codings {
+        let coding = core::str::from_utf8(coding)
+            .map_err(|_| HttpError::UnsupportedContentEncoding)?
+            .trim();
+
+        let coding_format =
+            if coding.eq_ignore_ascii_case("gzip") || coding.eq_ignore_ascii_case("x-gzip") {
+                Format::Gzip
+            } else if coding.eq_ignore_ascii_case("deflate") {
+                Format::Zlib
+            } else if coding.is_empty() || coding.eq_ignore_ascii_case("identity") {
+                continue;
+            } else {
+                return Err(HttpError::UnsupportedContentEncoding);
+            };
+
+        //Only a single layer of compression is supported
+        if format.replace(coding_format).is_some() {
+            return Err(HttpError::UnsupportedContentEncoding);
+        }
+    }
+
+    Ok(format)
+}
 
-    for header in res_headers.iter(){
-        let new_header = HttpHeader {
-            name: String::from(header.name),
-            value: String::from(core::str::from_utf8(header.value).unwrap()),
+/// HTTP/1.1 connections are persistent unless the server says `Connection: close`,
+/// HTTP/1.0 ones only if it says `Connection: keep-alive`
+fn is_persistent(version: Option<u8>, raw_headers: &[Header]) -> bool {
+    let has_option = |option: &str| {
+        raw_headers
+            .iter()
+            .filter(|header| header.name.eq_ignore_ascii_case("Connection"))
+            .flat_map(|header| header.value.split(|&byte| byte == b','))
+            .filter_map(|value| core::str::from_utf8(value).ok())
+            .any(|value| value.trim().eq_ignore_ascii_case(option))
+    };
+
+    match version {
+        Some(1) => !has_option("close"),
+        _ => has_option("keep-alive"),
+    }
+}
+
+/// Reads a body of `content_length` bytes, or until the connection is closed if the length
+/// is not known. Starts with the part of the body that came with the headers.
+/// Returns true if more data came after the body.
+fn read_body<StackT: TcpStack, SinkT: BodySink>(
+    stack: &mut StackT,
+    socket: &mut StackT::TcpSocket,
+    buffer: &mut [u8],
+    mut start: usize,
+    mut end: usize,
+    content_length: Option<usize>,
+    body: &mut SinkT,
+) -> Result<bool, HttpError> {
+    let mut remaining = content_length.unwrap_or(usize::MAX);
+
+    loop {
+        let count = (end - start).min(remaining);
+        body.write(&buffer[start..start + count])?;
+        remaining -= count;
+
+        if remaining == 0 {
+            return Ok(start + count < end);
+        }
+
+        start = 0;
+        end = match read_some(stack, socket, buffer) {
+            Ok(count) => count,
+            //That's the end of the body only if its length is not known
+            Err(HttpError::ConnectionClosed) if content_length.is_none() => return Ok(false),
+            Err(error) => return Err(error),
         };
+    }
+}
+
+/// Reads and decodes a chunked body, starting with the part of it that came with the headers.
+/// Returns the trailer fields, and whether more data came after the body.
+fn read_chunked_body<StackT: TcpStack, SinkT: BodySink>(
+    stack: &mut StackT,
+    socket: &mut StackT::TcpSocket,
+    buffer: &mut [u8],
+    mut start: usize,
+    mut end: usize,
+    body: &mut SinkT,
+) -> Result<(HttpHeaders, bool), HttpError> {
+    let mut decoder = ChunkedDecoder::new();
 
-        headers.push(new_header).ok();
+    loop {
+        let mut written = Ok(());
+        let consumed = decoder.feed(&buffer[start..end], |data| {
+            if written.is_ok() {
+                written = body.write(data);
+            }
+        })?;
+        written?;
 
-        if header.name == "Content-Length" {
-            content_length = core::str::from_utf8(header.value)
-                .unwrap()
-                .parse::<usize>()
-                .unwrap();
+        if decoder.is_finished() {
+            return Ok((decoder.into_trailers(), start + consumed < end));
         }
+
+        start = 0;
+        end = read_some(stack, socket, buffer)?;
     }
-    
+}
+
+/// Destination of the body of a response, with the transfer coding removed
+pub(crate) trait BodySink {
+    /// Called with the status and the content coding of the response before its body is read
+    fn start(&mut self, status: u16, coding: Option<Format>) -> Result<(), HttpError>;
 
-    let string = core::str::from_utf8(&buffer);
+    fn write(&mut self, data: &[u8]) -> Result<(), HttpError>;
+}
+
+/// Collects the body, inflating it if it is compressed
+pub(crate) struct BodyWriter<const MAX_RESPONSE_LENGTH: usize> {
+    body: Vec<u8, MAX_RESPONSE_LENGTH>,
+    inflater: Option<Inflater>,
+    /// Length of the inflated data. The whole buffer is the output of the inflater.
+    inflated_length: usize,
+    compressed_length: usize,
+}
 
-    if string.is_err() {
-        return None;
+impl<const MAX_RESPONSE_LENGTH: usize> BodyWriter<MAX_RESPONSE_LENGTH> {
+    pub(crate) fn new() -> Self {
+        BodyWriter {
+            body: Vec::new(),
+            inflater: None,
+            inflated_length: 0,
+            compressed_length: 0,
+        }
     }
 
-    let mut string = string.unwrap();
+    /// Adds the body to the response read with `read_response_into`
+    pub(crate) fn into_response(
+        mut self,
+        response: HttpResponse<0>,
+    ) -> Result<HttpResponse<MAX_RESPONSE_LENGTH>, HttpError> {
+        if let Some(inflater) = &self.inflater {
+            //Some servers mark empty bodies, such as the ones of redirects, as compressed
+            if self.compressed_length > 0 {
+                inflater.finish()?;
+            }
+            self.body.truncate(self.inflated_length);
+        }
 
-    let terminator_index = string.find("\0").unwrap_or(buffer.len());
+        Ok(HttpResponse {
+            status: response.status,
+            headers: response.headers,
+            body: self.body,
+            location: response.location,
+            keep_alive: response.keep_alive,
+        })
+    }
+}
 
-    string = &string[0..terminator_index];
-    content_bytes_read += string[offset..terminator_index].len();
+impl<const MAX_RESPONSE_LENGTH: usize> BodySink for BodyWriter<MAX_RESPONSE_LENGTH> {
+    fn start(&mut self, _status: u16, coding: Option<Format>) -> Result<(), HttpError> {
+        if coding.is_some() {
+            self.body.resize(MAX_RESPONSE_LENGTH, 0).ok();
+        }
+        self.inflater = coding.map(Inflater::new);
 
-    let _result = full_response.push_str(string);
+        Ok(())
+    }
 
-    buffer.fill(0);
+    fn write(&mut self, data: &[u8]) -> Result<(), HttpError> {
+        match &mut self.inflater {
+            Some(inflater) => {
+                self.compressed_length += data.len();
+                inflater.feed(data, &mut self.body, &mut self.inflated_length)?;
+            }
+            None => self
+                .body
+                .extend_from_slice(data)
+                .map_err(|_| HttpError::BodyTooLarge)?,
+        }
 
-    //Read until we read content-length data
-    //If server doesn't return content length header, read until socket is closed
-    while content_bytes_read < content_length || content_length == 0 {
+        Ok(())
+    }
+}
 
-        let read_result = stack.read(socket, &mut buffer);
+/// Passes the body of the response to `sink` as it is read, inflating it if it is compressed.
+/// When redirects are followed, the bodies of the redirects are dropped.
+pub(crate) struct StreamingBody<SinkT> {
+    sink: SinkT,
+    follow_redirects: bool,
+    discard: bool,
+    //Inflates through a window of `WINDOW_SIZE` bytes rather than the whole body
+    inflater: Option<StreamInflater>,
+    compressed_length: usize,
+}
 
-        match read_result {
-            Ok(bytes_read) => {
-                if bytes_read == 0 {
-                    break;
-                }
+impl<SinkT: FnMut(&[u8]) -> Result<(), ()>> StreamingBody<SinkT> {
+    pub(crate) fn new(sink: SinkT, follow_redirects: bool) -> Self {
+        StreamingBody {
+            sink,
+            follow_redirects,
+            discard: false,
+            inflater: None,
+            compressed_length: 0,
+        }
+    }
+
+    /// Checks that the compressed body of the last response was complete
+    pub(crate) fn finish(&self) -> Result<(), HttpError> {
+        match &self.inflater {
+            //Some servers mark empty bodies as compressed
+            Some(inflater) if self.compressed_length > 0 => Ok(inflater.finish()?),
+            _ => Ok(()),
+        }
+    }
+}
 
-                let string = core::str::from_utf8(&buffer);
+impl<SinkT: FnMut(&[u8]) -> Result<(), ()>> BodySink for StreamingBody<SinkT> {
+    fn start(&mut self, status: u16, coding: Option<Format>) -> Result<(), HttpError> {
+        self.discard = self.follow_redirects && is_redirect(status);
+        self.inflater = coding.filter(|_| !self.discard).map(StreamInflater::new);
+        self.compressed_length = 0;
 
-                if string.is_err() {
-                    continue;
-                }
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), HttpError> {
+        if self.discard {
+            return Ok(());
+        }
+
+        let sink = &mut self.sink;
+        let mut output = |data: &[u8]| sink(data).map_err(|_| HttpError::BodyRejected);
+
+        match &mut self.inflater {
+            Some(inflater) => {
+                self.compressed_length += data.len();
+                inflater.feed(data, output)
+            }
+            None => output(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::StaticResolver;
+    use crate::test_support::{MockStack, Step};
+    use drogue_network::addr::Ipv4Addr;
+
+    fn read_canned<const SIZE: usize>(
+        response: &[u8],
+        read_size: usize,
+    ) -> Result<HttpResponse<SIZE>, HttpError> {
+        let script = [Step::Send(response)];
+        let scripts: [&[Step]; 1] = [&script];
+        let mut stack = MockStack::new(&scripts).read_size(read_size);
+        let mut socket = stack.connected();
+        read_response(&mut stack, &mut socket)
+    }
+
+    const HOSTS: [(&str, Ipv4Addr); 2] = [
+        ("old.example.com", Ipv4Addr::new(10, 0, 0, 1)),
+        ("new.example.com", Ipv4Addr::new(10, 0, 0, 2)),
+    ];
+
+    fn written_request_lines(stack: &MockStack) -> Vec<String<128>, 8> {
+        stack
+            .written()
+            .split("\r\n")
+            .filter(|line| {
+                line.starts_with("GET ") || line.starts_with("POST ") || line.starts_with("Host: ")
+            })
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn body_split_inside_utf8_character() {
+        let response = "HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nzażółć".as_bytes();
+
+        for read_size in 1..=response.len() {
+            let response = read_canned::<64>(response, read_size).unwrap();
+
+            assert_eq!(response.status, 200);
+            assert_eq!(response.body_str(), Ok("zażółć"));
+        }
+    }
 
-                let mut string = string.unwrap();
+    #[test]
+    fn binary_body() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n\x1f\x8b\x00\xff";
+        let response = read_canned::<64>(response, 7).unwrap();
 
-                let terminator_index = string.find("\0").unwrap_or(buffer.len());
+        assert_eq!(response.body_bytes(), b"\x1f\x8b\x00\xff");
+        assert!(response.body_str().is_err());
+    }
+
+    #[test]
+    fn body_until_connection_closed() {
+        let response = b"HTTP/1.0 200 OK\r\n\r\n{\"BTC\":{\"USD\":1}}";
+        let response = read_canned::<64>(response, 5).unwrap();
+
+        assert_eq!(response.body_str(), Ok("{\"BTC\":{\"USD\":1}}"));
+    }
+
+    #[test]
+    fn chunked_body() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n2\r\n{}\r\n0\r\nX-Trailer: 1\r\n\r\n";
+        let response = read_canned::<64>(response, 3).unwrap();
+
+        assert_eq!(response.body_str(), Ok("{}"));
+        assert_eq!(response.headers.get("x-trailer"), Some("1"));
+    }
+
+    #[test]
+    fn truncated_or_oversized_body() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort";
+        assert_eq!(
+            read_canned::<64>(response, 64).unwrap_err(),
+            HttpError::ConnectionClosed
+        );
 
-                string = &string[0..terminator_index];
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n0123456789";
+        assert_eq!(
+            read_canned::<8>(response, 64).unwrap_err(),
+            HttpError::BodyTooLarge
+        );
+    }
+
+    #[test]
+    fn compressed_bodies() {
+        use crate::inflate::tests::{DECOMPRESSED, GZIP};
+
+        let mut response = Vec::<u8, 256>::new();
+        response
+            .extend_from_slice(
+                b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: 86\r\n\r\n",
+            )
+            .unwrap();
+        response.extend_from_slice(GZIP).unwrap();
+
+        let mut chunked = Vec::<u8, 256>::new();
+        chunked
+            .extend_from_slice(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Encoding: x-gzip, identity\r\n\r\n20\r\n")
+            .unwrap();
+        chunked.extend_from_slice(&GZIP[..32]).unwrap();
+        chunked.extend_from_slice(b"\r\n36\r\n").unwrap();
+        chunked.extend_from_slice(&GZIP[32..]).unwrap();
+        chunked.extend_from_slice(b"\r\n0\r\n\r\n").unwrap();
 
-                let _result = full_response.push_str(string);
+        for response in [&response, &chunked].iter() {
+            for read_size in 1..=response.len() {
+                let response = read_canned::<128>(response, read_size).unwrap();
 
-                buffer.fill(0);
+                assert_eq!(response.body_bytes(), DECOMPRESSED);
+                assert!(response.keep_alive());
             }
-            Err(_) => {
-                break;
+
+            //The compressed body fits, the inflated one doesn't
+            assert_eq!(
+                read_canned::<100>(response, 64).unwrap_err(),
+                HttpError::BodyTooLarge
+            );
+        }
+    }
+
+    #[test]
+    fn content_coding_errors() {
+        let cases: [(&[u8], HttpError); 5] = [
+            (
+                b"HTTP/1.1 200 OK\r\nContent-Encoding: br\r\nContent-Length: 2\r\n\r\n{}",
+                HttpError::UnsupportedContentEncoding,
+            ),
+            (
+                b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip, deflate\r\nContent-Length: 2\r\n\r\n{}",
+                HttpError::UnsupportedContentEncoding,
+            ),
+            (
+                b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: 2\r\n\r\n{}",
+                HttpError::InvalidCompressedBody(InflateError::Truncated),
+            ),
+            (
+                b"HTTP/1.1 200 OK\r\nContent-Encoding: deflate\r\nContent-Length: 4\r\n\r\n\x78\x9c\xff\xff",
+                HttpError::InvalidCompressedBody(InflateError::InvalidData),
+            ),
+            (
+                b"HTTP/1.1 302 Found\r\nContent-Encoding: br\r\nContent-Length: 0\r\n\r\n",
+                HttpError::UnsupportedContentEncoding,
+            ),
+        ];
+
+        for (response, error) in cases.iter() {
+            assert_eq!(read_canned::<64>(response, 64).unwrap_err(), *error);
+        }
+
+        //Empty bodies and responses without a body are never inflated
+        let cases: [&[u8]; 2] = [
+            b"HTTP/1.1 302 Found\r\nContent-Encoding: gzip\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 304 Not Modified\r\nContent-Encoding: br\r\n\r\n",
+        ];
+
+        for response in cases.iter() {
+            assert_eq!(read_canned::<64>(response, 64).unwrap().body_bytes(), b"");
+        }
+    }
+
+    #[test]
+    fn interrupted_responses() {
+        const HEADER: Step = Step::Send(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n");
+        let cases: [(&[Step], HttpError); 4] = [
+            //A stack that would block has nothing to give yet
+            (
+                &[HEADER, Step::WouldBlock, Step::Send(b"0123456789")],
+                HttpError::ReadError(TransportError::Busy),
+            ),
+            (
+                &[Step::WouldBlock, HEADER],
+                HttpError::ReadError(TransportError::Busy),
+            ),
+            //The server closing the connection in the middle of the body
+            (&[HEADER, Step::Send(b"01234")], HttpError::ConnectionClosed),
+            (
+                &[HEADER, Step::Send(b"01234"), Step::Fail],
+                HttpError::ReadError(TransportError::Read),
+            ),
+        ];
+
+        for (script, error) in cases.iter() {
+            let scripts = [*script];
+            for read_size in [1, 3, 64].iter() {
+                let mut stack = MockStack::new(&scripts).read_size(*read_size);
+                let mut socket = stack.connected();
+
+                assert_eq!(
+                    read_response::<_, 64>(&mut stack, &mut socket).unwrap_err(),
+                    *error
+                );
             }
-        }   
+        }
     }
 
-    let response = HttpResponse{
-        status,
-        headers,
-        content: String::from(&full_response[offset..])
-    };
+    #[test]
+    fn header_larger_than_buffer() {
+        let mut response = Vec::<u8, 4096>::new();
+        response
+            .extend_from_slice(b"HTTP/1.1 200 OK\r\nX-Padding: ")
+            .unwrap();
+        response.resize(READ_BUFFER_SIZE + 10, b'a').unwrap();
+
+        assert_eq!(
+            read_canned::<64>(&response, 512).unwrap_err(),
+            HttpError::HeaderTooLarge
+        );
+    }
+
+    #[test]
+    fn only_received_headers_are_kept() {
+        let response = b"HTTP/1.1 404 Not Found\r\ncontent-length: 2\r\nContent-Type: text/plain\r\nX-Raw: \xff\r\n\r\nno";
+        let response = read_canned::<64>(response, 64).unwrap();
 
-    Some(response)
+        assert_eq!(response.status, 404);
+        assert_eq!(response.headers.len(), 2);
+        assert_eq!(response.headers.content_length(), Ok(Some(2)));
+        assert_eq!(response.headers.content_type(), Some("text/plain"));
+        assert_eq!(response.body_str(), Ok("no"));
+    }
+
+    #[test]
+    fn malformed_responses_are_errors() {
+        let cases: [(&[u8], HttpError); 4] = [
+            (
+                b"HTTP/1.1 200 OK\r\nContent-Length: ten\r\n\r\n",
+                HttpError::InvalidContentLength,
+            ),
+            (
+                b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+                HttpError::InvalidContentLength,
+            ),
+            (b"HTTP/1.1 OK\r\n\r\n", HttpError::MalformedResponse),
+            (
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+                HttpError::InvalidChunkedBody(ChunkedError::InvalidChunkSize),
+            ),
+        ];
+
+        for (response, error) in cases.iter() {
+            assert_eq!(read_canned::<64>(response, 64).unwrap_err(), *error);
+        }
+    }
+
+    #[test]
+    fn follow_redirects_across_hosts() {
+        let scripts: [&[Step]; 3] = [
+            &[Step::Respond(b"HTTP/1.1 301 Moved Permanently\r\nLocation: http://new.example.com/data/v2?x=1\r\nContent-Length: 0\r\n\r\n")],
+            &[Step::Respond(b"HTTP/1.1 302 Found\r\nLocation: v3\r\nContent-Length: 0\r\n\r\n")],
+            &[Step::Respond(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}")],
+        ];
+        let mut stack = MockStack::new(&scripts).read_size(16);
+        let resolver = StaticResolver::new(&HOSTS);
+        let request = Request::get("http://old.example.com/data?fsym=BTC").unwrap();
+
+        let response = send_request::<_, _, 64>(&mut stack, &resolver, &request, 3).unwrap();
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body_str(), Ok("{}"));
+        assert_eq!(
+            &stack.remotes()[..],
+            &[
+                (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 80),
+                (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 80),
+                (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 80),
+            ]
+        );
+        assert_eq!(
+            &stack.hostnames()[..],
+            ["old.example.com", "new.example.com", "new.example.com"]
+        );
+        assert_eq!(
+            &written_request_lines(&stack)[..],
+            &[
+                "GET /data?fsym=BTC HTTP/1.1",
+                "Host: old.example.com",
+                "GET /data/v2?x=1 HTTP/1.1",
+                "Host: new.example.com",
+                "GET /data/v3 HTTP/1.1",
+                "Host: new.example.com",
+            ]
+        );
+    }
+
+    #[test]
+    fn redirect_method_semantics() {
+        let scripts: [&[Step]; 3] = [
+            &[Step::Respond(
+                b"HTTP/1.1 307 Temporary Redirect\r\nLocation: /b\r\n\r\n",
+            )],
+            &[Step::Respond(
+                b"HTTP/1.1 303 See Other\r\nLocation: /c\r\n\r\n",
+            )],
+            &[Step::Respond(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+            )],
+        ];
+        let mut stack = MockStack::new(&scripts).read_size(64);
+        let resolver = StaticResolver::new(&HOSTS);
+        let request = Request::post("http://old.example.com/a", b"body=1")
+            .unwrap()
+            .header("Content-Type", "application/x-www-form-urlencoded");
+
+        send_request::<_, _, 64>(&mut stack, &resolver, &request, 3).unwrap();
+
+        assert_eq!(
+            &written_request_lines(&stack)[..],
+            &[
+                "POST /a HTTP/1.1",
+                "Host: old.example.com",
+                "POST /b HTTP/1.1",
+                "Host: old.example.com",
+                "GET /c HTTP/1.1",
+                "Host: old.example.com",
+            ]
+        );
+
+        assert_eq!(stack.written_count("body=1"), 2);
+        assert_eq!(stack.written_count("Content-Type"), 2);
+    }
+
+    #[test]
+    fn redirect_limits_and_errors() {
+        let resolver = StaticResolver::new(&HOSTS);
+        let request = Request::get("http://old.example.com/").unwrap();
+        const REDIRECT: &[Step] = &[Step::Respond(
+            b"HTTP/1.1 308 Permanent Redirect\r\nLocation: /next\r\n\r\n",
+        )];
+
+        //Redirects are only followed when asked to
+        let mut stack = MockStack::new(&[REDIRECT]);
+        let response = send_request::<_, _, 64>(&mut stack, &resolver, &request, 0).unwrap();
+        assert_eq!(response.status, 308);
+        assert_eq!(response.headers.get("location"), Some("/next"));
+
+        let mut stack = MockStack::new(&[REDIRECT; 3]);
+        assert_eq!(
+            send_request::<_, _, 64>(&mut stack, &resolver, &request, 2).unwrap_err(),
+            HttpError::TooManyRedirects
+        );
+        assert_eq!(stack.opened(), 3);
+
+        let cases: [(&[u8], HttpError); 3] = [
+            (b"HTTP/1.1 302 Found\r\n\r\n", HttpError::InvalidRedirect),
+            (
+                b"HTTP/1.1 302 Found\r\nLocation: ftp://old.example.com/\r\n\r\n",
+                HttpError::InvalidRedirect,
+            ),
+            (
+                b"HTTP/1.1 302 Found\r\nLocation: https://unknown.example.com/\r\n\r\n",
+                HttpError::UnknownHost,
+            ),
+        ];
+
+        for (response, error) in cases.iter() {
+            let script = [Step::Respond(response)];
+            let scripts: [&[Step]; 1] = [&script];
+            let mut stack = MockStack::new(&scripts);
+            assert_eq!(
+                send_request::<_, _, 64>(&mut stack, &resolver, &request, 3).unwrap_err(),
+                *error
+            );
+        }
+    }
+
+    /// Redirect to `path` on new.example.com
+    fn redirect_to(path: &str) -> Vec<u8, 1024> {
+        let mut redirect = Vec::new();
+        let parts = [
+            "HTTP/1.1 302 Found\r\nLocation: http://new.example.com",
+            path,
+            "\r\nContent-Length: 0\r\n\r\n",
+        ];
+        for part in parts.iter() {
+            redirect.extend_from_slice(part.as_bytes()).unwrap();
+        }
+        redirect
+    }
+
+    #[test]
+    fn redirect_to_long_url() {
+        let resolver = StaticResolver::new(&HOSTS);
+        let request = Request::get("http://old.example.com/").unwrap();
+
+        //Longer than the values of `HttpHeaders`, but not than a URL
+        let mut path = String::<MAX_URL_LENGTH>::from("/data?key=");
+        while path.len() < 300 {
+            path.push('x').unwrap();
+        }
+        let redirect = redirect_to(&path);
+        let scripts: [&[Step]; 2] = [
+            &[Step::Respond(&redirect)],
+            &[Step::Respond(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}",
+            )],
+        ];
+        let mut stack = MockStack::new(&scripts);
+
+        let response = send_request::<_, _, 64>(&mut stack, &resolver, &request, 1).unwrap();
+        assert_eq!(response.body_str(), Ok("{}"));
+        assert_eq!(stack.written_count(&path), 1);
+
+        //Too long for a URL
+        while path.len() < MAX_URL_LENGTH {
+            path.push('x').unwrap();
+        }
+        let redirect = redirect_to(&path);
+        let script = [Step::Respond(&redirect)];
+        let scripts: [&[Step]; 1] = [&script];
+        let mut stack = MockStack::new(&scripts);
+        assert_eq!(
+            send_request::<_, _, 64>(&mut stack, &resolver, &request, 1).unwrap_err(),
+            HttpError::InvalidRedirect
+        );
+    }
 }
@@ -0,0 +1,481 @@
+//! Decoder of `gzip` and `deflate` content codings.
+//! `Inflater` inflates straight into the caller's output buffer, which doubles as the
+//! decompression window, so no memory beyond the decompressor state is needed.
+//! `StreamInflater` passes the data on as it is inflated, keeping only the window.
+
+use miniz_oxide::inflate::core::inflate_flags::{
+    TINFL_FLAG_COMPUTE_ADLER32, TINFL_FLAG_HAS_MORE_INPUT, TINFL_FLAG_PARSE_ZLIB_HEADER,
+    TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF,
+};
+use miniz_oxide::inflate::core::{decompress, DecompressorOxide};
+use miniz_oxide::inflate::TINFLStatus;
+
+/// Longest distance deflate refers back to, the size of the window of `StreamInflater`
+pub const WINDOW_SIZE: usize = 32 * 1024;
+
+/// Gzip header flags, see RFC 1952
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+#[derive(Debug, PartialEq)]
+pub enum InflateError {
+    InvalidHeader,
+    InvalidData,
+    ChecksumMismatch,
+    /// The decompressed data doesn't fit in the output buffer
+    OutputTooSmall,
+    /// There is data after the end of the compressed stream
+    TrailingData,
+    /// The compressed stream ended before it was complete
+    Truncated,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Deflate stream with a gzip header and trailer (RFC 1952)
+    Gzip,
+    /// Deflate stream with a zlib header and trailer (RFC 1950), which is what the HTTP
+    /// `deflate` coding means
+    Zlib,
+}
+
+#[derive(Debug, PartialEq)]
+enum State {
+    /// Fixed part of the gzip header
+    Header,
+    ExtraLength,
+    Extra,
+    Name,
+    Comment,
+    HeaderCrc,
+    Deflate,
+    /// CRC-32 and size of the data, after a gzip deflate stream
+    Trailer,
+    Finished,
+}
+
+pub struct Inflater {
+    format: Format,
+    state: State,
+    decompressor: DecompressorOxide,
+    //Bytes of the current fixed-size field: the header, extra length, header CRC or trailer
+    field: [u8; 10],
+    field_length: usize,
+    flags: u8,
+    extra_remaining: usize,
+    crc: u32,
+    //Length of the decompressed data modulo 2^32, as the gzip trailer stores it
+    size: u32,
+}
+
+impl Inflater {
+    pub fn new(format: Format) -> Self {
+        Inflater {
+            format,
+            state: match format {
+                Format::Gzip => State::Header,
+                Format::Zlib => State::Deflate,
+            },
+            decompressor: DecompressorOxide::new(),
+            field: [0; 10],
+            field_length: 0,
+            flags: 0,
+            extra_remaining: 0,
+            crc: 0,
+            size: 0,
+        }
+    }
+
+    /// Decompresses a piece of the stream.
+    /// # Arguments
+    /// * `input` - Next piece of the compressed stream
+    /// * `output` - Buffer for the whole decompressed data, including what has been
+    ///   decompressed so far
+    /// * `position` - Length of the data already decompressed into `output`, advanced past
+    ///   the newly decompressed data
+    pub fn feed(
+        &mut self,
+        mut input: &[u8],
+        output: &mut [u8],
+        position: &mut usize,
+    ) -> Result<(), InflateError> {
+        while !input.is_empty() {
+            let (consumed, written) = self.step(input, output, *position, false)?;
+            input = &input[consumed..];
+            *position += written;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the whole stream has been decompressed
+    pub fn finish(&self) -> Result<(), InflateError> {
+        match self.state {
+            State::Finished => Ok(()),
+            _ => Err(InflateError::Truncated),
+        }
+    }
+
+    /// Decompresses as much of `input` as fits in `output` after `position`, or takes a byte
+    /// of the gzip header or trailer. Returns the number of bytes taken from `input` and
+    /// written to `output`. If `wrapping`, `output` is a window a power of two long,
+    /// which the caller empties once it is full by starting over at position 0.
+    fn step(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        position: usize,
+        wrapping: bool,
+    ) -> Result<(usize, usize), InflateError> {
+        if self.state == State::Deflate {
+            return self.inflate(input, output, position, wrapping);
+        }
+
+        match input.first() {
+            Some(&byte) => {
+                self.header_byte(byte)?;
+                Ok((1, 0))
+            }
+            None => Ok((0, 0)),
+        }
+    }
+
+    fn inflate(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        position: usize,
+        wrapping: bool,
+    ) -> Result<(usize, usize), InflateError> {
+        let mut flags = TINFL_FLAG_HAS_MORE_INPUT;
+        if !wrapping {
+            flags |= TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+        }
+        if self.format == Format::Zlib {
+            flags |= TINFL_FLAG_PARSE_ZLIB_HEADER | TINFL_FLAG_COMPUTE_ADLER32;
+        }
+
+        let (status, consumed, written) =
+            decompress(&mut self.decompressor, input, output, position, flags);
+
+        self.crc = crc32(self.crc, &output[position..position + written]);
+        self.size = self.size.wrapping_add(written as u32);
+
+        match status {
+            TINFLStatus::Done => {
+                self.state = match self.format {
+                    Format::Gzip => State::Trailer,
+                    Format::Zlib => State::Finished,
+                };
+                Ok((consumed, written))
+            }
+            TINFLStatus::NeedsMoreInput => Ok((consumed, written)),
+            //The window is full, more is inflated into it once it has been passed on
+            TINFLStatus::HasMoreOutput if wrapping => Ok((consumed, written)),
+            TINFLStatus::HasMoreOutput => Err(InflateError::OutputTooSmall),
+            TINFLStatus::Adler32Mismatch => Err(InflateError::ChecksumMismatch),
+            _ => Err(InflateError::InvalidData),
+        }
+    }
+
+    /// Handles a byte of the gzip header or trailer
+    fn header_byte(&mut self, byte: u8) -> Result<(), InflateError> {
+        match self.state {
+            State::Header => {
+                if self.push_field(byte, 10) {
+                    let header = &self.field;
+                    if header[0] != 0x1f || header[1] != 0x8b || header[2] != 8 {
+                        return Err(InflateError::InvalidHeader);
+                    }
+                    self.flags = header[3];
+                    self.next_header_field(State::ExtraLength);
+                }
+            }
+            State::ExtraLength => {
+                if self.push_field(byte, 2) {
+                    self.extra_remaining =
+                        u16::from_le_bytes([self.field[0], self.field[1]]) as usize;
+                    self.next_header_field(State::Extra);
+                }
+            }
+            State::Extra => {
+                self.extra_remaining -= 1;
+                if self.extra_remaining == 0 {
+                    self.next_header_field(State::Name);
+                }
+            }
+            State::Name if byte == 0 => self.next_header_field(State::Comment),
+            State::Comment if byte == 0 => self.next_header_field(State::HeaderCrc),
+            State::Name | State::Comment => {}
+            State::HeaderCrc => {
+                if self.push_field(byte, 2) {
+                    self.state = State::Deflate;
+                }
+            }
+            State::Trailer => {
+                if self.push_field(byte, 8) {
+                    let trailer = &self.field;
+                    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+                    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+
+                    if crc != self.crc || size != self.size {
+                        return Err(InflateError::ChecksumMismatch);
+                    }
+                    self.state = State::Finished;
+                }
+            }
+            State::Finished => return Err(InflateError::TrailingData),
+            State::Deflate => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    /// Collects a byte of a fixed-size field, returns true once the field is complete
+    fn push_field(&mut self, byte: u8, length: usize) -> bool {
+        self.field[self.field_length] = byte;
+        self.field_length += 1;
+
+        if self.field_length == length {
+            self.field_length = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Moves to the first optional header field, starting from `state`, present in the stream
+    fn next_header_field(&mut self, mut state: State) {
+        loop {
+            let present = match state {
+                State::ExtraLength => self.flags & FEXTRA != 0,
+                State::Extra => self.extra_remaining > 0,
+                State::Name => self.flags & FNAME != 0,
+                State::Comment => self.flags & FCOMMENT != 0,
+                State::HeaderCrc => self.flags & FHCRC != 0,
+                _ => true,
+            };
+            if present {
+                break;
+            }
+
+            state = match state {
+                State::ExtraLength => State::Name,
+                State::Extra => State::Name,
+                State::Name => State::Comment,
+                State::Comment => State::HeaderCrc,
+                _ => State::Deflate,
+            };
+        }
+
+        self.state = state;
+    }
+}
+
+/// Inflates a stream without keeping all of the decompressed data. It is passed on piece by
+/// piece, only the last `WINDOW_SIZE` bytes, which the stream may refer back to, are kept.
+pub struct StreamInflater {
+    inflater: Inflater,
+    window: [u8; WINDOW_SIZE],
+    //Where the next decompressed byte goes in the window
+    position: usize,
+}
+
+impl StreamInflater {
+    pub fn new(format: Format) -> Self {
+        StreamInflater {
+            inflater: Inflater::new(format),
+            window: [0; WINDOW_SIZE],
+            position: 0,
+        }
+    }
+
+    /// Decompresses a piece of the stream, passing the decompressed data to `output`.
+    /// Stops at the first error, including the ones returned by `output`.
+    pub fn feed<ErrorT: From<InflateError>>(
+        &mut self,
+        mut input: &[u8],
+        mut output: impl FnMut(&[u8]) -> Result<(), ErrorT>,
+    ) -> Result<(), ErrorT> {
+        loop {
+            let (consumed, written) =
+                self.inflater
+                    .step(input, &mut self.window, self.position, true)?;
+            input = &input[consumed..];
+
+            if written > 0 {
+                output(&self.window[self.position..self.position + written])?;
+            }
+            self.position = (self.position + written) % WINDOW_SIZE;
+
+            //Once the input is used up, the window may still have been too full for all of it
+            if input.is_empty() && written == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Checks that the whole stream has been decompressed
+    pub fn finish(&self) -> Result<(), InflateError> {
+        self.inflater.finish()
+    }
+}
+
+/// Nibble-wise lookup table of the CRC-32 used by gzip
+const CRC32_TABLE: [u32; 16] = [
+    0x00000000, 0x1db71064, 0x3b6e20c8, 0x26d930ac, 0x76dc4190, 0x6b6b51f4, 0x4db26158, 0x5005713c,
+    0xedb88320, 0xf00f9344, 0xd6d6a3e8, 0xcb61b38c, 0x9b64c2b0, 0x86d3d2d4, 0xa00ae278, 0xbdbdf21c,
+];
+
+/// Continues the CRC-32 `crc` of the preceding data over `data`
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+
+    for &byte in data {
+        crc = (crc >> 4) ^ CRC32_TABLE[((crc ^ byte as u32) & 0xf) as usize];
+        crc = (crc >> 4) ^ CRC32_TABLE[((crc ^ (byte as u32 >> 4)) & 0xf) as usize];
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) const DECOMPRESSED: &[u8] = b"{\"BTC\":{\"USD\":35000.1},\"ETH\":{\"USD\":2000},\"LTC\":{\"USD\":150.5},\"XRP\":{\"USD\":0.5},\"ADA\":{\"USD\":1.2},\"DOT\":{\"USD\":20}}";
+
+    pub(crate) const GZIP: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xab\x56\x72\x0a\x71\x56\xb2\xaa\x56\x0a\x0d\x76\x51\xb2\x32\x36\x35\x30\x30\xd0\x33\xac\xd5\x51\x72\x0d\xf1\x80\x8b\x1a\x01\x05\x81\x42\x3e\x48\x0a\x0d\x4d\x0d\xf4\x4c\x81\x62\x11\x41\x01\x70\x31\x88\x88\xa3\x8b\x23\x42\x95\x9e\x11\x50\xc4\xc5\x3f\x04\xc9\xa8\xda\x5a\x00\x8d\x2c\x6d\x9f\x73\x00\x00\x00";
+
+    const ZLIB: &[u8] = b"\x78\x9c\xab\x56\x72\x0a\x71\x56\xb2\xaa\x56\x0a\x0d\x76\x51\xb2\x32\x36\x35\x30\x30\xd0\x33\xac\xd5\x51\x72\x0d\xf1\x80\x8b\x1a\x01\x05\x81\x42\x3e\x48\x0a\x0d\x4d\x0d\xf4\x4c\x81\x62\x11\x41\x01\x70\x31\x88\x88\xa3\x8b\x23\x42\x95\x9e\x11\x50\xc4\xc5\x3f\x04\xc9\xa8\xda\x5a\x00\x7a\xad\x1c\xf1";
+
+    fn inflate_in_pieces(
+        format: Format,
+        input: &[u8],
+        piece_size: usize,
+        output: &mut [u8],
+    ) -> Result<usize, InflateError> {
+        let mut inflater = Inflater::new(format);
+        let mut position = 0;
+
+        for piece in input.chunks(piece_size) {
+            inflater.feed(piece, output, &mut position)?;
+        }
+        inflater.finish()?;
+
+        Ok(position)
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(0, b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn inflate_with_every_piece_size() {
+        for &(format, input) in [(Format::Gzip, GZIP), (Format::Zlib, ZLIB)].iter() {
+            for piece_size in 1..=input.len() {
+                let mut output = [0; 256];
+                let length = inflate_in_pieces(format, input, piece_size, &mut output).unwrap();
+
+                assert_eq!(
+                    &output[..length],
+                    DECOMPRESSED,
+                    "{:?} {}",
+                    format,
+                    piece_size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn optional_gzip_header_fields() {
+        //FEXTRA, FNAME and FCOMMENT set, then the stream of an empty file
+        let input = b"\x1f\x8b\x08\x1c\x00\x00\x00\x00\x00\x03\x03\x00ab\x01prices.json\x00hi\x00\x03\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+
+        for piece_size in 1..=input.len() {
+            let mut output = [0; 16];
+            assert_eq!(
+                inflate_in_pieces(Format::Gzip, input, piece_size, &mut output),
+                Ok(0)
+            );
+        }
+    }
+
+    /// `LONG_PART` 5000 times, much longer than the window
+    const LONG_GZIP: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xed\xc8\xb1\x09\x80\x30\x10\x00\xc0\x5d\xbe\x16\x79\x11\x9b\x94\x26\x1b\xa8\xd3\x3c\xd9\x3d\xe0\x1c\x77\xe5\x55\xdc\x6f\x8f\x56\xf1\x3d\x23\xda\x79\x65\xe6\x7e\xcc\xb9\x95\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xf7\xde\x7b\xef\xbd\xff\x7f\x01\x9f\x30\xa5\x82\xc0\xd4\x01\x00";
+    const LONG_PART: &[u8] = b"{\"BTC\":{\"USD\":35000.1}},";
+
+    #[test]
+    fn stream_longer_than_the_window() {
+        for &piece_size in [1, 7, 100, LONG_GZIP.len()].iter() {
+            let mut inflater = StreamInflater::new(Format::Gzip);
+            let mut length = 0;
+
+            for piece in LONG_GZIP.chunks(piece_size) {
+                inflater
+                    .feed(piece, |data| {
+                        for &byte in data {
+                            if byte != LONG_PART[length % LONG_PART.len()] {
+                                return Err(InflateError::InvalidData);
+                            }
+                            length += 1;
+                        }
+                        Ok(())
+                    })
+                    .unwrap();
+            }
+            inflater.finish().unwrap();
+
+            assert_eq!(length, LONG_PART.len() * 5000, "{}", piece_size);
+        }
+
+        //The output stops the stream
+        let mut inflater = StreamInflater::new(Format::Gzip);
+        assert_eq!(
+            inflater.feed(LONG_GZIP, |_| Err(InflateError::TrailingData)),
+            Err(InflateError::TrailingData)
+        );
+    }
+
+    #[test]
+    fn invalid_streams() {
+        let mut corrupted_crc = [0; 86];
+        corrupted_crc.copy_from_slice(GZIP);
+        corrupted_crc[79] ^= 1;
+
+        let mut trailing = [0; 87];
+        trailing[..86].copy_from_slice(GZIP);
+
+        let cases: [(Format, &[u8], InflateError); 6] = [
+            (
+                Format::Gzip,
+                b"\x1f\x8c\x08\x00\x00\x00\x00\x00\x02\x03",
+                InflateError::InvalidHeader,
+            ),
+            (Format::Gzip, &corrupted_crc, InflateError::ChecksumMismatch),
+            (Format::Gzip, &trailing, InflateError::TrailingData),
+            (Format::Gzip, &GZIP[..60], InflateError::Truncated),
+            (Format::Zlib, &ZLIB[..73], InflateError::Truncated),
+            (Format::Zlib, b"\x78\x9c\xff\xff", InflateError::InvalidData),
+        ];
+
+        for (format, input, error) in cases.iter() {
+            let mut output = [0; 256];
+            assert_eq!(
+                inflate_in_pieces(*format, input, 7, &mut output).unwrap_err(),
+                *error
+            );
+        }
+
+        let mut output = [0; 64];
+        assert_eq!(
+            inflate_in_pieces(Format::Gzip, GZIP, 7, &mut output).unwrap_err(),
+            InflateError::OutputTooSmall
+        );
+    }
+}
@@ -0,0 +1,843 @@
+//! Pull-based JSON tokenizer for documents that arrive in pieces, such as bodies read from
+//! a socket. Only the token being read is buffered, so memory use doesn't depend on the size
+//! of the document.
+
+use heapless::{String, Vec};
+
+/// Deepest nesting of objects and arrays the tokenizer accepts
+pub const MAX_NESTING: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JsonError {
+    /// A byte that can't appear at its position in the document
+    Syntax,
+    InvalidEscape,
+    /// Strings must be valid UTF-8
+    InvalidUtf8,
+    /// Numbers, unlike strings, can't be cut short, so a longer one is an error
+    NumberTooLong,
+    TooDeep,
+    /// The document ended before it was complete
+    Truncated,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Token<'a> {
+    BeginObject,
+    EndObject,
+    BeginArray,
+    EndArray,
+    /// Name of an object member, `None` if it is longer than the tokenizer's buffer
+    Key(Option<&'a str>),
+    /// Unescaped string value, `None` if it is longer than the tokenizer's buffer
+    String(Option<&'a str>),
+    /// Number as written in the document
+    Number(&'a str),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    BeginObject,
+    EndObject,
+    BeginArray,
+    EndArray,
+    Key,
+    String,
+    Number,
+    True,
+    False,
+    Null,
+}
+
+/// What may come next, outside of tokens
+#[derive(Clone, Copy, PartialEq)]
+enum Expect {
+    Value,
+    /// Value or the end of an empty array
+    FirstValue,
+    Key,
+    /// Key or the end of an empty object
+    FirstKey,
+    Colon,
+    CommaOrEnd,
+    /// The whole document has been read
+    Done,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Number {
+    Minus,
+    Zero,
+    Integer,
+    Point,
+    Fraction,
+    Exponent,
+    ExponentSign,
+    ExponentDigits,
+}
+
+impl Number {
+    fn next(self, byte: u8) -> Option<Number> {
+        match (self, byte) {
+            (Number::Minus, b'0') => Some(Number::Zero),
+            (Number::Minus, b'1'..=b'9') => Some(Number::Integer),
+            (Number::Integer, b'0'..=b'9') => Some(Number::Integer),
+            (Number::Zero, b'.') | (Number::Integer, b'.') => Some(Number::Point),
+            (Number::Point, b'0'..=b'9') | (Number::Fraction, b'0'..=b'9') => {
+                Some(Number::Fraction)
+            }
+            (Number::Zero, b'e')
+            | (Number::Zero, b'E')
+            | (Number::Integer, b'e')
+            | (Number::Integer, b'E')
+            | (Number::Fraction, b'e')
+            | (Number::Fraction, b'E') => Some(Number::Exponent),
+            (Number::Exponent, b'+') | (Number::Exponent, b'-') => Some(Number::ExponentSign),
+            (Number::Exponent, b'0'..=b'9')
+            | (Number::ExponentSign, b'0'..=b'9')
+            | (Number::ExponentDigits, b'0'..=b'9') => Some(Number::ExponentDigits),
+            _ => None,
+        }
+    }
+
+    fn is_complete(self) -> bool {
+        matches!(
+            self,
+            Number::Zero | Number::Integer | Number::Fraction | Number::ExponentDigits
+        )
+    }
+}
+
+/// Token being read
+#[derive(Clone, Copy)]
+enum Lexeme {
+    None,
+    String { key: bool },
+    Escape { key: bool },
+    Unicode { key: bool, digits: u8, code: u16 },
+    Number(Number),
+    Literal { text: &'static [u8], matched: usize },
+}
+
+/// Tokenizer buffering strings and numbers of up to `MAX_TOKEN_LENGTH` bytes
+pub struct Tokenizer<const MAX_TOKEN_LENGTH: usize> {
+    expect: Expect,
+    lexeme: Lexeme,
+    /// Bit n is set if the container at depth n + 1 is an object
+    objects: u32,
+    depth: usize,
+    buffer: Vec<u8, MAX_TOKEN_LENGTH>,
+    overlong: bool,
+    /// First half of a surrogate pair written as `\uXXXX\uXXXX`
+    high_surrogate: Option<u16>,
+    /// The buffer holds a token that has been returned
+    returned: bool,
+    offset: usize,
+}
+
+impl<const MAX_TOKEN_LENGTH: usize> Default for Tokenizer<MAX_TOKEN_LENGTH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const MAX_TOKEN_LENGTH: usize> Tokenizer<MAX_TOKEN_LENGTH> {
+    pub fn new() -> Self {
+        Tokenizer {
+            expect: Expect::Value,
+            lexeme: Lexeme::None,
+            objects: 0,
+            depth: 0,
+            buffer: Vec::new(),
+            overlong: false,
+            high_surrogate: None,
+            returned: false,
+            offset: 0,
+        }
+    }
+
+    /// Reads the next token from `input`, which is advanced past the bytes consumed.
+    /// Returns `None` once `input` is used up without completing a token.
+    pub fn next_token(&mut self, input: &mut &[u8]) -> Result<Option<Token<'_>>, JsonError> {
+        if self.returned {
+            self.buffer.clear();
+            self.overlong = false;
+            self.returned = false;
+        }
+
+        let kind = self.advance(input)?;
+        Ok(kind.map(move |kind| self.token(kind)))
+    }
+
+    /// Checks that the document is complete. Returns the last token if the document is a
+    /// single number, whose end isn't known until the input ends.
+    pub fn finish(&mut self) -> Result<Option<Token<'_>>, JsonError> {
+        match self.lexeme {
+            Lexeme::Number(number) if number.is_complete() && self.depth == 0 => {
+                self.lexeme = Lexeme::None;
+                self.expect = Expect::Done;
+                self.returned = true;
+                Ok(Some(self.token(Kind::Number)))
+            }
+            Lexeme::None if self.expect == Expect::Done => Ok(None),
+            _ => Err(JsonError::Truncated),
+        }
+    }
+
+    /// Number of bytes of the document read so far. After an error, the byte that caused it
+    /// is the last one read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Depth of nesting in objects and arrays
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn token(&self, kind: Kind) -> Token<'_> {
+        //The content of strings has been checked to be valid UTF-8
+        let text = || core::str::from_utf8(&self.buffer).unwrap_or("");
+        let string = || if self.overlong { None } else { Some(text()) };
+
+        match kind {
+            Kind::BeginObject => Token::BeginObject,
+            Kind::EndObject => Token::EndObject,
+            Kind::BeginArray => Token::BeginArray,
+            Kind::EndArray => Token::EndArray,
+            Kind::Key => Token::Key(string()),
+            Kind::String => Token::String(string()),
+            Kind::Number => Token::Number(text()),
+            Kind::True => Token::Bool(true),
+            Kind::False => Token::Bool(false),
+            Kind::Null => Token::Null,
+        }
+    }
+
+    fn advance(&mut self, input: &mut &[u8]) -> Result<Option<Kind>, JsonError> {
+        while let Some((&byte, rest)) = input.split_first() {
+            //A number ends at the first byte that isn't part of it, which is left in the input
+            if let Lexeme::Number(number) = self.lexeme {
+                match number.next(byte) {
+                    Some(next) => {
+                        self.buffer
+                            .push(byte)
+                            .map_err(|_| JsonError::NumberTooLong)?;
+                        self.lexeme = Lexeme::Number(next);
+                        *input = rest;
+                        self.offset += 1;
+                        continue;
+                    }
+                    None if number.is_complete() => {
+                        self.lexeme = Lexeme::None;
+                        self.value_read();
+                        return Ok(Some(self.returned(Kind::Number)));
+                    }
+                    None => return Err(JsonError::Syntax),
+                }
+            }
+
+            *input = rest;
+            self.offset += 1;
+            if let Some(kind) = self.read_byte(byte)? {
+                return Ok(Some(self.returned(kind)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn returned(&mut self, kind: Kind) -> Kind {
+        self.returned = true;
+        kind
+    }
+
+    /// Handles a byte of the document, returning the token it completes
+    fn read_byte(&mut self, byte: u8) -> Result<Option<Kind>, JsonError> {
+        match self.lexeme {
+            Lexeme::None => self.structure(byte),
+            Lexeme::String { key } => {
+                if self.high_surrogate.is_some() && byte != b'\\' {
+                    return Err(JsonError::InvalidEscape);
+                }
+
+                match byte {
+                    b'"' => {
+                        if !self.overlong && core::str::from_utf8(&self.buffer).is_err() {
+                            return Err(JsonError::InvalidUtf8);
+                        }
+
+                        self.lexeme = Lexeme::None;
+                        if key {
+                            self.expect = Expect::Colon;
+                            Ok(Some(Kind::Key))
+                        } else {
+                            self.value_read();
+                            Ok(Some(Kind::String))
+                        }
+                    }
+                    b'\\' => {
+                        self.lexeme = Lexeme::Escape { key };
+                        Ok(None)
+                    }
+                    0..=0x1f => Err(JsonError::Syntax),
+                    _ => {
+                        self.push(&[byte]);
+                        Ok(None)
+                    }
+                }
+            }
+            Lexeme::Escape { key } => {
+                let unescaped = match byte {
+                    b'u' => {
+                        self.lexeme = Lexeme::Unicode {
+                            key,
+                            digits: 0,
+                            code: 0,
+                        };
+                        return Ok(None);
+                    }
+                    _ if self.high_surrogate.is_some() => return Err(JsonError::InvalidEscape),
+                    b'"' | b'\\' | b'/' => byte,
+                    b'b' => 0x08,
+                    b'f' => 0x0c,
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    _ => return Err(JsonError::InvalidEscape),
+                };
+
+                self.push(&[unescaped]);
+                self.lexeme = Lexeme::String { key };
+                Ok(None)
+            }
+            Lexeme::Unicode { key, digits, code } => {
+                let digit = (byte as char)
+                    .to_digit(16)
+                    .ok_or(JsonError::InvalidEscape)? as u16;
+                let code = code << 4 | digit;
+
+                if digits < 3 {
+                    self.lexeme = Lexeme::Unicode {
+                        key,
+                        digits: digits + 1,
+                        code,
+                    };
+                    return Ok(None);
+                }
+
+                self.unicode_escape(code)?;
+                self.lexeme = Lexeme::String { key };
+                Ok(None)
+            }
+            Lexeme::Literal { text, matched } => {
+                if byte != text[matched] {
+                    return Err(JsonError::Syntax);
+                }
+                if matched + 1 < text.len() {
+                    self.lexeme = Lexeme::Literal {
+                        text,
+                        matched: matched + 1,
+                    };
+                    return Ok(None);
+                }
+
+                self.lexeme = Lexeme::None;
+                self.value_read();
+                Ok(Some(match text[0] {
+                    b't' => Kind::True,
+                    b'f' => Kind::False,
+                    _ => Kind::Null,
+                }))
+            }
+            Lexeme::Number(_) => unreachable!(),
+        }
+    }
+
+    /// Handles a byte between tokens
+    fn structure(&mut self, byte: u8) -> Result<Option<Kind>, JsonError> {
+        if matches!(byte, b' ' | b'\t' | b'\n' | b'\r') {
+            return Ok(None);
+        }
+
+        match (self.expect, byte) {
+            (Expect::Value, _) | (Expect::FirstValue, _) if byte != b']' => self.value(byte),
+            (Expect::FirstValue, b']') => self.close(false).map(|_| Some(Kind::EndArray)),
+            (Expect::Key, b'"') | (Expect::FirstKey, b'"') => {
+                self.lexeme = Lexeme::String { key: true };
+                Ok(None)
+            }
+            (Expect::FirstKey, b'}') => self.close(true).map(|_| Some(Kind::EndObject)),
+            (Expect::Colon, b':') => {
+                self.expect = Expect::Value;
+                Ok(None)
+            }
+            (Expect::CommaOrEnd, b',') => {
+                self.expect = if self.in_object() {
+                    Expect::Key
+                } else {
+                    Expect::Value
+                };
+                Ok(None)
+            }
+            (Expect::CommaOrEnd, b'}') => self.close(true).map(|_| Some(Kind::EndObject)),
+            (Expect::CommaOrEnd, b']') => self.close(false).map(|_| Some(Kind::EndArray)),
+            _ => Err(JsonError::Syntax),
+        }
+    }
+
+    /// Handles the first byte of a value
+    fn value(&mut self, byte: u8) -> Result<Option<Kind>, JsonError> {
+        match byte {
+            b'{' | b'[' => {
+                if self.depth == MAX_NESTING {
+                    return Err(JsonError::TooDeep);
+                }
+
+                let object = byte == b'{';
+                self.objects = (self.objects & !(1 << self.depth)) | (object as u32) << self.depth;
+                self.depth += 1;
+
+                if object {
+                    self.expect = Expect::FirstKey;
+                    return Ok(Some(Kind::BeginObject));
+                }
+                self.expect = Expect::FirstValue;
+                return Ok(Some(Kind::BeginArray));
+            }
+            b'"' => self.lexeme = Lexeme::String { key: false },
+            b'-' => self.lexeme = Lexeme::Number(Number::Minus),
+            b'0' => self.lexeme = Lexeme::Number(Number::Zero),
+            b'1'..=b'9' => self.lexeme = Lexeme::Number(Number::Integer),
+            b't' | b'f' | b'n' => {
+                let text: &'static [u8] = match byte {
+                    b't' => b"true",
+                    b'f' => b"false",
+                    _ => b"null",
+                };
+                self.lexeme = Lexeme::Literal { text, matched: 1 };
+            }
+            _ => return Err(JsonError::Syntax),
+        }
+
+        if let Lexeme::Number(_) = self.lexeme {
+            self.buffer
+                .push(byte)
+                .map_err(|_| JsonError::NumberTooLong)?;
+        }
+        Ok(None)
+    }
+
+    fn close(&mut self, object: bool) -> Result<(), JsonError> {
+        if self.depth == 0 || self.in_object() != object {
+            return Err(JsonError::Syntax);
+        }
+
+        self.depth -= 1;
+        self.value_read();
+        Ok(())
+    }
+
+    fn in_object(&self) -> bool {
+        self.depth > 0 && (self.objects & 1 << (self.depth - 1)) != 0
+    }
+
+    fn value_read(&mut self) {
+        self.expect = if self.depth == 0 {
+            Expect::Done
+        } else {
+            Expect::CommaOrEnd
+        };
+    }
+
+    fn unicode_escape(&mut self, code: u16) -> Result<(), JsonError> {
+        let character = match (self.high_surrogate.take(), code) {
+            (Some(high), 0xdc00..=0xdfff) => {
+                let code = 0x10000 + ((high as u32 - 0xd800) << 10) + (code as u32 - 0xdc00);
+                core::char::from_u32(code)
+            }
+            (Some(_), _) | (None, 0xdc00..=0xdfff) => None,
+            (None, 0xd800..=0xdbff) => {
+                self.high_surrogate = Some(code);
+                return Ok(());
+            }
+            (None, code) => core::char::from_u32(code as u32),
+        };
+
+        let character = character.ok_or(JsonError::InvalidEscape)?;
+        self.push(character.encode_utf8(&mut [0; 4]).as_bytes());
+        Ok(())
+    }
+
+    /// Appends to the string being read, dropping the rest of it once the buffer is full
+    fn push(&mut self, bytes: &[u8]) {
+        if self.overlong || self.buffer.extend_from_slice(bytes).is_err() {
+            self.overlong = true;
+        }
+    }
+}
+
+/// Step from a container to one of its values
+#[derive(Debug)]
+enum Segment<const MAX_KEY_LENGTH: usize> {
+    /// Member of an object, `None` before the first key and for keys too long to keep
+    Key(Option<String<MAX_KEY_LENGTH>>),
+    /// Element of an array, `None` before the first element
+    Index(Option<usize>),
+}
+
+/// Keys and indices leading from the root of the document to a value
+#[derive(Debug)]
+pub struct Path<const MAX_KEY_LENGTH: usize, const MAX_DEPTH: usize> {
+    segments: Vec<Segment<MAX_KEY_LENGTH>, MAX_DEPTH>,
+    /// Levels of nesting below `MAX_DEPTH`, whose values aren't reported
+    hidden: usize,
+}
+
+impl<const MAX_KEY_LENGTH: usize, const MAX_DEPTH: usize> Path<MAX_KEY_LENGTH, MAX_DEPTH> {
+    fn new() -> Self {
+        Path {
+            segments: Vec::new(),
+            hidden: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Key at `level`, `None` if it's an array index or a key that was too long to keep
+    pub fn key(&self, level: usize) -> Option<&str> {
+        match self.segments.get(level) {
+            Some(Segment::Key(Some(key))) => Some(key),
+            _ => None,
+        }
+    }
+
+    /// Array index at `level`
+    pub fn index(&self, level: usize) -> Option<usize> {
+        match self.segments.get(level) {
+            Some(Segment::Index(index)) => *index,
+            _ => None,
+        }
+    }
+
+    /// Checks if the path consists of the keys in `pattern`, where `*` stands for any key
+    /// or index
+    pub fn matches(&self, pattern: &[&str]) -> bool {
+        pattern.len() == self.len()
+            && pattern
+                .iter()
+                .enumerate()
+                .all(|(level, &expected)| expected == "*" || self.key(level) == Some(expected))
+    }
+
+    fn begin_value(&mut self) {
+        if let (0, Some(Segment::Index(index))) = (self.hidden, self.segments.last_mut()) {
+            *index = Some(index.map_or(0, |index| index + 1));
+        }
+    }
+
+    fn push(&mut self, segment: Segment<MAX_KEY_LENGTH>) {
+        if self.hidden > 0 || self.segments.push(segment).is_err() {
+            self.hidden += 1;
+        }
+    }
+
+    fn pop(&mut self) {
+        if self.hidden > 0 {
+            self.hidden -= 1;
+        } else {
+            self.segments.pop();
+        }
+    }
+
+    fn set_key(&mut self, key: Option<&str>) {
+        if self.hidden > 0 {
+            return;
+        }
+
+        if let Some(Segment::Key(last)) = self.segments.last_mut() {
+            *last = key.and_then(|key| {
+                let mut owned = String::new();
+                owned.push_str(key).ok().map(|_| owned)
+            });
+        }
+    }
+}
+
+/// Tokenizer keeping track of the path to each value of the document.
+/// Keys are kept for up to `MAX_DEPTH` levels, values nested deeper aren't reported.
+pub struct JsonReader<const MAX_TOKEN_LENGTH: usize, const MAX_DEPTH: usize> {
+    tokenizer: Tokenizer<MAX_TOKEN_LENGTH>,
+    path: Path<MAX_TOKEN_LENGTH, MAX_DEPTH>,
+}
+
+impl<const MAX_TOKEN_LENGTH: usize, const MAX_DEPTH: usize> Default
+    for JsonReader<MAX_TOKEN_LENGTH, MAX_DEPTH>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const MAX_TOKEN_LENGTH: usize, const MAX_DEPTH: usize>
+    JsonReader<MAX_TOKEN_LENGTH, MAX_DEPTH>
+{
+    pub fn new() -> Self {
+        JsonReader {
+            tokenizer: Tokenizer::new(),
+            path: Path::new(),
+        }
+    }
+
+    /// Reads the next piece of the document, calling `on_value` with every string, number,
+    /// boolean and null in it, along with its path. Stops at the first error, including
+    /// the ones returned by `on_value`.
+    pub fn feed<E: From<JsonError>>(
+        &mut self,
+        mut input: &[u8],
+        mut on_value: impl FnMut(&Path<MAX_TOKEN_LENGTH, MAX_DEPTH>, Token) -> Result<(), E>,
+    ) -> Result<(), E> {
+        while let Some(token) = self.tokenizer.next_token(&mut input)? {
+            Self::visit(&mut self.path, token, &mut on_value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the document is complete
+    pub fn finish<E: From<JsonError>>(
+        &mut self,
+        mut on_value: impl FnMut(&Path<MAX_TOKEN_LENGTH, MAX_DEPTH>, Token) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if let Some(token) = self.tokenizer.finish()? {
+            Self::visit(&mut self.path, token, &mut on_value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Number of bytes of the document read so far
+    pub fn offset(&self) -> usize {
+        self.tokenizer.offset()
+    }
+
+    fn visit<E>(
+        path: &mut Path<MAX_TOKEN_LENGTH, MAX_DEPTH>,
+        token: Token,
+        on_value: &mut impl FnMut(&Path<MAX_TOKEN_LENGTH, MAX_DEPTH>, Token) -> Result<(), E>,
+    ) -> Result<(), E> {
+        match token {
+            Token::BeginObject => {
+                path.begin_value();
+                path.push(Segment::Key(None));
+            }
+            Token::BeginArray => {
+                path.begin_value();
+                path.push(Segment::Index(None));
+            }
+            Token::EndObject | Token::EndArray => path.pop(),
+            Token::Key(key) => path.set_key(key),
+            value => {
+                path.begin_value();
+                if path.hidden == 0 {
+                    return on_value(path, value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tokens of `document` read in pieces of `piece_size`, written out one per line
+    fn tokenize<const SIZE: usize>(
+        document: &[u8],
+        piece_size: usize,
+    ) -> Result<String<1024>, JsonError> {
+        let mut tokenizer = Tokenizer::<SIZE>::new();
+        let mut output = String::new();
+
+        let mut write = |token: Token| {
+            core::fmt::write(&mut output, format_args!("{:?}\n", token)).unwrap();
+        };
+
+        for mut piece in document.chunks(piece_size) {
+            while let Some(token) = tokenizer.next_token(&mut piece)? {
+                write(token);
+            }
+        }
+        if let Some(token) = tokenizer.finish()? {
+            write(token);
+        }
+
+        Ok(output)
+    }
+
+    #[test]
+    fn tokens_in_every_piece_size() {
+        let document = " {\"a\\u0142\\n\": [1, -0.5e+3, 0, true, false, null, {}, []], \"b\": \"x\\\"\\ud83d\\ude00\"} ";
+        let expected = "BeginObject\n\
+             Key(Some(\"ał\\n\"))\n\
+             BeginArray\n\
+             Number(\"1\")\n\
+             Number(\"-0.5e+3\")\n\
+             Number(\"0\")\n\
+             Bool(true)\n\
+             Bool(false)\n\
+             Null\n\
+             BeginObject\n\
+             EndObject\n\
+             BeginArray\n\
+             EndArray\n\
+             EndArray\n\
+             Key(Some(\"b\"))\n\
+             String(Some(\"x\\\"😀\"))\n\
+             EndObject\n";
+
+        for piece_size in 1..=document.len() {
+            assert_eq!(
+                tokenize::<16>(document.as_bytes(), piece_size).unwrap(),
+                expected,
+                "{}",
+                piece_size
+            );
+        }
+    }
+
+    #[test]
+    fn long_strings_and_scalar_documents() {
+        assert_eq!(
+            tokenize::<4>(b"{\"long key\":\"long value\",\"k\":\"v\"}", 3).unwrap(),
+            "BeginObject\nKey(None)\nString(None)\nKey(Some(\"k\"))\nString(Some(\"v\"))\nEndObject\n"
+        );
+        assert_eq!(tokenize::<8>(b" 12.5", 2).unwrap(), "Number(\"12.5\")\n");
+        assert_eq!(
+            tokenize::<8>(b"\"s\" ", 2).unwrap(),
+            "String(Some(\"s\"))\n"
+        );
+    }
+
+    #[test]
+    fn invalid_documents() {
+        let cases: [(&[u8], JsonError); 16] = [
+            (b"{\"a\" 1}", JsonError::Syntax),
+            (b"{\"a\":1,}", JsonError::Syntax),
+            (b"[1,]", JsonError::Syntax),
+            (b"[1}", JsonError::Syntax),
+            (b"{}}", JsonError::Syntax),
+            (b"{} {}", JsonError::Syntax),
+            (b"[01]", JsonError::Syntax),
+            (b"[1.]", JsonError::Syntax),
+            (b"[tru]", JsonError::Syntax),
+            (b"[\"a\nb\"]", JsonError::Syntax),
+            (b"[\"\\x\"]", JsonError::InvalidEscape),
+            (b"[\"\\ud83d\"]", JsonError::InvalidEscape),
+            (b"[\"\xff\"]", JsonError::InvalidUtf8),
+            (b"[123456789]", JsonError::NumberTooLong),
+            (b"{\"a\":[1", JsonError::Truncated),
+            (b"", JsonError::Truncated),
+        ];
+
+        for (document, error) in cases.iter() {
+            assert_eq!(tokenize::<8>(document, 3), Err(*error), "{:?}", document);
+        }
+
+        let mut deep = [b'['; MAX_NESTING + 1];
+        assert_eq!(tokenize::<8>(&deep, 3), Err(JsonError::TooDeep));
+        deep[MAX_NESTING] = b']';
+        assert_eq!(tokenize::<8>(&deep, 3), Err(JsonError::Truncated));
+    }
+
+    #[test]
+    fn errors_stop_reading_at_their_offset() {
+        let mut reader = JsonReader::<8, 2>::new();
+        assert_eq!(
+            reader.feed(b"[1, 2, x]", |_, _| Ok::<(), JsonError>(())),
+            Err(JsonError::Syntax)
+        );
+        assert_eq!(reader.offset(), 8);
+
+        #[derive(Debug, PartialEq)]
+        enum Stop {
+            Json(JsonError),
+            Rejected,
+        }
+
+        impl From<JsonError> for Stop {
+            fn from(error: JsonError) -> Self {
+                Stop::Json(error)
+            }
+        }
+
+        let mut reader = JsonReader::<8, 2>::new();
+        let mut values = 0;
+        let result = reader.feed(b"[true, \"no\", 3]", |_, token| {
+            values += 1;
+            match token {
+                Token::String(_) => Err(Stop::Rejected),
+                _ => Ok(()),
+            }
+        });
+
+        assert_eq!(result, Err(Stop::Rejected));
+        assert_eq!(values, 2);
+        assert_eq!(reader.offset(), 11);
+    }
+
+    #[test]
+    fn values_with_paths() {
+        let document = b"{\"RAW\":{\"BTC\":{\"USD\":{\"PRICE\":35000.1,\"OPENDAY\":34000}},\"ETH\":{\"USD\":{\"OPENDAY\":2000}}},\"list\":[[\"a\",\"b\"],{\"c\":true}],\"deep\":{\"x\":{\"y\":{\"z\":{\"w\":1}}}}}";
+
+        for piece_size in 1..=document.len() {
+            let mut reader = JsonReader::<16, 4>::new();
+            let mut output = String::<512>::new();
+            let mut on_value = |path: &Path<16, 4>, token: Token| {
+                for level in 0..path.len() {
+                    match (path.key(level), path.index(level)) {
+                        (Some(key), _) => output.push_str(key).unwrap(),
+                        (None, Some(index)) => {
+                            core::fmt::write(&mut output, format_args!("{}", index)).unwrap()
+                        }
+                        (None, None) => output.push('?').unwrap(),
+                    }
+                    output.push('.').unwrap();
+                }
+                core::fmt::write(&mut output, format_args!(" {:?}\n", token)).unwrap();
+
+                if path.matches(&["RAW", "*", "USD", "OPENDAY"]) {
+                    output.push_str("^ matches\n").unwrap();
+                }
+                Ok::<(), JsonError>(())
+            };
+
+            for piece in document.chunks(piece_size) {
+                reader.feed(piece, &mut on_value).unwrap();
+            }
+            reader.finish(&mut on_value).unwrap();
+
+            assert_eq!(
+                output,
+                "RAW.BTC.USD.PRICE. Number(\"35000.1\")\n\
+                 RAW.BTC.USD.OPENDAY. Number(\"34000\")\n\
+                 ^ matches\n\
+                 RAW.ETH.USD.OPENDAY. Number(\"2000\")\n\
+                 ^ matches\n\
+                 list.0.0. String(Some(\"a\"))\n\
+                 list.0.1. String(Some(\"b\"))\n\
+                 list.1.c. Bool(true)\n"
+            );
+        }
+    }
+}
@@ -0,0 +1,744 @@
+use crate::{
+    client::HttpClient,
+    crypto_api_client::{
+        self, insert_quote, is_concatenation, parse_time, update_stats, CandleReader,
+        CryptoApiClient, CryptoApiError, History, Interval, MarketStatsMap, PairName,
+        ParseErrorKind, Prices, UnknownPairs, MAX_CANDLES,
+    },
+    json::{Path, Token},
+    request::Request,
+    resolver::StaticResolver,
+};
+use core::ops::Range;
+use drogue_network::{addr::Ipv4Addr, tcp::TcpStack};
+use heapless::{String, Vec};
+
+const NAME: &str = "Kraken";
+
+/// There is no DNS on the device, the API host is resolved from this table.
+/// The host is behind a CDN, its address may change.
+const HOSTS: [(&str, Ipv4Addr); 1] = [("api.kraken.com", Ipv4Addr::new(104, 16, 144, 4))];
+const RESOLVER: StaticResolver = StaticResolver::new(&HOSTS);
+/// Redirects followed when the API moves an endpoint
+const MAX_REDIRECTS: u8 = 3;
+/// Longest key or string kept while parsing responses, longer ones are skipped
+const MAX_TOKEN_LENGTH: usize = 32;
+/// Deepest values read from responses: `result.<PAIR>.c[0]`
+const MAX_DEPTH: usize = 4;
+
+type JsonPath = Path<MAX_TOKEN_LENGTH, MAX_DEPTH>;
+
+/// Assets Kraken names differently than everyone else
+const ASSET_NAMES: [(&str, &str); 2] = [("BTC", "XBT"), ("DOGE", "XDG")];
+
+pub struct KrakenApiClient<StackT: TcpStack> {
+    //All requests go to the same host, a single connection is kept open
+    http: HttpClient<StackT, StaticResolver<'static>, 1>,
+    //pairs the exchange doesn't have, left out of the requests
+    unknown_pairs: UnknownPairs,
+}
+
+impl<StackT: TcpStack> KrakenApiClient<StackT> {
+    pub fn new() -> Self {
+        KrakenApiClient {
+            http: HttpClient::new(RESOLVER).max_redirects(MAX_REDIRECTS),
+            unknown_pairs: UnknownPairs::new(),
+        }
+    }
+
+    /// Trading pairs the exchange doesn't have, in its names, e.g. `GEROUSD`. The symbols
+    /// get no prices in the currencies of these pairs.
+    pub fn unknown_pairs(&self) -> &[PairName] {
+        &self.unknown_pairs
+    }
+
+    /// Reads the ticker of every symbol traded against each of `currencies`
+    fn get_tickers<'a, const MAX_CURRENCIES: usize>(
+        &mut self,
+        network: &mut StackT,
+        symbols: &'a [String<16>],
+        currencies: &'a [String<8>],
+    ) -> Result<Tickers<'a, MAX_CURRENCIES>, CryptoApiError> {
+        let mut tickers = Tickers::new(symbols, currencies);
+        let http = &mut self.http;
+
+        //The whole batch is rejected if one of the pairs is unknown
+        crypto_api_client::read_known_pairs(
+            pair_names(symbols, currencies).count(),
+            &mut self.unknown_pairs,
+            &|index| pair_names(symbols, currencies).nth(index).flatten(),
+            &mut |batch, unknown| {
+                //Nothing to ask for when every symbol is priced in itself
+                let request = match construct_request(symbols, currencies, batch, unknown)? {
+                    Some(request) => request,
+                    None => return Ok(()),
+                };
+
+                let result =
+                    crypto_api_client::read_json(http, network, &request, |path, token| {
+                        tickers.visit(path, token)
+                    });
+                tickers.check(result)
+            },
+        )?;
+
+        Ok(tickers)
+    }
+}
+
+impl<StackT: TcpStack> Default for KrakenApiClient<StackT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<StackT: TcpStack, const MAX_CURRENCIES: usize> CryptoApiClient<StackT, MAX_CURRENCIES>
+    for KrakenApiClient<StackT>
+{
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn disconnect(&mut self, network: &mut StackT) {
+        self.http.close_all(network);
+    }
+
+    //Public market data needs no key
+    fn set_api_key(&mut self, _provider: &str, _api_key: &str) -> Result<(), CryptoApiError> {
+        Ok(())
+    }
+
+    fn get_openday_price(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currencies: &[String<8>],
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError> {
+        let tickers = self.get_tickers(network, symbols, currencies)?;
+        Ok(tickers.open_prices)
+    }
+
+    fn get_current_prices(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currencies: &[String<8>],
+    ) -> Result<Prices<MAX_CURRENCIES>, CryptoApiError> {
+        let tickers = self.get_tickers(network, symbols, currencies)?;
+        Ok(tickers.last_prices)
+    }
+
+    fn get_market_stats(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, MAX_CURRENCIES>,
+        currency: &String<8>,
+    ) -> Result<MarketStatsMap<MAX_CURRENCIES>, CryptoApiError> {
+        //The exchange doesn't know how many coins there are, so there is no market cap
+        let tickers = self.get_tickers(network, symbols, core::slice::from_ref(currency))?;
+        Ok(tickers.stats)
+    }
+
+    fn get_history(
+        &mut self,
+        network: &mut StackT,
+        symbol: &String<16>,
+        currency: &String<8>,
+        interval: Interval,
+        count: usize,
+    ) -> Result<History, CryptoApiError> {
+        //The price of an asset in itself never changes, there is nothing to ask for
+        if is_same_asset(symbol, currency) {
+            return Ok(History::new());
+        }
+
+        //The exchange sends the last 720 candles, the latest are kept as they arrive
+        let request = construct_history_request(symbol, currency, interval)?;
+        let mut candles = Candles::new(interval);
+
+        let result =
+            crypto_api_client::read_json(&mut self.http, network, &request, |path, token| {
+                candles.visit(path, token)
+            });
+
+        check_error(candles.error.as_deref(), result)?;
+        Ok(candles.reader.finish(count.min(MAX_CANDLES)))
+    }
+
+    fn list_symbols(
+        &mut self,
+        network: &mut StackT,
+        currency: &String<8>,
+        on_symbol: &mut dyn FnMut(&str),
+    ) -> Result<(), CryptoApiError> {
+        let request = Request::get("https://api.kraken.com/0/public/AssetPairs")
+            .map_err(|_| CryptoApiError::RequestError)?;
+        let mut error = None;
+
+        let result =
+            crypto_api_client::read_json(&mut self.http, network, &request, |path, token| {
+                if path.matches(&["error", "*"]) {
+                    return Err(read_error(&mut error, token));
+                }
+                visit_listed(on_symbol, currency, path, token);
+                Ok(())
+            });
+
+        check_error(error.as_deref(), result)
+    }
+}
+
+/// Calls `on_symbol` with the base asset of a pair quoted in `currency`, read from the
+/// `wsname` of an `/AssetPairs` response: `{"result":{"XXBTZUSD":{"wsname":"XBT/USD",...}}}`
+fn visit_listed(on_symbol: &mut dyn FnMut(&str), currency: &str, path: &JsonPath, token: Token) {
+    let name = match token {
+        Token::String(Some(name)) if path.matches(&["result", "*", "wsname"]) => name,
+        _ => return,
+    };
+
+    if let Some(separator) = name.find('/') {
+        let (base, quote) = (&name[..separator], &name[separator + 1..]);
+        if quote.eq_ignore_ascii_case(asset_name(currency)) {
+            on_symbol(symbol_of(base));
+        }
+    }
+}
+
+/// Values read from a `/Ticker` response, by symbol and currency:
+/// `{"error":[],"result":{"XXBTZUSD":{"c":["43127.1","0.001"],"o":"43718.0",...}}}`.
+/// Arrays of values over a period have today's value first and the last 24 hours' second.
+struct Tickers<'a, const MAX_CURRENCIES: usize> {
+    //symbols and currencies asked for, the pairs in the response are translated back to them
+    symbols: &'a [String<16>],
+    currencies: &'a [String<8>],
+    last_prices: Prices<MAX_CURRENCIES>,
+    open_prices: Prices<MAX_CURRENCIES>,
+    //of the pairs with the first currency
+    stats: MarketStatsMap<MAX_CURRENCIES>,
+    //first message of the `error` array, e.g. `EQuery:Unknown asset pair`
+    error: Option<String<MAX_TOKEN_LENGTH>>,
+}
+
+impl<'a, const MAX_CURRENCIES: usize> Tickers<'a, MAX_CURRENCIES> {
+    fn new(symbols: &'a [String<16>], currencies: &'a [String<8>]) -> Self {
+        let mut tickers = Tickers {
+            symbols,
+            currencies,
+            last_prices: Prices::new(),
+            open_prices: Prices::new(),
+            stats: MarketStatsMap::new(),
+            error: None,
+        };
+
+        //There is no pair of an asset with itself, its price is known anyway
+        for symbol in symbols.iter() {
+            for currency in currencies
+                .iter()
+                .filter(|currency| is_same_asset(symbol, currency))
+            {
+                for prices in &mut [&mut tickers.last_prices, &mut tickers.open_prices] {
+                    insert_quote(prices, symbol, currency, 1.0).ok();
+                }
+            }
+        }
+
+        tickers
+    }
+
+    fn visit(&mut self, path: &JsonPath, token: Token) -> Result<(), ParseErrorKind> {
+        if path.matches(&["error", "*"]) {
+            return Err(read_error(&mut self.error, token));
+        }
+
+        let is_last_price = path.matches(&["result", "*", "c", "*"]) && path.index(3) == Some(0);
+        let is_open_price = path.matches(&["result", "*", "o"]);
+        let stat = if path.matches(&["result", "*", "*", "*"]) && path.index(3) == Some(1) {
+            path.key(2).filter(|field| ["h", "l", "v"].contains(field))
+        } else {
+            None
+        };
+        if !is_last_price && !is_open_price && stat.is_none() {
+            return Ok(());
+        }
+
+        let (symbol, currency) = path
+            .key(1)
+            .and_then(|pair| self.pair_of(pair))
+            .ok_or(ParseErrorKind::UnexpectedValue)?;
+        let price = parse_price(token)?;
+
+        if let Some(stat) = stat {
+            if !self.currencies[..1].iter().any(|main| main == currency) {
+                return Ok(());
+            }
+            return update_stats(&mut self.stats, symbol, |stats| match stat {
+                "h" => stats.high_24h = Some(price),
+                "l" => stats.low_24h = Some(price),
+                _ => stats.volume_24h = Some(price),
+            });
+        }
+
+        let prices = if is_last_price {
+            &mut self.last_prices
+        } else {
+            &mut self.open_prices
+        };
+        insert_quote(prices, symbol, currency, price)
+    }
+
+    /// Symbol and currency asked for that `pair` trades. Pairs of older assets have
+    /// prefixed names: `XXBTZUSD` rather than `XBTUSD`.
+    fn pair_of(&self, pair: &str) -> Option<(&'a str, &'a str)> {
+        for symbol in self.symbols.iter() {
+            let asset = asset_name(symbol);
+
+            for currency in self.currencies.iter() {
+                let quote = asset_name(currency);
+                let is_pair = [
+                    ["", asset, "", quote],
+                    ["X", asset, "Z", quote],
+                    ["X", asset, "X", quote],
+                ]
+                .iter()
+                .any(|parts| is_concatenation(pair, parts));
+
+                if is_pair {
+                    return Some((symbol.as_str(), currency.as_str()));
+                }
+            }
+        }
+        None
+    }
+
+    /// Turns the result of reading a response into an error, ready for the next response
+    fn check(&mut self, result: Result<(), CryptoApiError>) -> Result<(), CryptoApiError> {
+        check_error(self.error.take().as_deref(), result)
+    }
+}
+
+/// Values read from an `/OHLC` response, every candle is an array:
+/// `{"error":[],"result":{"XXBTZUSD":[[1705392000,"43401.2","43483.9",...]],"last":1705395600}}`
+struct Candles {
+    reader: CandleReader,
+    //first message of the `error` array
+    error: Option<String<MAX_TOKEN_LENGTH>>,
+}
+
+impl Candles {
+    fn new(interval: Interval) -> Self {
+        Candles {
+            reader: CandleReader::new(interval),
+            error: None,
+        }
+    }
+
+    fn visit(&mut self, path: &JsonPath, token: Token) -> Result<(), ParseErrorKind> {
+        if path.matches(&["error", "*"]) {
+            return Err(read_error(&mut self.error, token));
+        }
+        if !path.matches(&["result", "*", "*", "*"]) {
+            return Ok(());
+        }
+
+        let (index, column) = match (path.index(2), path.index(3)) {
+            (Some(index), Some(column)) => (index, column),
+            _ => return Err(ParseErrorKind::UnexpectedValue),
+        };
+        //time, open, high, low and close come first
+        match column {
+            0 => {
+                let time = parse_time(token, 1)?;
+                self.reader.update(index, |candle| candle.time = time);
+            }
+            1..=4 => {
+                let price = parse_price(token)?;
+                self.reader.update(index, |candle| match column {
+                    1 => candle.open = price,
+                    2 => candle.high = price,
+                    3 => candle.low = price,
+                    _ => candle.close = price,
+                });
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps a message of the `error` array, messages too long to keep are reported like any
+/// other error
+fn read_error(error: &mut Option<String<MAX_TOKEN_LENGTH>>, token: Token) -> ParseErrorKind {
+    *error = match token {
+        Token::String(message) => message.and_then(|message| message.parse().ok()),
+        _ => None,
+    };
+    ParseErrorKind::ErrorMessage
+}
+
+/// Turns the messages from the `error` array into errors
+fn check_error(
+    error: Option<&str>,
+    result: Result<(), CryptoApiError>,
+) -> Result<(), CryptoApiError> {
+    let message = error.unwrap_or("");
+
+    match result {
+        Err(CryptoApiError::ParseError {
+            kind: ParseErrorKind::ErrorMessage,
+            ..
+        }) if message.starts_with("EQuery:Unknown asset pair") => {
+            Err(CryptoApiError::UnknownSymbol)
+        }
+        Err(CryptoApiError::ParseError {
+            kind: ParseErrorKind::ErrorMessage,
+            ..
+        }) if message.starts_with("EAPI:Rate limit exceeded") => Err(CryptoApiError::Status {
+            status: 429,
+            retry_after: None,
+        }),
+        result => result,
+    }
+}
+
+/// Prices are sent as strings to keep their precision
+fn parse_price(token: Token) -> Result<f32, ParseErrorKind> {
+    match token {
+        Token::String(Some(price)) => price.parse().map_err(|_| ParseErrorKind::UnexpectedValue),
+        _ => Err(ParseErrorKind::UnexpectedValue),
+    }
+}
+
+fn asset_name(symbol: &str) -> &str {
+    ASSET_NAMES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(symbol))
+        .map_or(symbol, |&(_, name)| name)
+}
+
+/// Symbol everyone else uses for an asset named `name` by Kraken
+fn symbol_of(name: &str) -> &str {
+    ASSET_NAMES
+        .iter()
+        .find(|(_, known)| known.eq_ignore_ascii_case(name))
+        .map_or(name, |&(symbol, _)| symbol)
+}
+
+fn is_same_asset(symbol: &str, currency: &str) -> bool {
+    asset_name(symbol).eq_ignore_ascii_case(asset_name(currency))
+}
+
+/// Names of the pairs of `symbols` with `currencies` in the names of the exchange, e.g.
+/// `XBTUSD`, in the order they are requested. Pairs of an asset with itself are left out,
+/// names too long are `None`.
+fn pair_names<'a>(
+    symbols: &'a [String<16>],
+    currencies: &'a [String<8>],
+) -> impl Iterator<Item = Option<PairName>> + 'a {
+    symbols.iter().flat_map(move |symbol| {
+        currencies
+            .iter()
+            .filter(move |currency| !is_same_asset(symbol, currency))
+            .map(move |currency| {
+                let mut pair = PairName::new();
+                pair.push_str(asset_name(symbol)).ok()?;
+                pair.push_str(asset_name(currency)).ok()?;
+                Some(pair)
+            })
+    })
+}
+
+/// Builds a GET request with pair - the selected cryptos traded against the currencies,
+/// e.g. `XBTUSD,ETHUSD`, of the pairs with the indices in `batch` that aren't `unknown`.
+/// There is no request if no pair is left, or all would be of an asset with itself.
+fn construct_request(
+    symbols: &[String<16>],
+    currencies: &[String<8>],
+    batch: Range<usize>,
+    unknown: &[PairName],
+) -> Result<Option<Request<'static>>, CryptoApiError> {
+    let mut pairs: String<512> = String::new();
+
+    for (index, pair) in pair_names(symbols, currencies).enumerate() {
+        let pair = pair.ok_or(CryptoApiError::RequestError)?;
+        if !batch.contains(&index) || unknown.contains(&pair) {
+            continue;
+        }
+        if !pairs.is_empty() {
+            pairs.push(',').map_err(|_| CryptoApiError::RequestError)?;
+        }
+        pairs
+            .push_str(&pair)
+            .map_err(|_| CryptoApiError::RequestError)?;
+    }
+
+    if pairs.is_empty() {
+        return Ok(None);
+    }
+
+    let request = Request::get("https://api.kraken.com/0/public/Ticker")
+        .map_err(|_| CryptoApiError::RequestError)?
+        .query("pair", &pairs);
+
+    Ok(Some(request))
+}
+
+/// Builds a GET request for the candles of the pair of `symbol` with `currency`
+fn construct_history_request(
+    symbol: &str,
+    currency: &str,
+    interval: Interval,
+) -> Result<Request<'static>, CryptoApiError> {
+    let mut pair: String<32> = String::new();
+    for name in &[asset_name(symbol), asset_name(currency)] {
+        pair.push_str(name)
+            .map_err(|_| CryptoApiError::RequestError)?;
+    }
+
+    let minutes = match interval {
+        Interval::Hour => "60",
+        Interval::Day => "1440",
+    };
+
+    let request = Request::get("https://api.kraken.com/0/public/OHLC")
+        .map_err(|_| CryptoApiError::RequestError)?
+        .query("pair", &pair)
+        .query("interval", minutes);
+
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto_api_client::tests::{currencies, price, read_fixture};
+    use crate::crypto_api_client::MarketStats;
+    use crate::test_support::{ok_response, MockStack, Step};
+
+    const TICKER: &[u8] = include_bytes!("../fixtures/kraken/ticker.json");
+    const UNKNOWN_PAIR: &[u8] = include_bytes!("../fixtures/kraken/unknown_pair.json");
+    const OHLC: &[u8] = include_bytes!("../fixtures/kraken/ohlc.json");
+    const ASSET_PAIRS: &[u8] = include_bytes!("../fixtures/kraken/asset_pairs.json");
+
+    fn symbols(symbols: &[&str]) -> Vec<String<16>, 4> {
+        symbols.iter().map(|&symbol| String::from(symbol)).collect()
+    }
+
+    /// Error `read_json` returns when the visitor stops at an error message
+    fn error_message() -> CryptoApiError {
+        CryptoApiError::ParseError {
+            offset: 10,
+            kind: ParseErrorKind::ErrorMessage,
+        }
+    }
+
+    #[test]
+    fn tickers() {
+        let symbols = symbols(&["BTC", "DOT", "ETH"]);
+        let currencies = currencies(&["USD", "BTC"]);
+        let mut tickers = Tickers::<4>::new(&symbols, &currencies);
+        read_fixture(TICKER, |path, token| tickers.visit(path, token)).unwrap();
+
+        assert_eq!(tickers.last_prices.len(), 3);
+        assert_eq!(price(&tickers.last_prices, "BTC", "USD"), Some(43127.1));
+        assert_eq!(price(&tickers.last_prices, "DOT", "USD"), Some(7.8123));
+        assert_eq!(price(&tickers.last_prices, "ETH", "USD"), Some(2291.52));
+        assert_eq!(price(&tickers.last_prices, "ETH", "BTC"), Some(0.05313));
+        assert_eq!(price(&tickers.open_prices, "BTC", "USD"), Some(43718.0));
+        assert_eq!(price(&tickers.open_prices, "DOT", "USD"), Some(7.9561));
+        assert_eq!(price(&tickers.open_prices, "ETH", "USD"), Some(2245.68));
+        assert_eq!(price(&tickers.open_prices, "ETH", "BTC"), Some(0.05137));
+        //there is no XBTXBT pair
+        assert_eq!(price(&tickers.open_prices, "BTC", "BTC"), Some(1.0));
+    }
+
+    #[test]
+    fn market_stats() {
+        let symbols = symbols(&["BTC", "DOT", "ETH"]);
+        let currencies = currencies(&["USD", "BTC"]);
+        let mut tickers = Tickers::<4>::new(&symbols, &currencies);
+        read_fixture(TICKER, |path, token| tickers.visit(path, token)).unwrap();
+
+        assert_eq!(
+            tickers.stats.get(&String::from("BTC")),
+            Some(&MarketStats {
+                high_24h: Some(43900.0),
+                low_24h: Some(42821.0),
+                volume_24h: Some(2498.1091),
+                market_cap: None,
+            })
+        );
+        //the XETHXXBT ticker is left out
+        assert_eq!(
+            tickers.stats.get(&String::from("ETH")).unwrap().high_24h,
+            Some(2297.74)
+        );
+        assert_eq!(tickers.stats.len(), 3);
+    }
+
+    #[test]
+    fn pairs_not_asked_for() {
+        let symbols = symbols(&["BTC", "DOT", "ETH"]);
+        let currencies = currencies(&["EUR"]);
+        let mut tickers = Tickers::<4>::new(&symbols, &currencies);
+
+        assert_eq!(
+            read_fixture(TICKER, |path, token| tickers.visit(path, token)),
+            Err(ParseErrorKind::UnexpectedValue)
+        );
+    }
+
+    #[test]
+    fn error_messages() {
+        let symbols = symbols(&["BTC", "NOPE"]);
+        let currencies = currencies(&["USD"]);
+        let mut tickers = Tickers::<4>::new(&symbols, &currencies);
+
+        assert_eq!(
+            read_fixture(UNKNOWN_PAIR, |path, token| tickers.visit(path, token)),
+            Err(ParseErrorKind::ErrorMessage)
+        );
+        assert_eq!(
+            tickers.check(Err(error_message())).err(),
+            Some(CryptoApiError::UnknownSymbol)
+        );
+
+        let mut tickers = Tickers::<4>::new(&symbols, &currencies);
+        read_fixture(
+            br#"{"error":["EAPI:Rate limit exceeded"]}"#,
+            |path, token| tickers.visit(path, token),
+        )
+        .ok();
+        assert!(tickers
+            .check(Err(error_message()))
+            .err()
+            .unwrap()
+            .is_rate_limited());
+
+        let mut tickers = Tickers::<4>::new(&symbols, &currencies);
+        read_fixture(
+            br#"{"error":["EGeneral:Internal error"]}"#,
+            |path, token| tickers.visit(path, token),
+        )
+        .ok();
+        assert_eq!(
+            tickers.check(Err(error_message())).err(),
+            Some(error_message())
+        );
+    }
+
+    #[test]
+    fn pairs_use_kraken_names() {
+        let symbols = symbols(&["BTC", "ETH", "DOGE"]);
+        let usd_btc = currencies(&["USD", "BTC"]);
+        let request = construct_request(&symbols, &usd_btc, 0..5, &[])
+            .unwrap()
+            .unwrap()
+            .serialize::<512>()
+            .unwrap();
+
+        assert!(request.starts_with(
+            b"GET /0/public/Ticker?pair=XBTUSD%2CETHUSD%2CETHXBT%2CXDGUSD%2CXDGXBT HTTP/1.1\r\n"
+        ));
+        let request = construct_request(&symbols, &usd_btc, 1..4, &["ETHXBT".into()])
+            .unwrap()
+            .unwrap()
+            .serialize::<512>()
+            .unwrap();
+        assert!(request.starts_with(b"GET /0/public/Ticker?pair=ETHUSD%2CXDGUSD HTTP/1.1\r\n"));
+        assert!(
+            construct_request(&symbols[..1], &currencies(&["XBT"]), 0..0, &[])
+                .unwrap()
+                .is_none()
+        );
+        assert!(is_concatenation("XXBTZUSD", &["x", "XBT", "Z", "usd"]));
+        assert!(!is_concatenation("XXBTZUSD", &["XBT", "USD"]));
+    }
+
+    #[test]
+    fn unknown_pairs_are_left_out() {
+        let rejected = ok_response::<128>(UNKNOWN_PAIR);
+        let xbt =
+            ok_response::<128>(br#"{"error":[],"result":{"XXBTZUSD":{"c":["43127.1","0.001"]}}}"#);
+        let eth =
+            ok_response::<128>(br#"{"error":[],"result":{"XETHZUSD":{"c":["2291.52","0.1"]}}}"#);
+        //the batch is split in BTC and GERO with ETH, which is split again
+        let scripts: [&[Step]; 1] = [&[
+            Step::Respond(&rejected),
+            Step::Respond(&xbt),
+            Step::Respond(&rejected),
+            Step::Respond(&rejected),
+            Step::Respond(&eth),
+        ]];
+        let mut stack = MockStack::new(&scripts);
+        let mut client = KrakenApiClient::new();
+        let usd = currencies(&["USD"]);
+
+        let prices = client
+            .get_current_prices(&mut stack, &symbols(&["BTC", "GERO", "ETH"]), &usd)
+            .unwrap();
+        assert_eq!(price(&prices, "BTC", "USD"), Some(43127.1));
+        assert_eq!(price(&prices, "ETH", "USD"), Some(2291.52));
+        assert_eq!(price(&prices, "GERO", "USD"), None);
+        assert_eq!(client.unknown_pairs(), ["GEROUSD"]);
+        assert_eq!(stack.written_count("?pair="), 5);
+        assert_eq!(stack.written_count("?pair=GEROUSD "), 1);
+
+        //the unknown pair is left out from then on
+        assert_eq!(
+            client.get_current_prices(&mut stack, &symbols(&["GERO"]), &usd),
+            Err(CryptoApiError::UnknownSymbol)
+        );
+        assert_eq!(stack.written_count("?pair="), 5);
+    }
+
+    #[test]
+    fn history() {
+        let mut candles = Candles::new(Interval::Hour);
+        read_fixture(OHLC, |path, token| candles.visit(path, token)).unwrap();
+        let history = candles.reader.finish(2);
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].time, 1705395600);
+        assert_eq!(history[0].high, 43352.0);
+        assert_eq!(history[1].open, 43215.6);
+        assert_eq!(history[1].close, 43127.1);
+
+        let mut candles = Candles::new(Interval::Hour);
+        assert_eq!(
+            read_fixture(UNKNOWN_PAIR, |path, token| candles.visit(path, token)),
+            Err(ParseErrorKind::ErrorMessage)
+        );
+        assert_eq!(
+            check_error(candles.error.as_deref(), Err(error_message())),
+            Err(CryptoApiError::UnknownSymbol)
+        );
+    }
+
+    #[test]
+    fn history_requests() {
+        let request = construct_history_request("BTC", "EUR", Interval::Day)
+            .unwrap()
+            .serialize::<512>()
+            .unwrap();
+
+        assert!(request.starts_with(b"GET /0/public/OHLC?pair=XBTEUR&interval=1440 HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn listed_symbols() {
+        let mut symbols = Vec::<String<16>, 4>::new();
+        read_fixture(ASSET_PAIRS, |path, token| {
+            visit_listed(
+                &mut |symbol| symbols.push(String::from(symbol)).unwrap(),
+                "usd",
+                path,
+                token,
+            );
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(symbols, ["BTC", "DOGE", "ETH"]);
+    }
+}
@@ -1,5 +1,20 @@
 #![no_std]
 
+pub mod backoff;
+pub mod binance_api_client;
+pub mod chunked;
+pub mod client;
+pub mod coingecko_api_client;
+pub mod headers;
 pub mod http_client;
+pub mod inflate;
+pub mod json;
+pub mod kraken_api_client;
+pub mod request;
+pub mod resolver;
+pub mod url;
 pub mod crypto_api_client;
-pub mod cryptocompare_api_client;
\ No newline at end of file
+pub mod cryptocompare_api_client;
+pub mod failover_api_client;
+#[cfg(test)]
+pub(crate) mod test_support;
\ No newline at end of file
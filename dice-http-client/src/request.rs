@@ -0,0 +1,352 @@
+//! Builder of HTTP/1.1 requests, serialized into a bounded buffer.
+
+use crate::url::{percent_encode_into, Url, UrlError};
+use heapless::{String, Vec};
+
+/// Value of the `User-Agent` header sent unless another one is set
+pub const DEFAULT_USER_AGENT: &str = "dice-http-client/0.1.0";
+/// Value of the `Accept` header sent unless another one is set
+pub const DEFAULT_ACCEPT: &str = "*/*";
+/// Value of the `Accept-Encoding` header sent unless another one is set.
+/// Responses in these codings are inflated by the client.
+pub const DEFAULT_ACCEPT_ENCODING: &str = "gzip, deflate";
+
+pub const MAX_REQUEST_HEADERS: usize = 16;
+pub const MAX_QUERY_LENGTH: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RequestError {
+    Url(UrlError),
+    TooManyHeaders,
+    /// Header name isn't a token, the value contains a line break, or the header
+    /// is one that the builder sets itself (`Host`, `Content-Length`, `Transfer-Encoding`)
+    InvalidHeader,
+    QueryTooLong,
+    /// The serialized request doesn't fit in the output buffer
+    BufferTooSmall,
+}
+
+impl From<UrlError> for RequestError {
+    fn from(error: UrlError) -> Self {
+        RequestError::Url(error)
+    }
+}
+
+/// HTTP/1.1 request. Builder methods take and return the request by value, so calls can be
+/// chained. The first error hit while building is kept and returned by `serialize`.
+#[derive(Clone, Debug)]
+pub struct Request<'a> {
+    method: Method,
+    url: Url<'a>,
+    query: String<MAX_QUERY_LENGTH>,
+    headers: Vec<(&'a str, &'a str), MAX_REQUEST_HEADERS>,
+    body: Option<&'a [u8]>,
+    error: Option<RequestError>,
+}
+
+impl<'a> Request<'a> {
+    pub fn new(method: Method, url: &'a str) -> Result<Self, RequestError> {
+        let url = Url::parse(url)?;
+        let mut query = String::new();
+        if let Some(existing) = url.query {
+            query
+                .push_str(existing)
+                .map_err(|_| RequestError::QueryTooLong)?;
+        }
+
+        Ok(Request {
+            method,
+            url,
+            query,
+            headers: Vec::new(),
+            body: None,
+            error: None,
+        })
+    }
+
+    pub fn get(url: &'a str) -> Result<Self, RequestError> {
+        Self::new(Method::Get, url)
+    }
+
+    pub fn post(url: &'a str, body: &'a [u8]) -> Result<Self, RequestError> {
+        Ok(Self::new(Method::Post, url)?.body(body))
+    }
+
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    pub fn url(&self) -> &Url<'a> {
+        &self.url
+    }
+
+    /// Appends a query parameter. Both the key and the value are percent-encoded.
+    pub fn query(mut self, key: &str, value: &str) -> Self {
+        if self.error.is_none() {
+            let result = self.append_query(key, value);
+            self.error = result.err();
+        }
+        self
+    }
+
+    /// Adds a header. Setting `User-Agent`, `Accept` or `Accept-Encoding` replaces the default value.
+    pub fn header(mut self, name: &'a str, value: &'a str) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+
+        let reserved = ["Host", "Content-Length", "Transfer-Encoding"]
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(name));
+        let valid_name = !name.is_empty() && name.bytes().all(is_token_byte);
+        let valid_value = !value.bytes().any(|byte| byte == b'\r' || byte == b'\n');
+
+        if reserved || !valid_name || !valid_value {
+            self.error = Some(RequestError::InvalidHeader);
+        } else if self.headers.push((name, value)).is_err() {
+            self.error = Some(RequestError::TooManyHeaders);
+        }
+
+        self
+    }
+
+    pub fn body(mut self, body: &'a [u8]) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// Writes the whole request - request line, headers and body - into a buffer
+    pub fn serialize<const SIZE: usize>(&self) -> Result<Vec<u8, SIZE>, RequestError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let mut output = Vec::new();
+
+        //Request line
+        push(&mut output, self.method.as_str())?;
+        push(&mut output, " ")?;
+        push(&mut output, self.url.path)?;
+        if !self.query.is_empty() {
+            push(&mut output, "?")?;
+            push(&mut output, &self.query)?;
+        }
+        push(&mut output, " HTTP/1.1\r\n")?;
+
+        let host: String<256> = self.url.host_header()?;
+        push_header(&mut output, "Host", &host)?;
+
+        if !self.has_header("User-Agent") {
+            push_header(&mut output, "User-Agent", DEFAULT_USER_AGENT)?;
+        }
+        if !self.has_header("Accept") {
+            push_header(&mut output, "Accept", DEFAULT_ACCEPT)?;
+        }
+        if !self.has_header("Accept-Encoding") {
+            push_header(&mut output, "Accept-Encoding", DEFAULT_ACCEPT_ENCODING)?;
+        }
+
+        for (name, value) in self.headers.iter() {
+            push_header(&mut output, name, value)?;
+        }
+
+        let body = self.body.unwrap_or(&[]);
+        if self.body.is_some() || matches!(self.method, Method::Post | Method::Put) {
+            let mut length = String::<20>::new();
+            core::fmt::write(&mut length, format_args!("{}", body.len()))
+                .map_err(|_| RequestError::BufferTooSmall)?;
+            push_header(&mut output, "Content-Length", &length)?;
+        }
+
+        push(&mut output, "\r\n")?;
+        output
+            .extend_from_slice(body)
+            .map_err(|_| RequestError::BufferTooSmall)?;
+
+        Ok(output)
+    }
+
+    /// The same request sent to another URL, as when following a redirect.
+    /// The query parameters come from the new URL only, the headers are kept.
+    pub fn with_url<'b>(
+        &self,
+        url: &'b str,
+        method: Method,
+        keep_body: bool,
+    ) -> Result<Request<'b>, RequestError>
+    where
+        'a: 'b,
+    {
+        let mut request = Request::new(method, url)?;
+        request.error = self.error;
+
+        //Without the body, its type isn't sent either
+        let headers = self
+            .headers
+            .iter()
+            .filter(|(name, _)| keep_body || !name.eq_ignore_ascii_case("Content-Type"));
+        for &header in headers {
+            request.headers.push(header).ok();
+        }
+
+        request.body = if keep_body { self.body } else { None };
+        Ok(request)
+    }
+
+    fn append_query(&mut self, key: &str, value: &str) -> Result<(), RequestError> {
+        if !self.query.is_empty() {
+            self.query
+                .push('&')
+                .map_err(|_| RequestError::QueryTooLong)?;
+        }
+
+        percent_encode_into(&mut self.query, key).map_err(|_| RequestError::QueryTooLong)?;
+        self.query
+            .push('=')
+            .map_err(|_| RequestError::QueryTooLong)?;
+        percent_encode_into(&mut self.query, value).map_err(|_| RequestError::QueryTooLong)
+    }
+
+    /// True if a header called `name` was added, compared case-insensitively
+    pub fn has_header(&self, name: &str) -> bool {
+        self.headers
+            .iter()
+            .any(|(header, _)| header.eq_ignore_ascii_case(name))
+    }
+}
+
+fn push<const SIZE: usize>(output: &mut Vec<u8, SIZE>, text: &str) -> Result<(), RequestError> {
+    output
+        .extend_from_slice(text.as_bytes())
+        .map_err(|_| RequestError::BufferTooSmall)
+}
+
+fn push_header<const SIZE: usize>(
+    output: &mut Vec<u8, SIZE>,
+    name: &str,
+    value: &str,
+) -> Result<(), RequestError> {
+    push(output, name)?;
+    push(output, ": ")?;
+    push(output, value)?;
+    push(output, "\r\n")
+}
+
+/// `tchar` from RFC 7230
+fn is_token_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serialize(request: Request) -> Result<String<1024>, RequestError> {
+        let bytes = request.serialize::<1024>()?;
+        Ok(String::from(core::str::from_utf8(&bytes).unwrap()))
+    }
+
+    #[test]
+    fn get_with_query_and_defaults() {
+        let request =
+            Request::get("https://min-api.cryptocompare.com/data/pricemulti?extraParams=dice")
+                .unwrap()
+                .query("fsyms", "BTC,ETH")
+                .query("tsyms", "USD");
+
+        assert_eq!(
+            serialize(request).unwrap(),
+            "GET /data/pricemulti?extraParams=dice&fsyms=BTC%2CETH&tsyms=USD HTTP/1.1\r\n\
+             Host: min-api.cryptocompare.com\r\n\
+             User-Agent: dice-http-client/0.1.0\r\n\
+             Accept: */*\r\n\
+             Accept-Encoding: gzip, deflate\r\n\
+             \r\n"
+        );
+    }
+
+    #[test]
+    fn post_with_headers_and_body() {
+        let request = Request::post("http://192.168.1.10:8080/api", b"{\"a\":1}")
+            .unwrap()
+            .header("accept", "application/json")
+            .header("Accept-Encoding", "identity")
+            .header("Content-Type", "application/json");
+
+        assert_eq!(
+            serialize(request).unwrap(),
+            "POST /api HTTP/1.1\r\n\
+             Host: 192.168.1.10:8080\r\n\
+             User-Agent: dice-http-client/0.1.0\r\n\
+             accept: application/json\r\n\
+             Accept-Encoding: identity\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: 7\r\n\
+             \r\n\
+             {\"a\":1}"
+        );
+    }
+
+    #[test]
+    fn invalid_requests() {
+        assert_eq!(
+            Request::get("ftp://example.com").unwrap_err(),
+            RequestError::Url(UrlError::UnsupportedScheme)
+        );
+
+        let invalid_headers = [
+            ("Host", "example.org"),
+            ("content-length", "5"),
+            ("Bad Name", "value"),
+            ("", "value"),
+            ("X-Injected", "a\r\nEvil: 1"),
+        ];
+        for (name, value) in invalid_headers.iter() {
+            let request = Request::get("http://example.com")
+                .unwrap()
+                .header(name, value);
+            assert_eq!(
+                serialize(request).unwrap_err(),
+                RequestError::InvalidHeader,
+                "{}",
+                name
+            );
+        }
+
+        let mut request = Request::get("http://example.com").unwrap();
+        for _ in 0..=MAX_REQUEST_HEADERS {
+            request = request.header("X-Filler", "1");
+        }
+        assert_eq!(
+            serialize(request).unwrap_err(),
+            RequestError::TooManyHeaders
+        );
+
+        let request = Request::get("http://example.com").unwrap();
+        assert_eq!(
+            request.serialize::<16>().unwrap_err(),
+            RequestError::BufferTooSmall
+        );
+    }
+}
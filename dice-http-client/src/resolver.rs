@@ -0,0 +1,74 @@
+//! Translation of host names to addresses the `TcpStack` can connect to.
+
+use drogue_network::addr::Ipv4Addr;
+
+pub trait Resolver {
+    fn resolve(&self, host: &str) -> Option<Ipv4Addr>;
+}
+
+/// Resolves IPv4 literals and the host names from a fixed table, for networks without DNS
+pub struct StaticResolver<'a> {
+    hosts: &'a [(&'a str, Ipv4Addr)],
+}
+
+impl<'a> StaticResolver<'a> {
+    pub const fn new(hosts: &'a [(&'a str, Ipv4Addr)]) -> Self {
+        StaticResolver { hosts }
+    }
+}
+
+impl<'a> Resolver for StaticResolver<'a> {
+    fn resolve(&self, host: &str) -> Option<Ipv4Addr> {
+        parse_ipv4(host).or_else(|| {
+            self.hosts
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(host))
+                .map(|&(_, address)| address)
+        })
+    }
+}
+
+/// Parses a dotted-decimal IPv4 address, e.g. `192.168.1.10`
+pub fn parse_ipv4(text: &str) -> Option<Ipv4Addr> {
+    let mut octets = [0u8; 4];
+    let mut parts = text.split('.');
+
+    for octet in octets.iter_mut() {
+        let part = parts.next()?;
+        if part.is_empty() || part.len() > 3 || !part.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        *octet = part.parse().ok()?;
+    }
+
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_literals_and_table() {
+        let hosts = [("min-api.cryptocompare.com", Ipv4Addr::new(40, 115, 22, 134))];
+        let resolver = StaticResolver::new(&hosts);
+
+        assert_eq!(
+            resolver.resolve("Min-Api.CryptoCompare.com"),
+            Some(Ipv4Addr::new(40, 115, 22, 134))
+        );
+        assert_eq!(
+            resolver.resolve("192.168.1.10"),
+            Some(Ipv4Addr::new(192, 168, 1, 10))
+        );
+        assert_eq!(resolver.resolve("api.coingecko.com"), None);
+
+        for invalid in ["1.2.3", "1.2.3.4.5", "256.1.1.1", "1..2.3", "01234.1.1.1"].iter() {
+            assert_eq!(parse_ipv4(invalid), None, "{}", invalid);
+        }
+    }
+}
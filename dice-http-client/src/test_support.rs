@@ -0,0 +1,378 @@
+//! Test doubles for testing the clients on the host: an in-memory TCP stack playing a
+//! scripted server, and a price provider answering from a table.
+
+use crate::crypto_api_client::tests::price;
+use crate::crypto_api_client::{
+    insert_quote, Candle, CryptoApiClient, CryptoApiError, History, Interval, MarketStats,
+    MarketStatsMap, Prices,
+};
+use core::cell::{Cell, Ref, RefCell};
+use drogue_network::addr::{HostAddr, HostSocketAddr, IpAddr, Ipv4Addr};
+use drogue_network::tcp::{Mode, TcpError, TcpStack};
+use heapless::{String, Vec};
+
+/// Most connections a `MockStack` can open
+const MAX_SOCKETS: usize = 8;
+
+/// What the server does next on a connection
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Step<'a> {
+    /// Sends data right away
+    Send(&'a [u8]),
+    /// Sends a response once the client has written a request
+    Respond(&'a [u8]),
+    /// Has nothing to send for one read
+    WouldBlock,
+    /// Breaks the connection, every read and write fails from then on
+    Fail,
+}
+
+/// Stack whose connections talk to a scripted server. The n-th connection opened follows
+/// `scripts[n]`, and is closed by the server once the script is over. Reads return at most
+/// `read_size` bytes, to split responses at every possible place.
+pub(crate) struct MockStack<'a> {
+    scripts: &'a [&'a [Step<'a>]],
+    read_size: usize,
+    sockets: RefCell<Vec<Socket<'a>, MAX_SOCKETS>>,
+    remotes: RefCell<Vec<(IpAddr, u16), MAX_SOCKETS>>,
+    //host names given for TLS, empty if none was
+    hostnames: RefCell<Vec<String<64>, MAX_SOCKETS>>,
+    closed: Cell<usize>,
+    written: RefCell<Vec<u8, 8192>>,
+}
+
+struct Socket<'a> {
+    steps: &'a [Step<'a>],
+    unread: &'a [u8],
+    //a request was written since the last response was sent
+    requested: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum MockError {
+    //there is no script for another connection
+    Refused,
+    Write,
+    Read,
+}
+
+impl From<MockError> for TcpError {
+    fn from(error: MockError) -> Self {
+        match error {
+            MockError::Refused => TcpError::ConnectionRefused,
+            MockError::Write => TcpError::WriteError,
+            MockError::Read => TcpError::ReadError,
+        }
+    }
+}
+
+impl<'a> MockStack<'a> {
+    pub(crate) fn new(scripts: &'a [&'a [Step<'a>]]) -> Self {
+        MockStack {
+            scripts,
+            read_size: usize::MAX,
+            sockets: RefCell::new(Vec::new()),
+            remotes: RefCell::new(Vec::new()),
+            hostnames: RefCell::new(Vec::new()),
+            closed: Cell::new(0),
+            written: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn read_size(mut self, read_size: usize) -> Self {
+        self.read_size = read_size;
+        self
+    }
+
+    /// Opens the next connection, for reading a response without sending a request
+    pub(crate) fn connected(&mut self) -> usize {
+        let socket = self.open(Mode::Blocking).unwrap();
+        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let remote = HostSocketAddr::new(HostAddr::new(ip, None), 80);
+        self.connect(socket, remote).unwrap()
+    }
+
+    pub(crate) fn opened(&self) -> usize {
+        self.sockets.borrow().len()
+    }
+
+    pub(crate) fn closed(&self) -> usize {
+        self.closed.get()
+    }
+
+    /// Addresses connected to, in order
+    pub(crate) fn remotes(&self) -> Ref<'_, [(IpAddr, u16)]> {
+        Ref::map(self.remotes.borrow(), |remotes| &remotes[..])
+    }
+
+    /// Host names given with the addresses connected to, in order
+    pub(crate) fn hostnames(&self) -> Ref<'_, [String<64>]> {
+        Ref::map(self.hostnames.borrow(), |hostnames| &hostnames[..])
+    }
+
+    /// Everything written on all connections, separated by an empty line between connections
+    pub(crate) fn written(&self) -> Ref<'_, str> {
+        Ref::map(self.written.borrow(), |written| {
+            core::str::from_utf8(written).unwrap()
+        })
+    }
+
+    pub(crate) fn written_count(&self, text: &str) -> usize {
+        self.written().matches(text).count()
+    }
+}
+
+impl<'a> TcpStack for MockStack<'a> {
+    type TcpSocket = usize;
+    type Error = MockError;
+
+    fn open(&self, _mode: Mode) -> Result<usize, MockError> {
+        Ok(self.opened())
+    }
+
+    fn connect(&self, socket: usize, remote: HostSocketAddr) -> Result<usize, MockError> {
+        let steps = *self.scripts.get(socket).ok_or(MockError::Refused)?;
+        let socket = Socket {
+            steps,
+            unread: &[],
+            requested: false,
+        };
+        self.sockets
+            .borrow_mut()
+            .push(socket)
+            .map_err(|_| MockError::Refused)?;
+        self.remotes
+            .borrow_mut()
+            .push((remote.addr().ip(), remote.port()))
+            .ok();
+        let hostname = remote
+            .addr()
+            .hostname()
+            .map_or(String::new(), |name| name.chars().collect());
+        self.hostnames.borrow_mut().push(hostname).ok();
+
+        //Keeps a request line from being glued to the body of the previous request
+        let mut written = self.written.borrow_mut();
+        if !written.is_empty() {
+            written.extend_from_slice(b"\r\n").unwrap();
+        }
+
+        Ok(self.opened() - 1)
+    }
+
+    fn is_connected(&self, _socket: &usize) -> Result<bool, MockError> {
+        //Like a real stack, it doesn't know yet that the server closed the connection
+        Ok(true)
+    }
+
+    fn write(&self, socket: &mut usize, buffer: &[u8]) -> nb::Result<usize, MockError> {
+        let mut sockets = self.sockets.borrow_mut();
+        let socket = &mut sockets[*socket];
+        if let Some(Step::Fail) = socket.steps.first() {
+            return Err(nb::Error::Other(MockError::Write));
+        }
+
+        self.written.borrow_mut().extend_from_slice(buffer).unwrap();
+        socket.requested = true;
+        Ok(buffer.len())
+    }
+
+    fn read(&self, socket: &mut usize, buffer: &mut [u8]) -> nb::Result<usize, MockError> {
+        let mut sockets = self.sockets.borrow_mut();
+        let socket = &mut sockets[*socket];
+
+        while socket.unread.is_empty() {
+            let (&step, rest) = match socket.steps.split_first() {
+                Some(next) => next,
+                None => return Ok(0),
+            };
+
+            match step {
+                Step::Send(data) => socket.unread = data,
+                Step::Respond(data) if socket.requested => {
+                    socket.unread = data;
+                    socket.requested = false;
+                }
+                Step::Respond(_) => return Err(nb::Error::WouldBlock),
+                Step::WouldBlock => {
+                    socket.steps = rest;
+                    return Err(nb::Error::WouldBlock);
+                }
+                Step::Fail => return Err(nb::Error::Other(MockError::Read)),
+            }
+            socket.steps = rest;
+        }
+
+        let count = socket.unread.len().min(buffer.len()).min(self.read_size);
+        buffer[..count].copy_from_slice(&socket.unread[..count]);
+        socket.unread = &socket.unread[count..];
+
+        Ok(count)
+    }
+
+    fn close(&self, _socket: usize) -> Result<(), MockError> {
+        self.closed.set(self.closed.get() + 1);
+        Ok(())
+    }
+}
+
+/// An HTTP/1.1 response with `body` and its length
+pub(crate) fn ok_response<const SIZE: usize>(body: &[u8]) -> Vec<u8, SIZE> {
+    let mut header = String::<64>::new();
+    core::fmt::write(
+        &mut header,
+        format_args!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()),
+    )
+    .unwrap();
+
+    let mut response = Vec::new();
+    response.extend_from_slice(header.as_bytes()).unwrap();
+    response.extend_from_slice(body).unwrap();
+    response
+}
+
+/// Provider knowing the prices of a few symbols, or failing every request
+pub(crate) struct MockApiClient {
+    pub(crate) name: &'static str,
+    //symbol, currency and price
+    pub(crate) prices: &'static [(&'static str, &'static str, f32)],
+    pub(crate) error: Option<CryptoApiError>,
+    pub(crate) requested: Vec<Vec<String<16>, 4>, 4>,
+    pub(crate) connected: bool,
+    pub(crate) api_key: Option<String<16>>,
+}
+
+impl MockApiClient {
+    pub(crate) fn new(
+        name: &'static str,
+        prices: &'static [(&'static str, &'static str, f32)],
+    ) -> Self {
+        MockApiClient {
+            name,
+            prices,
+            error: None,
+            requested: Vec::new(),
+            connected: false,
+            api_key: None,
+        }
+    }
+
+    pub(crate) fn failing(name: &'static str, error: CryptoApiError) -> Self {
+        MockApiClient {
+            error: Some(error),
+            ..MockApiClient::new(name, &[])
+        }
+    }
+}
+
+impl<StackT: TcpStack> CryptoApiClient<StackT, 4> for MockApiClient {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn disconnect(&mut self, _network: &mut StackT) {
+        self.connected = false;
+    }
+
+    fn set_api_key(&mut self, provider: &str, api_key: &str) -> Result<(), CryptoApiError> {
+        if provider == self.name {
+            self.api_key = Some(api_key.into());
+        }
+        Ok(())
+    }
+
+    fn get_openday_price(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, 4>,
+        currencies: &[String<8>],
+    ) -> Result<Prices<4>, CryptoApiError> {
+        self.get_current_prices(network, symbols, currencies)
+    }
+
+    fn get_current_prices(
+        &mut self,
+        _network: &mut StackT,
+        symbols: &Vec<String<16>, 4>,
+        currencies: &[String<8>],
+    ) -> Result<Prices<4>, CryptoApiError> {
+        self.connected = true;
+        self.requested.push(symbols.clone()).unwrap();
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let mut prices = Prices::new();
+        for &(symbol, currency, price) in self.prices.iter() {
+            if symbols.contains(&String::from(symbol))
+                && currencies.contains(&String::from(currency))
+            {
+                insert_quote(&mut prices, symbol, currency, price).unwrap();
+            }
+        }
+        Ok(prices)
+    }
+
+    //the price is taken for the high, and there are no other values
+    fn get_market_stats(
+        &mut self,
+        network: &mut StackT,
+        symbols: &Vec<String<16>, 4>,
+        currency: &String<8>,
+    ) -> Result<MarketStatsMap<4>, CryptoApiError> {
+        let prices = self.get_current_prices(network, symbols, core::slice::from_ref(currency))?;
+
+        Ok(prices
+            .iter()
+            .map(|(symbol, quotes)| {
+                let stats = MarketStats {
+                    high_24h: quotes.get(currency).copied(),
+                    ..MarketStats::default()
+                };
+                (symbol.clone(), stats)
+            })
+            .collect())
+    }
+
+    //a single candle at the price
+    fn get_history(
+        &mut self,
+        network: &mut StackT,
+        symbol: &String<16>,
+        currency: &String<8>,
+        _interval: Interval,
+        _count: usize,
+    ) -> Result<History, CryptoApiError> {
+        let symbols = Vec::from_slice(core::slice::from_ref(symbol)).unwrap();
+        let prices = self.get_current_prices(network, &symbols, core::slice::from_ref(currency))?;
+        let price = price(&prices, symbol, currency).ok_or(CryptoApiError::UnknownSymbol)?;
+
+        let candle = Candle {
+            time: 0,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+        };
+        Ok(Vec::from_slice(&[candle]).unwrap())
+    }
+
+    fn list_symbols(
+        &mut self,
+        _network: &mut StackT,
+        currency: &String<8>,
+        on_symbol: &mut dyn FnMut(&str),
+    ) -> Result<(), CryptoApiError> {
+        self.connected = true;
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        for &(symbol, quote, _price) in self.prices.iter() {
+            if currency == quote {
+                on_symbol(symbol);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+//! The CryptoCompare client against `dice-mock-server`, over the sockets of the host
+
+use dice_http_client::crypto_api_client::{CryptoApiClient, CryptoApiError, Prices};
+use dice_http_client::cryptocompare_api_client::CryptoCompareApiClient;
+use dice_http_client::resolver::StaticResolver;
+use dice_mock_server::accept_all;
+use dice_mock_server::script::Script;
+use drogue_network::addr::{HostSocketAddr, IpAddr};
+use drogue_network::tcp::{Mode, TcpError, TcpStack};
+use heapless::{String, Vec};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const PRICES: &str = "price BTC USD 43127 43718\n\
+                      price ETH USD 2245.68 2290\n";
+
+/// Blocking `TcpStack` on std sockets. Reads time out, so a test fails instead of hanging.
+struct StdStack;
+
+impl TcpStack for StdStack {
+    type TcpSocket = Option<TcpStream>;
+    type Error = TcpError;
+
+    fn open(&self, _mode: Mode) -> Result<Option<TcpStream>, TcpError> {
+        Ok(None)
+    }
+
+    fn connect(
+        &self,
+        _socket: Option<TcpStream>,
+        remote: HostSocketAddr,
+    ) -> Result<Option<TcpStream>, TcpError> {
+        let ip = match remote.addr().ip() {
+            IpAddr::V4(ip) => ip.octets(),
+            _ => return Err(TcpError::ConnectionRefused),
+        };
+        let address = SocketAddr::from((Ipv4Addr::from(ip), remote.port()));
+
+        let stream = TcpStream::connect(address).map_err(|_| TcpError::ConnectionRefused)?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .map_err(|_| TcpError::SocketNotOpen)?;
+        Ok(Some(stream))
+    }
+
+    fn is_connected(&self, socket: &Option<TcpStream>) -> Result<bool, TcpError> {
+        Ok(socket.is_some())
+    }
+
+    fn write(&self, socket: &mut Option<TcpStream>, buffer: &[u8]) -> nb::Result<usize, TcpError> {
+        let stream = socket.as_mut().ok_or(TcpError::SocketNotOpen)?;
+        stream
+            .write(buffer)
+            .map_err(|_| nb::Error::Other(TcpError::WriteError))
+    }
+
+    fn read(
+        &self,
+        socket: &mut Option<TcpStream>,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, TcpError> {
+        let stream = socket.as_mut().ok_or(TcpError::SocketNotOpen)?;
+        stream
+            .read(buffer)
+            .map_err(|error| nb::Error::Other(read_error(error)))
+    }
+
+    fn close(&self, _socket: Option<TcpStream>) -> Result<(), TcpError> {
+        Ok(())
+    }
+}
+
+fn read_error(error: io::Error) -> TcpError {
+    match error.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => TcpError::Timeout,
+        _ => TcpError::ReadError,
+    }
+}
+
+/// Serves `script` on a free port of the loopback and returns a client of it
+fn client_of(script: &str) -> CryptoCompareApiClient<StdStack> {
+    let script = Arc::new(Mutex::new(Script::parse(script).unwrap()));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || accept_all(listener, &script, Ok));
+
+    //The client keeps the base URL for its whole life, as it does the API's
+    let base_url = format!("http://127.0.0.1:{}", port);
+    CryptoCompareApiClient::with_endpoint(
+        Box::leak(base_url.into_boxed_str()),
+        StaticResolver::new(&[]),
+    )
+}
+
+fn symbols(symbols: &[&str]) -> Vec<String<16>, 4> {
+    symbols.iter().map(|&symbol| String::from(symbol)).collect()
+}
+
+fn currencies(currencies: &[&str]) -> [String<8>; 1] {
+    [String::from(currencies[0])]
+}
+
+fn get_prices(client: &mut CryptoCompareApiClient<StdStack>) -> Result<Prices<4>, CryptoApiError> {
+    client.get_current_prices(
+        &mut StdStack,
+        &symbols(&["BTC", "ETH"]),
+        &currencies(&["USD"]),
+    )
+}
+
+fn price(prices: &Prices<4>, symbol: &str, currency: &str) -> Option<f32> {
+    let quotes = prices.get(&String::from(symbol))?;
+    quotes.get(&String::from(currency)).copied()
+}
+
+#[test]
+fn current_prices() {
+    let mut client = client_of(&format!("{}ok\n", PRICES));
+
+    let prices = get_prices(&mut client).unwrap();
+
+    assert_eq!(prices.len(), 2);
+    assert_eq!(price(&prices, "BTC", "USD"), Some(43127.0));
+    assert_eq!(price(&prices, "ETH", "USD"), Some(2245.68));
+}
+
+#[test]
+fn openday_prices_in_chunks() {
+    let mut client = client_of(&format!("{}chunked 7 ok\n", PRICES));
+
+    let prices = client
+        .get_openday_price(
+            &mut StdStack,
+            &symbols(&["BTC", "ETH"]),
+            &currencies(&["USD"]),
+        )
+        .unwrap();
+
+    assert_eq!(price(&prices, "BTC", "USD"), Some(43718.0));
+    assert_eq!(price(&prices, "ETH", "USD"), Some(2290.0));
+}
+
+#[test]
+fn rate_limit() {
+    let mut client = client_of(&format!("{}limit 30\nok\n", PRICES));
+
+    assert_eq!(
+        get_prices(&mut client),
+        Err(CryptoApiError::Status {
+            status: 429,
+            retry_after: Some(30),
+        })
+    );
+    assert!(get_prices(&mut client).is_ok());
+}
+
+#[test]
+fn kept_alive_connection_closed() {
+    //The server closes the idle connection, the request is sent again on a new one
+    let mut client = client_of(&format!("{}ok\nclose\nok\n", PRICES));
+
+    assert!(get_prices(&mut client).is_ok());
+    assert!(get_prices(&mut client).is_ok());
+}
+
+#[test]
+fn new_connection_closed() {
+    let mut client = client_of(&format!("{}close\nok\n", PRICES));
+
+    assert_eq!(
+        get_prices(&mut client),
+        Err(CryptoApiError::ConnectionClosed)
+    );
+    assert!(get_prices(&mut client).is_ok());
+}
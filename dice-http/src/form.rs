@@ -0,0 +1,363 @@
+//! Decoder for `application/x-www-form-urlencoded` request bodies.
+//! Handles `+` as space, `%XX` escapes, repeated keys and empty values.
+//! Malformed input never panics, it is reported as a `FormError` instead.
+
+use core::str;
+use heapless::{String, Vec};
+
+#[derive(Debug, PartialEq)]
+pub enum FormError {
+    /// `%` was not followed by two hexadecimal digits
+    InvalidPercentEncoding,
+    /// Decoded bytes are not valid UTF-8
+    InvalidUtf8,
+    /// Decoded key or value doesn't fit into its buffer
+    CapacityExceeded,
+    /// Body contains more pairs than the form can hold
+    TooManyPairs,
+}
+
+/// Iterator over raw (still encoded) `key=value` pairs of a form body.
+/// Empty segments (`a=1&&b=2`) are skipped and a pair without `=` yields an empty value.
+pub struct RawPairs<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> RawPairs<'a> {
+    pub fn new(body: &'a [u8]) -> Self {
+        RawPairs { remaining: body }
+    }
+}
+
+impl<'a> Iterator for RawPairs<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.remaining.is_empty() {
+            let (segment, rest) = match self.remaining.iter().position(|&b| b == b'&') {
+                Some(pos) => (&self.remaining[..pos], &self.remaining[pos + 1..]),
+                None => (self.remaining, &self.remaining[self.remaining.len()..]),
+            };
+            self.remaining = rest;
+
+            if segment.is_empty() {
+                continue;
+            }
+
+            return match segment.iter().position(|&b| b == b'=') {
+                Some(pos) => Some((&segment[..pos], &segment[pos + 1..])),
+                None => Some((segment, &segment[segment.len()..])),
+            };
+        }
+
+        None
+    }
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decodes a single form component (key or value).
+/// # Arguments
+/// * `raw` - Encoded component, as yielded by `RawPairs`
+pub fn decode<const SIZE: usize>(raw: &[u8]) -> Result<String<SIZE>, FormError> {
+    let mut bytes = Vec::<u8, SIZE>::new();
+    let mut i = 0;
+
+    while i < raw.len() {
+        let byte = match raw[i] {
+            b'+' => b' ',
+            b'%' => {
+                let high = raw.get(i + 1).copied().and_then(hex_value);
+                let low = raw.get(i + 2).copied().and_then(hex_value);
+                match (high, low) {
+                    (Some(high), Some(low)) => {
+                        i += 2;
+                        (high << 4) | low
+                    }
+                    _ => return Err(FormError::InvalidPercentEncoding),
+                }
+            }
+            other => other,
+        };
+
+        bytes.push(byte).map_err(|_| FormError::CapacityExceeded)?;
+        i += 1;
+    }
+
+    let decoded = str::from_utf8(&bytes).map_err(|_| FormError::InvalidUtf8)?;
+
+    let mut string = String::new();
+    string
+        .push_str(decoded)
+        .map_err(|_| FormError::CapacityExceeded)?;
+    Ok(string)
+}
+
+/// Encodes `input` as a form component and appends it to `output`.
+/// # Arguments
+/// * `output` - String the encoded component is appended to
+/// * `input` - Text to encode
+pub fn encode_into<const SIZE: usize>(
+    output: &mut String<SIZE>,
+    input: &str,
+) -> Result<(), FormError> {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+    for &byte in input.as_bytes() {
+        let result = match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'*' => {
+                output.push(byte as char)
+            }
+            b' ' => output.push('+'),
+            _ => output.push('%').and_then(|_| {
+                output.push(HEX[(byte >> 4) as usize] as char)?;
+                output.push(HEX[(byte & 0xF) as usize] as char)
+            }),
+        };
+
+        result.map_err(|_| FormError::CapacityExceeded)?;
+    }
+
+    Ok(())
+}
+
+/// Appends an encoded `key=value` pair to a form body, separating it from previous pairs with `&`.
+pub fn append_pair<const SIZE: usize>(
+    output: &mut String<SIZE>,
+    key: &str,
+    value: &str,
+) -> Result<(), FormError> {
+    if !output.is_empty() {
+        output.push('&').map_err(|_| FormError::CapacityExceeded)?;
+    }
+
+    encode_into(output, key)?;
+    output.push('=').map_err(|_| FormError::CapacityExceeded)?;
+    encode_into(output, value)
+}
+
+/// A decoded form body. Pairs are kept in the order they were sent, including repeated keys.
+pub struct Form<const KEY_SIZE: usize, const VALUE_SIZE: usize, const CAPACITY: usize> {
+    pairs: Vec<(String<KEY_SIZE>, String<VALUE_SIZE>), CAPACITY>,
+}
+
+impl<const KEY_SIZE: usize, const VALUE_SIZE: usize, const CAPACITY: usize>
+    Form<KEY_SIZE, VALUE_SIZE, CAPACITY>
+{
+    /// Parse and decode a whole form body.
+    /// # Arguments
+    /// * `body` - Request body in `application/x-www-form-urlencoded` format
+    pub fn parse(body: &[u8]) -> Result<Self, FormError> {
+        let mut pairs = Vec::new();
+
+        for (key, value) in RawPairs::new(body) {
+            let pair = (decode(key)?, decode(value)?);
+            pairs.push(pair).map_err(|_| FormError::TooManyPairs)?;
+        }
+
+        Ok(Form { pairs })
+    }
+
+    /// Returns the first value sent under `key`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k.as_str() == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns all values sent under `key`, in order
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.pairs
+            .iter()
+            .filter(move |(k, _)| k.as_str() == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type TestForm = Form<16, 32, 16>;
+
+    #[test]
+    fn simple_pairs() {
+        let form = TestForm::parse(b"BTC=on&ETH=on&1INCH=on").unwrap();
+
+        assert_eq!(form.len(), 3);
+        assert_eq!(form.get("BTC"), Some("on"));
+        assert_eq!(form.get("1INCH"), Some("on"));
+        assert_eq!(form.get("DOGE"), None);
+    }
+
+    #[test]
+    fn plus_and_percent_decoding() {
+        let form = TestForm::parse(b"name=hello+world&sym=%31INCH&pct=100%25&utf=%C5%82").unwrap();
+
+        assert_eq!(form.get("name"), Some("hello world"));
+        assert_eq!(form.get("sym"), Some("1INCH"));
+        assert_eq!(form.get("pct"), Some("100%"));
+        assert_eq!(form.get("utf"), Some("ł"));
+    }
+
+    #[test]
+    fn repeated_keys_keep_order() {
+        let form = TestForm::parse(b"sym=BTC&other=1&sym=ETH&sym=ADA").unwrap();
+
+        assert_eq!(form.get("sym"), Some("BTC"));
+
+        let mut values = form.get_all("sym");
+        assert_eq!(values.next(), Some("BTC"));
+        assert_eq!(values.next(), Some("ETH"));
+        assert_eq!(values.next(), Some("ADA"));
+        assert_eq!(values.next(), None);
+    }
+
+    #[test]
+    fn empty_values_and_segments() {
+        let form = TestForm::parse(b"&a=&&b&c==&").unwrap();
+
+        assert_eq!(form.len(), 3);
+        assert_eq!(form.get("a"), Some(""));
+        assert_eq!(form.get("b"), Some(""));
+        assert_eq!(form.get("c"), Some("="));
+    }
+
+    #[test]
+    fn empty_body() {
+        assert!(TestForm::parse(b"").unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert_eq!(
+            TestForm::parse(b"a=%").err(),
+            Some(FormError::InvalidPercentEncoding)
+        );
+        assert_eq!(
+            TestForm::parse(b"a=%4").err(),
+            Some(FormError::InvalidPercentEncoding)
+        );
+        assert_eq!(
+            TestForm::parse(b"a=%zz").err(),
+            Some(FormError::InvalidPercentEncoding)
+        );
+        assert_eq!(
+            TestForm::parse(b"a=%FF%FE").err(),
+            Some(FormError::InvalidUtf8)
+        );
+        assert_eq!(
+            TestForm::parse(b"averyveryverylongkey=1").err(),
+            Some(FormError::CapacityExceeded)
+        );
+        assert_eq!(
+            Form::<4, 4, 2>::parse(b"a=1&b=2&c=3").err(),
+            Some(FormError::TooManyPairs)
+        );
+    }
+
+    //Simple xorshift generator, good enough to produce reproducible garbage
+    struct Garbage(u32);
+
+    impl Garbage {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+    }
+
+    fn encode<const SIZE: usize>(input: &str) -> String<SIZE> {
+        let mut output = String::new();
+        encode_into(&mut output, input).unwrap();
+        output
+    }
+
+    #[test]
+    fn append_encoded_pairs() {
+        let mut body = String::<64>::new();
+        append_pair(&mut body, "name", "hello world").unwrap();
+        append_pair(&mut body, "sym", "a&b=c%").unwrap();
+
+        assert_eq!(body, "name=hello+world&sym=a%26b%3Dc%25");
+
+        let mut short = String::<4>::new();
+        assert_eq!(
+            append_pair(&mut short, "k", "%%"),
+            Err(FormError::CapacityExceeded)
+        );
+    }
+
+    #[test]
+    fn fuzz_random_bodies_never_panic() {
+        const ALPHABET: &[u8] = b"%&=+aZ09fF\0\xff\xc5\x82 ";
+        let mut rng = Garbage(0x1234_5678);
+
+        for _ in 0..20000 {
+            let len = (rng.next() % 48) as usize;
+            let mut body = [0u8; 48];
+            for byte in body[..len].iter_mut() {
+                *byte = ALPHABET[(rng.next() as usize) % ALPHABET.len()];
+            }
+
+            let _ = TestForm::parse(&body[..len]);
+            let _ = Form::<2, 2, 2>::parse(&body[..len]);
+        }
+    }
+
+    #[test]
+    fn fuzz_encode_decode_roundtrip() {
+        const CHARACTERS: [char; 12] = ['a', 'Z', '7', ' ', '+', '%', '&', '=', 'ł', '€', '.', '~'];
+        let mut rng = Garbage(0xDEAD_BEEF);
+
+        for _ in 0..5000 {
+            let mut key = String::<8>::new();
+            let mut value = String::<8>::new();
+            for _ in 0..(rng.next() % 3) {
+                key.push(CHARACTERS[(rng.next() as usize) % CHARACTERS.len()])
+                    .unwrap();
+            }
+            for _ in 0..(rng.next() % 3) {
+                value
+                    .push(CHARACTERS[(rng.next() as usize) % CHARACTERS.len()])
+                    .unwrap();
+            }
+
+            //empty keys without a value are skipped by design
+            if key.is_empty() {
+                key.push('k').unwrap();
+            }
+
+            let mut body = encode::<64>(key.as_str());
+            body.push('=').unwrap();
+            body.push_str(encode::<64>(value.as_str()).as_str())
+                .unwrap();
+            body.push_str("&x=1").unwrap();
+
+            let form = TestForm::parse(body.as_bytes()).unwrap();
+            assert_eq!(form.len(), 2);
+            assert_eq!(form.iter().next(), Some((key.as_str(), value.as_str())));
+        }
+    }
+}
@@ -3,7 +3,8 @@
 //! A very simple HTTP server designed to work in no_std environments.
 //! Uses smoltcp TCP sockets for communication.
 //! Currently only supports HEAD, GET and POST requests.
-//! Currently parsing HTTP headers is not implemented and they're ignored.
+//! Currently parsing HTTP headers is not implemented and they're ignored,
+//! except for upload routes which need Content-Type and Content-Length.
 
 use spin::MutexGuard;
 
@@ -18,12 +19,42 @@ use smoltcp::{
 pub use httparse::Request;
 
 pub mod default_pages;
+pub mod form;
+pub mod multipart;
 pub mod response;
 
+use multipart::{MultipartError, MultipartEvent, MultipartParser};
+
+/// Maximum size of the header block of a single multipart part
+const PART_HEADER_SIZE: usize = 512;
+
 pub enum ServerError {
     RouteCapacityExceeded,
 }
 
+/// Handler of a streaming multipart/form-data upload route.
+pub struct UploadHandler<const RESPONSE_SIZE: usize> {
+    /// Called for every part start, data chunk and part end, as the body arrives.
+    pub on_event: fn(&MultipartEvent),
+    /// Called once the whole body was received or the upload failed.
+    /// Is expected to return a valid Http response.
+    pub on_complete: fn(Result<(), MultipartError>) -> String<RESPONSE_SIZE>,
+}
+
+struct Upload<const RESPONSE_SIZE: usize> {
+    parser: MultipartParser<PART_HEADER_SIZE>,
+    handler: UploadHandler<RESPONSE_SIZE>,
+    remaining: usize,
+}
+
+//Everything the server needs to know about a request to start an upload
+struct UploadRequest {
+    path: String<128>,
+    boundary: String<70>,
+    content_length: usize,
+    body_offset: usize,
+}
+
 pub struct HttpServer<
     const URL_SIZE: usize,
     const RESPONSE_SIZE: usize,
@@ -39,6 +70,8 @@ pub struct HttpServer<
         fn(Request, &[u8]) -> String<RESPONSE_SIZE>,
         ROUTE_CAPACITY,
     >,
+    upload_routes: FnvIndexMap<String<URL_SIZE>, UploadHandler<RESPONSE_SIZE>, ROUTE_CAPACITY>,
+    upload: Option<Upload<RESPONSE_SIZE>>,
     timeout_counter: u32,
 }
 
@@ -67,6 +100,8 @@ impl<
             socket_handle,
             endpoint,
             routes,
+            upload_routes: FnvIndexMap::new(),
+            upload: None,
             timeout_counter: 0,
         }
     }
@@ -89,10 +124,20 @@ impl<
             socket.listen(self.endpoint).unwrap();
         }
 
+        if self.upload.is_some() && !socket.is_active() {
+            //Client went away in the middle of an upload
+            let upload = self.upload.take().unwrap();
+            (upload.handler.on_complete)(Err(MultipartError::UnexpectedEnd));
+        }
+
         if socket.may_recv() {
             match socket.recv_slice(&mut self.rx_buffer) {
-                Ok(_) => {
-                    self.handle_request(&mut socket);
+                Ok(received) => {
+                    if self.upload.is_some() {
+                        self.continue_upload(&mut socket, received);
+                    } else {
+                        self.handle_request(&mut socket, received);
+                    }
                 }
                 Err(_) => {
                     socket.close();
@@ -102,7 +147,7 @@ impl<
             self.rx_buffer.fill(0);
         }
 
-        if socket.may_send() {
+        if socket.may_send() && self.upload.is_none() {
             socket.close();
             self.timeout_counter = 0;
         }
@@ -144,7 +189,29 @@ impl<
         }
     }
 
-    fn handle_request(&mut self, socket: &mut SocketRef<TcpSocket>) {
+    /// Add a new route accepting multipart/form-data POST requests.
+    /// Unlike regular routes, the body is not limited by the size of the receive buffer.
+    /// It is parsed as it arrives and handed to `handler` in chunks.
+    /// # Arguments
+    /// * `path` - Route path. Examples: "/", "/resource", "/foo/bar"
+    /// * `handler` - Callbacks receiving the uploaded parts and producing the response
+    pub fn add_upload_route(
+        &mut self,
+        path: &str,
+        handler: UploadHandler<RESPONSE_SIZE>,
+    ) -> Result<(), ServerError> {
+        match self.upload_routes.insert(String::from(path), handler) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(ServerError::RouteCapacityExceeded),
+        }
+    }
+
+    fn handle_request(&mut self, socket: &mut SocketRef<TcpSocket>, received: usize) {
+        if let Some(upload_request) = self.parse_upload_request(received) {
+            self.start_upload(socket, upload_request, received);
+            return;
+        }
+
         let mut request_headers = [EMPTY_HEADER; HEADER_BUFFER_LENGTH];
         let parse_result = parse_request(&mut self.rx_buffer, &mut request_headers);
 
@@ -188,6 +255,122 @@ impl<
             }
         }
     }
+
+    //Returns Some if the request is a POST to one of the upload routes
+    fn parse_upload_request(&self, received: usize) -> Option<UploadRequest> {
+        let mut request_headers = [EMPTY_HEADER; HEADER_BUFFER_LENGTH];
+        let mut request = httparse::Request::new(&mut request_headers);
+
+        let body_offset = match request.parse(&self.rx_buffer[..received]) {
+            Ok(status) if status.is_complete() => status.unwrap(),
+            _ => return None,
+        };
+
+        if request.method != Some("POST") || request.path.is_none() {
+            return None;
+        }
+
+        let path = request.path.unwrap();
+
+        if path.len() > URL_SIZE || !self.upload_routes.contains_key(&String::from(path)) {
+            return None;
+        }
+
+        let mut content_type = None;
+        let mut content_length = None;
+
+        for header in request.headers.iter() {
+            let value = core::str::from_utf8(header.value).ok();
+            if header.name.eq_ignore_ascii_case("Content-Type") {
+                content_type = value;
+            } else if header.name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.and_then(|value| value.trim().parse::<usize>().ok());
+            }
+        }
+
+        let boundary = content_type.and_then(multipart::boundary_from_content_type)?;
+
+        if boundary.len() > 70 {
+            return None;
+        }
+
+        Some(UploadRequest {
+            path: String::from(path),
+            boundary: String::from(boundary),
+            content_length: content_length?,
+            body_offset,
+        })
+    }
+
+    fn start_upload(
+        &mut self,
+        socket: &mut SocketRef<TcpSocket>,
+        request: UploadRequest,
+        received: usize,
+    ) {
+        let handler = match self.upload_routes.get(&String::from(request.path.as_str())) {
+            Some(handler) => UploadHandler {
+                on_event: handler.on_event,
+                on_complete: handler.on_complete,
+            },
+            None => return,
+        };
+
+        let parser = match MultipartParser::new(request.boundary.as_str()) {
+            Ok(parser) => parser,
+            Err(err) => {
+                send_response(socket, (handler.on_complete)(Err(err)));
+                return;
+            }
+        };
+
+        self.upload = Some(Upload {
+            parser,
+            handler,
+            remaining: request.content_length,
+        });
+
+        let body_length = received - request.body_offset;
+        self.feed_upload(socket, request.body_offset, body_length);
+    }
+
+    fn continue_upload(&mut self, socket: &mut SocketRef<TcpSocket>, received: usize) {
+        if received > 0 {
+            self.feed_upload(socket, 0, received);
+        }
+    }
+
+    fn feed_upload(&mut self, socket: &mut SocketRef<TcpSocket>, offset: usize, length: usize) {
+        let upload = self.upload.as_mut().unwrap();
+
+        //Don't read past the body, whatever comes after it is not ours
+        let length = length.min(upload.remaining);
+        upload.remaining -= length;
+
+        let on_event = upload.handler.on_event;
+        let result = upload
+            .parser
+            .feed(&self.rx_buffer[offset..offset + length], |event| {
+                on_event(&event)
+            });
+
+        let result = match result {
+            Ok(()) if upload.remaining == 0 => upload.parser.finish(),
+            Ok(()) => return,
+            Err(err) => Err(err),
+        };
+
+        let upload = self.upload.take().unwrap();
+        send_response(socket, (upload.handler.on_complete)(result));
+    }
+}
+
+fn send_response<const SIZE: usize>(socket: &mut SocketRef<TcpSocket>, response: String<SIZE>) {
+    if socket.send_slice(response.as_bytes()).is_err() {
+        //For some reason, we couldn't send a response. Close connection
+        socket.close();
+        socket.abort();
+    }
 }
 
 fn parse_request<'a, 'b>(
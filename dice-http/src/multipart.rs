@@ -0,0 +1,457 @@
+//! Streaming `multipart/form-data` parser.
+//! The body can be fed in chunks of any size, so uploads larger than the receive buffer
+//! are handed to the handler piece by piece instead of being buffered whole.
+
+use core::str;
+use heapless::Vec;
+use httparse::{Status, EMPTY_HEADER};
+
+/// RFC 2046 limits the boundary to 70 characters. We store it with the leading "\r\n--".
+const MAX_DELIMITER_LENGTH: usize = 74;
+const MAX_PART_HEADERS: usize = 8;
+
+#[derive(Debug, PartialEq)]
+pub enum MultipartError {
+    /// Boundary is empty or longer than 70 characters
+    InvalidBoundary,
+    /// Part headers don't fit into the header buffer
+    HeadersTooLong,
+    /// Part headers couldn't be parsed
+    MalformedHeaders,
+    /// Garbage found after a boundary
+    MalformedBoundary,
+    /// The body ended before the closing boundary
+    UnexpectedEnd,
+}
+
+/// Information about a part, taken from its `Content-Disposition` and `Content-Type` headers
+#[derive(Debug, PartialEq)]
+pub struct PartInfo<'a> {
+    pub name: Option<&'a str>,
+    pub filename: Option<&'a str>,
+    pub content_type: Option<&'a str>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MultipartEvent<'a> {
+    /// A new part begins. Its data follows in one or more `Data` events.
+    PartStart(PartInfo<'a>),
+    /// A piece of the current part's content
+    Data(&'a [u8]),
+    /// The current part is complete
+    PartEnd,
+    /// The closing boundary was found. Nothing else will be reported.
+    Finished,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Preamble,
+    AfterBoundary,
+    AfterBoundaryDash,
+    AfterBoundaryCr,
+    Headers,
+    Body,
+    Epilogue,
+}
+
+/// Extracts the boundary parameter from a `multipart/form-data` Content-Type header value
+pub fn boundary_from_content_type(content_type: &str) -> Option<&str> {
+    let mut params = content_type.split(';');
+    let mime = params.next()?.trim();
+
+    if !mime.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+
+    params.find_map(|param| {
+        let (key, value) = split_param(param)?;
+        if key.eq_ignore_ascii_case("boundary") {
+            Some(value)
+        } else {
+            None
+        }
+    })
+}
+
+fn split_param(param: &str) -> Option<(&str, &str)> {
+    let position = param.find('=')?;
+    let key = param[..position].trim();
+    let value = param[position + 1..].trim().trim_matches('"');
+    Some((key, value))
+}
+
+fn parse_part_info<'a>(headers: &[httparse::Header<'a>]) -> PartInfo<'a> {
+    let mut info = PartInfo {
+        name: None,
+        filename: None,
+        content_type: None,
+    };
+
+    for header in headers {
+        let value = match str::from_utf8(header.value) {
+            Ok(value) => value.trim(),
+            Err(_) => continue,
+        };
+
+        if header.name.eq_ignore_ascii_case("Content-Type") {
+            info.content_type = Some(value);
+        } else if header.name.eq_ignore_ascii_case("Content-Disposition") {
+            for (key, value) in value.split(';').skip(1).filter_map(split_param) {
+                if key.eq_ignore_ascii_case("name") {
+                    info.name = Some(value);
+                } else if key.eq_ignore_ascii_case("filename") {
+                    info.filename = Some(value);
+                }
+            }
+        }
+    }
+
+    info
+}
+
+/// Push-based multipart parser.
+/// `HEADER_SIZE` - Maximum size of a single part's header block
+pub struct MultipartParser<const HEADER_SIZE: usize> {
+    delimiter: Vec<u8, MAX_DELIMITER_LENGTH>,
+    //Number of delimiter bytes matched at the end of the data fed so far
+    matched: usize,
+    state: State,
+    headers: Vec<u8, HEADER_SIZE>,
+}
+
+impl<const HEADER_SIZE: usize> MultipartParser<HEADER_SIZE> {
+    /// Create a new parser
+    /// # Arguments
+    /// * `boundary` - Boundary from the request's Content-Type header, without leading dashes
+    pub fn new(boundary: &str) -> Result<Self, MultipartError> {
+        if boundary.is_empty() || boundary.len() > MAX_DELIMITER_LENGTH - 4 {
+            return Err(MultipartError::InvalidBoundary);
+        }
+
+        let mut delimiter = Vec::new();
+        delimiter.extend_from_slice(b"\r\n--").ok();
+        delimiter.extend_from_slice(boundary.as_bytes()).ok();
+
+        Ok(MultipartParser {
+            delimiter,
+            //The first boundary may not be preceded by CRLF, so we pretend it was already there
+            matched: 2,
+            state: State::Preamble,
+            headers: Vec::new(),
+        })
+    }
+
+    /// True after the closing boundary was parsed
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Epilogue
+    }
+
+    /// Signal the end of the body. Fails if the closing boundary wasn't found.
+    pub fn finish(&self) -> Result<(), MultipartError> {
+        if self.is_finished() {
+            Ok(())
+        } else {
+            Err(MultipartError::UnexpectedEnd)
+        }
+    }
+
+    /// Feed the next chunk of the body to the parser.
+    /// # Arguments
+    /// * `data` - Next chunk of the request body
+    /// * `on_event` - Called for every event found in this chunk
+    pub fn feed<F: FnMut(MultipartEvent)>(
+        &mut self,
+        data: &[u8],
+        mut on_event: F,
+    ) -> Result<(), MultipartError> {
+        let mut i = 0;
+
+        while i < data.len() {
+            match self.state {
+                State::Preamble | State::Body => {
+                    i += self.scan_for_delimiter(&data[i..], &mut on_event);
+                }
+                State::AfterBoundary => {
+                    match data[i] {
+                        b'-' => self.state = State::AfterBoundaryDash,
+                        b'\r' => self.state = State::AfterBoundaryCr,
+                        //transport padding
+                        b' ' | b'\t' => {}
+                        _ => return Err(MultipartError::MalformedBoundary),
+                    }
+                    i += 1;
+                }
+                State::AfterBoundaryDash => {
+                    if data[i] != b'-' {
+                        return Err(MultipartError::MalformedBoundary);
+                    }
+                    self.state = State::Epilogue;
+                    on_event(MultipartEvent::Finished);
+                    i += 1;
+                }
+                State::AfterBoundaryCr => {
+                    if data[i] != b'\n' {
+                        return Err(MultipartError::MalformedBoundary);
+                    }
+                    self.headers.clear();
+                    self.state = State::Headers;
+                    i += 1;
+                }
+                State::Headers => {
+                    i += self.collect_headers(&data[i..], &mut on_event)?;
+                }
+                State::Epilogue => {
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    //Returns the number of bytes consumed. Stops right after a complete delimiter.
+    fn scan_for_delimiter<F: FnMut(MultipartEvent)>(
+        &mut self,
+        data: &[u8],
+        on_event: &mut F,
+    ) -> usize {
+        let in_body = self.state == State::Body;
+        let mut run_start = 0;
+
+        for (i, &byte) in data.iter().enumerate() {
+            if byte == self.delimiter[self.matched] {
+                self.matched += 1;
+
+                if self.matched == self.delimiter.len() {
+                    //Data before the delimiter's first byte in this chunk belongs to the part
+                    let delimiter_start = (i + 1).saturating_sub(self.matched);
+                    if in_body && delimiter_start > run_start {
+                        on_event(MultipartEvent::Data(&data[run_start..delimiter_start]));
+                    }
+                    if in_body {
+                        on_event(MultipartEvent::PartEnd);
+                    }
+
+                    self.matched = 0;
+                    self.state = State::AfterBoundary;
+                    return i + 1;
+                }
+                continue;
+            }
+
+            if self.matched > 0 {
+                //The partial match was data after all. The part of it that came from earlier
+                //chunks is no longer in `data`, so it's replayed from the delimiter itself.
+                let carried = self.matched.saturating_sub(i - run_start);
+                if in_body && carried > 0 {
+                    on_event(MultipartEvent::Data(&self.delimiter[..carried]));
+                }
+
+                //CR only appears at the start of the delimiter, so it's the only possible restart
+                self.matched = if byte == self.delimiter[0] { 1 } else { 0 };
+                if self.matched == 1 {
+                    if in_body && i > run_start {
+                        on_event(MultipartEvent::Data(&data[run_start..i]));
+                    }
+                    run_start = i;
+                }
+            }
+        }
+
+        //Keep a trailing partial match back, it might be the start of a delimiter
+        let pending_start = data.len().saturating_sub(self.matched).max(run_start);
+        if in_body && pending_start > run_start {
+            on_event(MultipartEvent::Data(&data[run_start..pending_start]));
+        }
+
+        data.len()
+    }
+
+    fn collect_headers<F: FnMut(MultipartEvent)>(
+        &mut self,
+        data: &[u8],
+        on_event: &mut F,
+    ) -> Result<usize, MultipartError> {
+        for (i, &byte) in data.iter().enumerate() {
+            self.headers
+                .push(byte)
+                .map_err(|_| MultipartError::HeadersTooLong)?;
+
+            let complete = self.headers.ends_with(b"\r\n\r\n") || &self.headers[..] == b"\r\n";
+
+            if complete {
+                let mut headers = [EMPTY_HEADER; MAX_PART_HEADERS];
+
+                let info = if &self.headers[..] == b"\r\n" {
+                    parse_part_info(&[])
+                } else {
+                    match httparse::parse_headers(&self.headers, &mut headers) {
+                        Ok(Status::Complete((_, parsed))) => parse_part_info(parsed),
+                        _ => return Err(MultipartError::MalformedHeaders),
+                    }
+                };
+
+                on_event(MultipartEvent::PartStart(info));
+                self.state = State::Body;
+                return Ok(i + 1);
+            }
+        }
+
+        Ok(data.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::{string::String, vec::Vec};
+
+    const BODY: &[u8] = b"preamble\r\n--XyZ\r\n\
+Content-Disposition: form-data; name=\"logo\"; filename=\"logo.tga\"\r\n\
+Content-Type: image/x-tga\r\n\r\n\
+\x00\x01\r\n--Xy\r\n\x02\r\n\
+--XyZ\r\n\
+Content-Disposition: form-data; name=\"config\"\r\n\r\n\
+BTC=on&ETH=on\r\n\
+--XyZ--\r\nepilogue";
+
+    #[derive(Debug, PartialEq)]
+    struct Part {
+        name: Option<String>,
+        filename: Option<String>,
+        content_type: Option<String>,
+        data: Vec<u8>,
+        complete: bool,
+    }
+
+    fn parse_in_chunks(
+        body: &[u8],
+        chunk_size: usize,
+    ) -> Result<(Vec<Part>, bool), MultipartError> {
+        let mut parser = MultipartParser::<256>::new("XyZ").unwrap();
+        let mut parts: Vec<Part> = Vec::new();
+        let mut finished = false;
+
+        for chunk in body.chunks(chunk_size) {
+            parser.feed(chunk, |event| match event {
+                MultipartEvent::PartStart(info) => parts.push(Part {
+                    name: info.name.map(String::from),
+                    filename: info.filename.map(String::from),
+                    content_type: info.content_type.map(String::from),
+                    data: Vec::new(),
+                    complete: false,
+                }),
+                MultipartEvent::Data(data) => {
+                    parts.last_mut().unwrap().data.extend_from_slice(data)
+                }
+                MultipartEvent::PartEnd => parts.last_mut().unwrap().complete = true,
+                MultipartEvent::Finished => finished = true,
+            })?;
+        }
+
+        parser.finish()?;
+        Ok((parts, finished))
+    }
+
+    #[test]
+    fn boundary_from_header() {
+        assert_eq!(
+            boundary_from_content_type("multipart/form-data; boundary=----WebKit123"),
+            Some("----WebKit123")
+        );
+        assert_eq!(
+            boundary_from_content_type("Multipart/Form-Data;charset=utf-8; Boundary=\"a b\""),
+            Some("a b")
+        );
+        assert_eq!(
+            boundary_from_content_type("application/x-www-form-urlencoded"),
+            None
+        );
+        assert_eq!(boundary_from_content_type("multipart/form-data"), None);
+    }
+
+    #[test]
+    fn two_parts_any_chunk_size() {
+        for chunk_size in 1..BODY.len() + 1 {
+            let (parts, finished) = parse_in_chunks(BODY, chunk_size).unwrap();
+
+            assert!(finished, "chunk size {}", chunk_size);
+            assert_eq!(parts.len(), 2, "chunk size {}", chunk_size);
+
+            assert_eq!(parts[0].name.as_deref(), Some("logo"));
+            assert_eq!(parts[0].filename.as_deref(), Some("logo.tga"));
+            assert_eq!(parts[0].content_type.as_deref(), Some("image/x-tga"));
+            assert_eq!(
+                parts[0].data, b"\x00\x01\r\n--Xy\r\n\x02",
+                "chunk size {}",
+                chunk_size
+            );
+            assert!(parts[0].complete);
+
+            assert_eq!(parts[1].name.as_deref(), Some("config"));
+            assert_eq!(parts[1].filename, None);
+            assert_eq!(parts[1].data, b"BTC=on&ETH=on");
+            assert!(parts[1].complete);
+        }
+    }
+
+    #[test]
+    fn large_part_is_streamed() {
+        let mut body = Vec::new();
+        body.extend_from_slice(b"--XyZ\r\nContent-Disposition: form-data; name=\"file\"\r\n\r\n");
+        for i in 0..10000u32 {
+            body.push((i % 251) as u8);
+        }
+        body.extend_from_slice(b"\r\n--XyZ--");
+
+        let (parts, _) = parse_in_chunks(&body, 700).unwrap();
+
+        assert_eq!(parts[0].data.len(), 10000);
+        assert!(parts[0]
+            .data
+            .iter()
+            .enumerate()
+            .all(|(i, &b)| b == (i % 251) as u8));
+    }
+
+    #[test]
+    fn part_without_headers() {
+        let (parts, _) = parse_in_chunks(b"--XyZ\r\n\r\nabc\r\n--XyZ--", 3).unwrap();
+
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].name, None);
+        assert_eq!(parts[0].data, b"abc");
+    }
+
+    #[test]
+    fn truncated_body() {
+        assert_eq!(
+            parse_in_chunks(&BODY[..BODY.len() - 15], 16).err(),
+            Some(MultipartError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert_eq!(
+            parse_in_chunks(b"--XyZ!!", 4).err(),
+            Some(MultipartError::MalformedBoundary)
+        );
+        assert_eq!(
+            parse_in_chunks(b"--XyZ\r\nnot a header\r\n\r\n", 4).err(),
+            Some(MultipartError::MalformedHeaders)
+        );
+
+        let mut long_headers = Vec::from(&b"--XyZ\r\nX-Long: "[..]);
+        long_headers.resize(400, b'a');
+        assert_eq!(
+            parse_in_chunks(&long_headers, 64).err(),
+            Some(MultipartError::HeadersTooLong)
+        );
+
+        assert!(MultipartParser::<16>::new("").is_err());
+    }
+}
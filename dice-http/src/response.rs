@@ -3,13 +3,38 @@
 use crate::default_pages::NOT_FOUND_HTML;
 use heapless::String;
 
+/// Status line and headers of `ok_response`
+pub const OK_HEADER: &str = "HTTP/1.1 200 OK\r\n\r\n";
+
 pub fn ok_response<const SIZE: usize>(content: &str) -> String<SIZE> {
     let mut response = String::<SIZE>::new();
-    response.push_str("HTTP/1.1 200 OK\r\n\r\n").unwrap();
+    response.push_str(OK_HEADER).unwrap();
     response.push_str(content).unwrap();
     response
 }
 
+/// Like `ok_response`, but `None` if `content` doesn't fit in the response
+pub fn try_ok_response<const SIZE: usize>(content: &str) -> Option<String<SIZE>> {
+    let mut response = String::<SIZE>::new();
+    response.push_str(OK_HEADER).ok()?;
+    response.push_str(content).ok()?;
+    Some(response)
+}
+
+pub fn server_error_response<const SIZE: usize>() -> String<SIZE> {
+    let mut response = String::<SIZE>::new();
+    response
+        .push_str("HTTP/1.1 500 Internal Server Error\r\n\r\n")
+        .unwrap();
+    response
+}
+
+pub fn bad_request_response<const SIZE: usize>() -> String<SIZE> {
+    let mut response = String::<SIZE>::new();
+    response.push_str("HTTP/1.1 400 Bad Request\r\n\r\n").unwrap();
+    response
+}
+
 pub fn not_found_response<const SIZE: usize>() -> String<SIZE> {
     let mut response = String::<SIZE>::new();
     response.push_str("HTTP/1.1 404 Not Found\r\n\r\n").unwrap();
@@ -0,0 +1,208 @@
+//! Answers in the format of the CryptoCompare API
+
+use crate::http::{Answer, Request};
+use crate::script::{Kind, Prices, Quote};
+use std::fmt::Write;
+
+/// Answer to `request` for the scripted `kind`, or `None` if the connection is to be closed
+pub fn answer(request: &Request, kind: &Kind, prices: &Prices) -> Option<Answer> {
+    match kind {
+        Kind::Ok => Some(prices_answer(request, prices)),
+        Kind::Status(status) => Some(Answer::new(*status, String::new())),
+        Kind::RateLimit(seconds) => {
+            let mut answer = Answer::new(
+                429,
+                error_message("You are over your rate limit please upgrade your account!"),
+            );
+            answer.retry_after = Some(*seconds);
+            Some(answer)
+        }
+        Kind::Error(message) => Some(Answer::new(200, error_message(message))),
+        Kind::Close => None,
+    }
+}
+
+fn prices_answer(request: &Request, prices: &Prices) -> Answer {
+    if request.method != "GET" {
+        return Answer::new(405, error_message("Only GET requests are supported"));
+    }
+
+    let full = match request.path() {
+        "/data/pricemulti" => false,
+        "/data/pricemultifull" => true,
+        _ => return Answer::new(404, error_message("Path does not exist")),
+    };
+    let symbols = match request.query("fsyms") {
+        Some(symbols) if !symbols.is_empty() => symbols.to_uppercase(),
+        _ => return Answer::new(200, error_message("fsyms is a required param.")),
+    };
+    let currencies = match request.query("tsyms") {
+        Some(currencies) if !currencies.is_empty() => currencies.to_uppercase(),
+        _ => return Answer::new(200, error_message("tsyms is a required param.")),
+    };
+
+    //Symbols without any price are left out, like the API does
+    let mut found = Vec::new();
+    for symbol in symbols.split(',') {
+        let quotes = currencies
+            .split(',')
+            .filter_map(|currency| {
+                let quote = prices.get(&(symbol.to_string(), currency.to_string()))?;
+                Some((currency, *quote))
+            })
+            .collect::<Vec<_>>();
+        if !quotes.is_empty() {
+            found.push((symbol, quotes));
+        }
+    }
+    if found.is_empty() {
+        let message = format!("There is no data for any of the toSymbols {} .", currencies);
+        return Answer::new(200, error_message(&message));
+    }
+
+    let body = if full {
+        pricemultifull(&found)
+    } else {
+        pricemulti(&found)
+    };
+    Answer::new(200, body)
+}
+
+/// `{"BTC":{"USD":43127,"EUR":39612}}`
+fn pricemulti(found: &[(&str, Vec<(&str, Quote)>)]) -> String {
+    let mut body = String::from("{");
+
+    for (i, (symbol, quotes)) in found.iter().enumerate() {
+        if i > 0 {
+            body.push(',');
+        }
+        write!(body, "\"{}\":{{", symbol).unwrap();
+        for (j, (currency, quote)) in quotes.iter().enumerate() {
+            if j > 0 {
+                body.push(',');
+            }
+            write!(body, "\"{}\":{}", currency, quote.price).unwrap();
+        }
+        body.push('}');
+    }
+
+    body.push('}');
+    body
+}
+
+/// `{"RAW":{"BTC":{"USD":{"PRICE":43127,"OPENDAY":43718,...}}}}`, with the fields the device reads
+fn pricemultifull(found: &[(&str, Vec<(&str, Quote)>)]) -> String {
+    let mut body = String::from("{\"RAW\":{");
+
+    for (i, (symbol, quotes)) in found.iter().enumerate() {
+        if i > 0 {
+            body.push(',');
+        }
+        write!(body, "\"{}\":{{", symbol).unwrap();
+        for (j, (currency, quote)) in quotes.iter().enumerate() {
+            if j > 0 {
+                body.push(',');
+            }
+            write!(
+                body,
+                "\"{}\":{{\"TYPE\":\"5\",\"MARKET\":\"CCCAGG\",\"FROMSYMBOL\":\"{}\",\"TOSYMBOL\":\"{}\",\
+                 \"PRICE\":{},\"OPENDAY\":{},\"HIGH24HOUR\":{},\"LOW24HOUR\":{}}}",
+                currency,
+                symbol,
+                currency,
+                quote.price,
+                quote.open,
+                quote.price.max(quote.open),
+                quote.price.min(quote.open)
+            )
+            .unwrap();
+        }
+        body.push('}');
+    }
+
+    body.push_str("}}");
+    body
+}
+
+fn error_message(message: &str) -> String {
+    let escaped = message.replace('\\', "\\\\").replace('"', "\\\"");
+    format!(
+        "{{\"Response\":\"Error\",\"Message\":\"{}\",\"HasWarning\":false,\"Type\":2,\"RateLimit\":{{}},\"Data\":{{}}}}",
+        escaped
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Script;
+
+    fn get(target: &str) -> Request {
+        Request {
+            method: "GET".to_string(),
+            target: target.to_string(),
+            close: false,
+        }
+    }
+
+    fn body(target: &str, kind: &Kind) -> (u16, String) {
+        let mut script = Script::parse("price BTC USD 43127 43718\nprice ETH USD 2245.68").unwrap();
+        script.next_response();
+        let answer = answer(&get(target), kind, script.prices()).unwrap();
+        (answer.status, answer.body)
+    }
+
+    #[test]
+    fn prices() {
+        assert_eq!(
+            body(
+                "/data/pricemulti?fsyms=BTC%2CETH%2CGERO&tsyms=usd,EUR",
+                &Kind::Ok
+            ),
+            (
+                200,
+                r#"{"BTC":{"USD":43127},"ETH":{"USD":2245.68}}"#.to_string()
+            )
+        );
+        assert_eq!(
+            body("/data/pricemultifull?fsyms=BTC&tsyms=USD", &Kind::Ok),
+            (
+                200,
+                "{\"RAW\":{\"BTC\":{\"USD\":{\"TYPE\":\"5\",\"MARKET\":\"CCCAGG\",\
+                 \"FROMSYMBOL\":\"BTC\",\"TOSYMBOL\":\"USD\",\"PRICE\":43127,\"OPENDAY\":43718,\
+                 \"HIGH24HOUR\":43718,\"LOW24HOUR\":43127}}}}"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let (status, message) = body("/data/pricemulti?fsyms=GERO&tsyms=USD", &Kind::Ok);
+        assert_eq!(status, 200);
+        assert!(message.starts_with(
+            r#"{"Response":"Error","Message":"There is no data for any of the toSymbols USD ."#
+        ));
+
+        let (_, message) = body("/data/pricemulti?fsyms=&tsyms=USD", &Kind::Ok);
+        assert!(message.contains("fsyms is a required param."));
+        let (_, message) = body("/data/pricemulti?fsyms=BTC", &Kind::Ok);
+        assert!(message.contains("tsyms is a required param."));
+
+        assert_eq!(body("/data/top/totalvolfull", &Kind::Ok).0, 404);
+        assert_eq!(
+            body("/data/pricemulti", &Kind::Status(503)),
+            (503, String::new())
+        );
+        let (status, message) = body(
+            "/data/pricemulti",
+            &Kind::Error("a \"quoted\" word".to_string()),
+        );
+        assert_eq!(status, 200);
+        assert!(message.contains(r#""Message":"a \"quoted\" word""#));
+
+        let limited = answer(&get("/"), &Kind::RateLimit(30), &Prices::new()).unwrap();
+        assert_eq!((limited.status, limited.retry_after), (429, Some(30)));
+        assert_eq!(answer(&get("/"), &Kind::Close, &Prices::new()), None);
+    }
+}
@@ -0,0 +1,251 @@
+//! Just enough HTTP/1.1 for the device's client: GET requests on kept-alive connections,
+//! answered with `Content-Length` or chunked encoding.
+
+use std::io::{self, BufRead, Write};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub target: String,
+    /// The client asked to close the connection after the answer
+    pub close: bool,
+}
+
+impl Request {
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or("")
+    }
+
+    /// Percent-decoded value of the query parameter `key`
+    pub fn query(&self, key: &str) -> Option<String> {
+        let query = &self.target[self.target.find('?')? + 1..];
+
+        query.split('&').find_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if name == key => Some(percent_decode(value)),
+                _ => None,
+            }
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Answer {
+    pub status: u16,
+    pub retry_after: Option<u32>,
+    pub body: String,
+}
+
+impl Answer {
+    pub fn new(status: u16, body: String) -> Self {
+        Answer {
+            status,
+            retry_after: None,
+            body,
+        }
+    }
+}
+
+/// Reads the next request on the connection, or `None` if the client closed it
+pub fn read_request(reader: &mut impl BufRead) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    let mut words = line.split_whitespace();
+    let (method, target, version) = match (words.next(), words.next(), words.next()) {
+        (Some(method), Some(target), Some(version)) => (method, target, version),
+        _ => return Err(invalid_data("malformed request line")),
+    };
+    let mut request = Request {
+        method: method.to_string(),
+        target: target.to_string(),
+        close: version == "HTTP/1.0",
+    };
+
+    //Requests have no body, the headers end with an empty line
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            return Ok(Some(request));
+        }
+
+        let mut parts = header.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let value = parts.next().map(str::trim).unwrap_or("");
+        if name.eq_ignore_ascii_case("Connection") {
+            request.close = value.eq_ignore_ascii_case("close");
+        }
+    }
+}
+
+/// Writes `answer`, in pieces of `chunk_size` bytes `chunk_delay` apart if a chunk size is given
+pub fn write_answer(
+    stream: &mut impl Write,
+    answer: &Answer,
+    chunk_size: Option<usize>,
+    chunk_delay: Duration,
+    close: bool,
+) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json; charset=UTF-8\r\n",
+        answer.status,
+        reason(answer.status)
+    );
+    if let Some(seconds) = answer.retry_after {
+        head += &format!("Retry-After: {}\r\n", seconds);
+    }
+    if close {
+        head += "Connection: close\r\n";
+    }
+
+    let chunk_size = match chunk_size {
+        Some(chunk_size) => chunk_size,
+        None => {
+            head += &format!("Content-Length: {}\r\n\r\n", answer.body.len());
+            stream.write_all(head.as_bytes())?;
+            stream.write_all(answer.body.as_bytes())?;
+            return stream.flush();
+        }
+    };
+
+    head += "Transfer-Encoding: chunked\r\n\r\n";
+    stream.write_all(head.as_bytes())?;
+    for (i, chunk) in answer.body.as_bytes().chunks(chunk_size).enumerate() {
+        if i > 0 {
+            thread::sleep(chunk_delay);
+        }
+        stream.write_all(format!("{:x}\r\n", chunk.len()).as_bytes())?;
+        stream.write_all(chunk)?;
+        stream.write_all(b"\r\n")?;
+        stream.flush()?;
+    }
+    stream.write_all(b"0\r\n\r\n")?;
+    stream.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Unknown",
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        let mut stream: &[u8] = b"GET /data/pricemulti?fsyms=BTC%2CETH&tsyms=USD HTTP/1.1\r\n\
+            Host: min-api.cryptocompare.com\r\n\
+            Accept: application/json\r\n\r\n\
+            GET /data/pricemultifull?fsyms=BTC HTTP/1.1\r\n\
+            Connection: close\r\n\r\n\
+            GET / HTTP/1.1\r\n";
+
+        let first = read_request(&mut stream).unwrap().unwrap();
+        assert_eq!(first.method, "GET");
+        assert_eq!(first.path(), "/data/pricemulti");
+        assert_eq!(first.query("fsyms").as_deref(), Some("BTC,ETH"));
+        assert_eq!(first.query("tsyms").as_deref(), Some("USD"));
+        assert_eq!(first.query("api_key"), None);
+        assert!(!first.close);
+
+        let second = read_request(&mut stream).unwrap().unwrap();
+        assert_eq!(second.path(), "/data/pricemultifull");
+        assert!(second.close);
+
+        //Closed in the middle of the headers
+        assert_eq!(read_request(&mut stream).unwrap(), None);
+        assert_eq!(read_request(&mut stream).unwrap(), None);
+    }
+
+    #[test]
+    fn answers() {
+        let mut answer = Answer::new(429, "{}".to_string());
+        answer.retry_after = Some(30);
+        let mut written = Vec::new();
+        write_answer(&mut written, &answer, None, Duration::from_millis(0), true).unwrap();
+
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "HTTP/1.1 429 Too Many Requests\r\n\
+             Content-Type: application/json; charset=UTF-8\r\n\
+             Retry-After: 30\r\n\
+             Connection: close\r\n\
+             Content-Length: 2\r\n\r\n{}"
+        );
+    }
+
+    #[test]
+    fn chunked_answers() {
+        let answer = Answer::new(200, r#"{"BTC":{"USD":43127}}"#.to_string());
+        let mut written = Vec::new();
+        write_answer(
+            &mut written,
+            &answer,
+            Some(16),
+            Duration::from_millis(0),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "HTTP/1.1 200 OK\r\n\
+             Content-Type: application/json; charset=UTF-8\r\n\
+             Transfer-Encoding: chunked\r\n\r\n\
+             10\r\n{\"BTC\":{\"USD\":43\r\n\
+             5\r\n127}}\r\n\
+             0\r\n\r\n"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! Serves `/data/pricemulti` and `/data/pricemultifull` like the CryptoCompare API, with the
+//! prices, delays and failures of a script, to test the client and the simulation end-to-end
+//! without network access.
+
+pub mod api;
+pub mod http;
+pub mod script;
+#[cfg(feature = "tls")]
+pub mod tls;
+
+use script::Script;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Serves every connection on its own thread, after wrapping it with `wrap`
+pub fn accept_all<S, W>(listener: TcpListener, script: &Arc<Mutex<Script>>, wrap: W)
+where
+    S: Read + Write + Send + 'static,
+    W: Fn(TcpStream) -> Result<S, String>,
+{
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("accepting failed: {}", error);
+                continue;
+            }
+        };
+        let peer = match stream.peer_addr() {
+            Ok(peer) => peer,
+            Err(_) => continue,
+        };
+
+        let script = script.clone();
+        match wrap(stream) {
+            Ok(stream) => {
+                thread::spawn(move || {
+                    if let Err(error) = serve(stream, peer, &script) {
+                        eprintln!("{}: {}", peer, error);
+                    }
+                });
+            }
+            Err(error) => eprintln!("{}: {}", peer, error),
+        }
+    }
+}
+
+/// Answers the requests on a connection until either side closes it
+pub fn serve(
+    stream: impl Read + Write,
+    peer: SocketAddr,
+    script: &Mutex<Script>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+
+    while let Some(request) = http::read_request(&mut reader)? {
+        //Taken from the script at once, so that concurrent requests get consecutive lines
+        let (response, answer) = {
+            let mut script = script.lock().unwrap();
+            let response = script.next_response();
+            let answer = api::answer(&request, &response.kind, script.prices());
+            (response, answer)
+        };
+
+        thread::sleep(response.delay);
+        let answer = match answer {
+            Some(answer) => answer,
+            None => {
+                println!("{} {} -> closed", peer, request.target);
+                return Ok(());
+            }
+        };
+        println!("{} {} -> {}", peer, request.target, answer.status);
+
+        http::write_answer(
+            reader.get_mut(),
+            &answer,
+            response.chunk_size,
+            response.chunk_delay,
+            request.close,
+        )?;
+        if request.close {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
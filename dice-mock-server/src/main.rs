@@ -0,0 +1,101 @@
+//! Command line of the mock server, see the crate documentation in lib.rs
+
+use dice_mock_server::accept_all;
+use dice_mock_server::script::{Script, DEFAULT_SCRIPT};
+#[cfg(feature = "tls")]
+use dice_mock_server::tls;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "tls")]
+use std::thread;
+
+const USAGE: &str = "Usage: dice-mock-server [options]
+
+Options:
+    --http <address>    Serves HTTP on this address, 127.0.0.1:8080 by default
+    --https <address>   Serves HTTPS on this address too, needs the `tls` feature
+    --cert <file>       Where the generated CA certificate is written, mock-server.pem by default
+    --script <file>     Prices and answers to serve, see script.rs for the format
+    --help              Prints this";
+
+struct Options {
+    http: String,
+    https: Option<String>,
+    cert: String,
+    script: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        http: "127.0.0.1:8080".to_string(),
+        https: None,
+        cert: "mock-server.pem".to_string(),
+        script: None,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value of {}", arg));
+        match arg.as_str() {
+            "--http" => options.http = value()?,
+            "--https" => options.https = Some(value()?),
+            "--cert" => options.cert = value()?,
+            "--script" => options.script = Some(value()?),
+            "--help" => return Err(String::new()),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+
+    if options.https.is_some() && cfg!(not(feature = "tls")) {
+        return Err("--https needs the server built with the `tls` feature".to_string());
+    }
+    Ok(options)
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("{}\n", message);
+            }
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let text = match &options.script {
+        Some(path) => std::fs::read_to_string(path).unwrap_or_else(|error| {
+            exit(format!("can't read {}: {}", path, error));
+        }),
+        None => DEFAULT_SCRIPT.to_string(),
+    };
+    let script = Script::parse(&text).unwrap_or_else(|error| exit(error));
+    let script = Arc::new(Mutex::new(script));
+
+    #[cfg(feature = "tls")]
+    if let Some(address) = &options.https {
+        let config = tls::self_signed_config(&options.cert).unwrap_or_else(|error| exit(error));
+        let listener = bind(address);
+        println!(
+            "Serving HTTPS on {}, CA certificate in {}",
+            address, options.cert
+        );
+
+        let script = script.clone();
+        thread::spawn(move || accept_all(listener, &script, |stream| tls::accept(&config, stream)));
+    }
+
+    let listener = bind(&options.http);
+    println!("Serving HTTP on {}", options.http);
+    accept_all(listener, &script, Ok);
+}
+
+fn exit(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+fn bind(address: &str) -> TcpListener {
+    TcpListener::bind(address)
+        .unwrap_or_else(|error| exit(format!("can't listen on {}: {}", address, error)))
+}
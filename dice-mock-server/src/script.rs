@@ -0,0 +1,305 @@
+//! The script telling the server what to answer. Every line is a price change or the
+//! answer to the next request, requests past the end of the script get the current prices.
+//!
+//! ```text
+//! # <symbol> <currency> <price> [<price at the start of the day>]
+//! price BTC USD 43127 43718
+//! ok
+//! delay 3000 ok           # answers after 3 seconds
+//! chunked 16 200 ok       # chunks of 16 bytes, 200 ms apart
+//! status 503
+//! limit 30                # 429 with Retry-After: 30
+//! error fsyms param is empty
+//! close                   # closes the connection without answering
+//! price BTC USD 44000
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+/// Used when no script is given
+pub const DEFAULT_SCRIPT: &str = "price BTC USD 43127 43718\n\
+                                  price ETH USD 2245.68 2290\n\
+                                  price BTC EUR 39612 40155\n\
+                                  price ETH EUR 2062.7 2103.5\n";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quote {
+    pub price: f64,
+    /// Price at the start of the day
+    pub open: f64,
+}
+
+/// Quotes by symbol and currency, both in upper case
+pub type Prices = BTreeMap<(String, String), Quote>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    /// The prices asked for
+    Ok,
+    /// An empty answer with this status
+    Status(u16),
+    /// 429 asking to wait this many seconds
+    RateLimit(u32),
+    /// The error message CryptoCompare sends with status 200
+    Error(String),
+    /// No answer, the connection is closed
+    Close,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+    pub kind: Kind,
+    /// Wait before answering
+    pub delay: Duration,
+    /// Send the body with chunked encoding, in chunks of this size
+    pub chunk_size: Option<usize>,
+    /// Wait between chunks
+    pub chunk_delay: Duration,
+}
+
+impl Default for Response {
+    fn default() -> Self {
+        Response {
+            kind: Kind::Ok,
+            delay: Duration::from_millis(0),
+            chunk_size: None,
+            chunk_delay: Duration::from_millis(0),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Line {
+    Price(String, String, Quote),
+    Respond(Response),
+}
+
+#[derive(Debug, Default)]
+pub struct Script {
+    prices: Prices,
+    lines: VecDeque<Line>,
+}
+
+impl Script {
+    /// Parses `text`, the error names the line that isn't valid
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = VecDeque::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parsed =
+                parse_line(line).map_err(|error| format!("line {}: {}", number + 1, error))?;
+            lines.push_back(parsed);
+        }
+
+        Ok(Script {
+            prices: Prices::new(),
+            lines,
+        })
+    }
+
+    /// Applies the price changes up to the next answer and returns it
+    pub fn next_response(&mut self) -> Response {
+        while let Some(line) = self.lines.pop_front() {
+            match line {
+                Line::Price(symbol, currency, quote) => {
+                    self.prices.insert((symbol, currency), quote);
+                }
+                Line::Respond(response) => return response,
+            }
+        }
+        Response::default()
+    }
+
+    pub fn prices(&self) -> &Prices {
+        &self.prices
+    }
+}
+
+fn parse_line(line: &str) -> Result<Line, String> {
+    let mut words = line.split_whitespace();
+
+    if line.starts_with("price ") {
+        words.next();
+        let symbol = words.next().ok_or("missing symbol")?.to_uppercase();
+        let currency = words.next().ok_or("missing currency")?.to_uppercase();
+        let price = parse_number::<f64>(words.next().ok_or("missing price")?)?;
+        let open = match words.next() {
+            Some(open) => parse_number::<f64>(open)?,
+            None => price,
+        };
+        if let Some(word) = words.next() {
+            return Err(format!("unexpected `{}`", word));
+        }
+        return Ok(Line::Price(symbol, currency, Quote { price, open }));
+    }
+
+    let mut response = Response::default();
+    let mut kind = None;
+
+    while let Some(word) = words.next() {
+        match word {
+            "delay" => response.delay = parse_millis(words.next())?,
+            "chunked" => {
+                let size = parse_number::<usize>(words.next().ok_or("missing chunk size")?)?;
+                if size == 0 {
+                    return Err("chunk size can't be 0".to_string());
+                }
+                response.chunk_size = Some(size);
+                //The pause between chunks is optional
+                let mut rest = words.clone();
+                if let Some(Ok(millis)) = rest.next().map(str::parse::<u64>) {
+                    response.chunk_delay = Duration::from_millis(millis);
+                    words = rest;
+                }
+            }
+            "ok" => kind = set_kind(kind, Kind::Ok)?,
+            "close" => kind = set_kind(kind, Kind::Close)?,
+            "status" => {
+                let status = parse_number::<u16>(words.next().ok_or("missing status")?)?;
+                if !(100..600).contains(&status) {
+                    return Err(format!("invalid status {}", status));
+                }
+                kind = set_kind(kind, Kind::Status(status))?;
+            }
+            "limit" => {
+                let seconds = parse_number::<u32>(words.next().ok_or("missing seconds")?)?;
+                kind = set_kind(kind, Kind::RateLimit(seconds))?;
+            }
+            "error" => {
+                //The message is the rest of the line
+                let message = words.by_ref().collect::<Vec<_>>().join(" ");
+                if message.is_empty() {
+                    return Err("missing error message".to_string());
+                }
+                kind = set_kind(kind, Kind::Error(message))?;
+            }
+            _ => return Err(format!("unknown word `{}`", word)),
+        }
+    }
+
+    response.kind = kind.unwrap_or(Kind::Ok);
+    Ok(Line::Respond(response))
+}
+
+fn set_kind(current: Option<Kind>, kind: Kind) -> Result<Option<Kind>, String> {
+    match current {
+        Some(_) => Err("more than one answer on a line".to_string()),
+        None => Ok(Some(kind)),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(word: &str) -> Result<T, String> {
+    word.parse()
+        .map_err(|_| format!("invalid number `{}`", word))
+}
+
+fn parse_millis(word: Option<&str>) -> Result<Duration, String> {
+    let millis = parse_number::<u64>(word.ok_or("missing milliseconds")?)?;
+    Ok(Duration::from_millis(millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(script: &Script, symbol: &str, currency: &str) -> Option<Quote> {
+        script
+            .prices()
+            .get(&(symbol.to_string(), currency.to_string()))
+            .copied()
+    }
+
+    #[test]
+    fn responses_in_order() {
+        let mut script = Script::parse(
+            "price btc usd 43127 43718 # lower case is fine\n\
+             \n\
+             delay 3000 ok\n\
+             chunked 16 200 status 503\n\
+             chunked 7 limit 30\n\
+             error fsyms param is empty\n\
+             close\n\
+             price BTC USD 44000\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            script.next_response(),
+            Response {
+                delay: Duration::from_millis(3000),
+                ..Response::default()
+            }
+        );
+        assert_eq!(
+            quote(&script, "BTC", "USD"),
+            Some(Quote {
+                price: 43127.0,
+                open: 43718.0
+            })
+        );
+        assert_eq!(
+            script.next_response(),
+            Response {
+                kind: Kind::Status(503),
+                chunk_size: Some(16),
+                chunk_delay: Duration::from_millis(200),
+                ..Response::default()
+            }
+        );
+        assert_eq!(
+            script.next_response(),
+            Response {
+                kind: Kind::RateLimit(30),
+                chunk_size: Some(7),
+                ..Response::default()
+            }
+        );
+        assert_eq!(
+            script.next_response().kind,
+            Kind::Error("fsyms param is empty".to_string())
+        );
+        assert_eq!(script.next_response().kind, Kind::Close);
+
+        //Past the end, the last prices are answered
+        assert_eq!(script.next_response(), Response::default());
+        assert_eq!(
+            quote(&script, "BTC", "USD"),
+            Some(Quote {
+                price: 44000.0,
+                open: 44000.0
+            })
+        );
+        assert_eq!(script.next_response(), Response::default());
+    }
+
+    #[test]
+    fn invalid_lines() {
+        let invalid = [
+            ("price BTC USD", "line 1: missing price"),
+            ("price BTC USD 1 2 3", "line 1: unexpected `3`"),
+            ("ok\nstatus 99", "line 2: invalid status 99"),
+            ("status 500 ok", "line 1: more than one answer on a line"),
+            ("chunked 0", "line 1: chunk size can't be 0"),
+            ("delay soon", "line 1: invalid number `soon`"),
+            ("error", "line 1: missing error message"),
+            ("retry", "line 1: unknown word `retry`"),
+        ];
+
+        for (text, error) in invalid.iter() {
+            assert_eq!(Script::parse(text).unwrap_err(), *error);
+        }
+    }
+
+    #[test]
+    fn default_script() {
+        let mut script = Script::parse(DEFAULT_SCRIPT).unwrap();
+
+        assert_eq!(script.next_response(), Response::default());
+        assert_eq!(script.prices().len(), 4);
+    }
+}
@@ -0,0 +1,52 @@
+//! HTTPS with certificates generated at every start
+
+use rcgen::{BasicConstraints, Certificate as RcgenCertificate, CertificateParams, DnType, IsCa};
+use rustls::{Certificate, PrivateKey, ServerConfig, ServerConnection, StreamOwned};
+use std::net::TcpStream;
+use std::sync::Arc;
+
+/// Names the certificate is valid for. Clients resolving the API host to this server
+/// can check the certificate against the real name.
+const NAMES: [&str; 2] = ["localhost", "min-api.cryptocompare.com"];
+
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+/// Generates a certificate authority and a server certificate signed by it. The authority
+/// is written to `ca_path` in PEM for clients to trust, some refuse a self-signed server
+/// certificate as trust anchor.
+pub fn self_signed_config(ca_path: &str) -> Result<Arc<ServerConfig>, String> {
+    let mut ca_params = CertificateParams::new(Vec::new());
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "DICE mock server CA");
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = RcgenCertificate::from_params(ca_params).map_err(|error| error.to_string())?;
+
+    let names = NAMES
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    let cert = RcgenCertificate::from_params(CertificateParams::new(names))
+        .map_err(|error| error.to_string())?;
+
+    let pem = ca.serialize_pem().map_err(|error| error.to_string())?;
+    std::fs::write(ca_path, pem).map_err(|error| format!("can't write {}: {}", ca_path, error))?;
+
+    let der = cert
+        .serialize_der_with_signer(&ca)
+        .map_err(|error| error.to_string())?;
+    let key = cert.serialize_private_key_der();
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![Certificate(der)], PrivateKey(key))
+        .map_err(|error| error.to_string())?;
+
+    Ok(Arc::new(config))
+}
+
+/// Wraps an accepted connection, the handshake happens on the first read
+pub fn accept(config: &Arc<ServerConfig>, stream: TcpStream) -> Result<TlsStream, String> {
+    let connection = ServerConnection::new(config.clone()).map_err(|error| error.to_string())?;
+    Ok(StreamOwned::new(connection, stream))
+}
@@ -21,7 +21,7 @@ pub fn draw_simulation() {
         screen.as_mut().unwrap().draw_crypto(
             &mut display,
             symbol.clone(),
-            *price,
+            &[("USD", *price)],
             *change,
             (6, 8),
             region,
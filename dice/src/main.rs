@@ -4,8 +4,13 @@
 
 use dice_http::response;
 use dice_http::Request;
-use dice_http_client::crypto_api_client::CryptoApiClient;
+use dice_http_client::backoff::Backoff;
+use dice_http_client::coingecko_api_client::CoinGeckoApiClient;
+use dice_http_client::crypto_api_client::{
+    CryptoApiClient, History, Interval, MarketStatsMap, Quotes, MAX_CANDLES,
+};
 use dice_http_client::cryptocompare_api_client::CryptoCompareApiClient;
+use dice_http_client::failover_api_client::{FailoverApiClient, Provider};
 
 use heapless::{FnvIndexMap, String, Vec};
 
@@ -19,6 +24,8 @@ use rtic::app;
 
 use embedded_hal::digital::v2::OutputPin;
 
+use dice_common::catalog::{Catalog, CatalogBuilder};
+use dice_common::config::{DeviceConfig, MAX_QUOTE_CURRENCIES, MAX_SYMBOLS};
 use dice_common::display::DrawableCrypto;
 
 use hal::gpio::{Output, PushPull};
@@ -37,7 +44,7 @@ use dice_common::smoltcp::{
     wire::{IpCidr, Ipv4Address, Ipv6Cidr},
 };
 
-use dice_http::HttpServer;
+use dice_http::{HttpServer, UploadHandler};
 
 mod tls_stack;
 
@@ -55,9 +62,12 @@ use tls_stack::TlsLayer;
 #[global_allocator]
 static ALLOCATOR: CortexMHeap = CortexMHeap::empty();
 
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+mod storage;
+mod upload;
 mod webpages;
 use dice_common::display as display_abstraction;
+use storage::BlobWriter;
 
 static mut INTERFACE_STORAGE: EthernetInterfaceStorage = EthernetInterfaceStorage {
     ip_storage: [IpCidr::Ipv6(Ipv6Cidr::SOLICITED_NODE_PREFIX)],
@@ -99,51 +109,145 @@ const SRC_MAC: [u8; 6] = [0x00, 0x60, 0xEE, 0xAD, 0xBE, 0xEF];
 static mut NETWORK_STACK: Option<NetworkStack<platform::EthDeviceT>> = None;
 static mut TLS_LAYER: Option<TlsLayer<NetworkStack<platform::EthDeviceT>>> = None;
 
-const ALL_SYMBOLS: [&str; 128] = [
-    "ETH", "BTC", "BNB", "XRP", "MATIC", "DOGE", "ETC", "ADA", "LTC", "DOT", "BCH", "EOS", "LINK",
-    "FIL", "UNI", "XLM", "VET", "TRX", "BTT", "SOL", "LUNA", "OMG", "CAKE", "YFI", "HT", "QTUM",
-    "NEO", "SUSHI", "OKB", "BSV", "AAVE", "THETA", "ONT", "ZEC", "KSM", "XVS", "RUNE", "DASH",
-    "CHZ", "MKR", "ATOM", "SXP", "BAKE", "ENJ", "WAVES", "XMR", "MANA", "ONE", "WRX", "XTZ", "CRV",
-    "FTT", "HOT", "IOST", "AVAX", "HBAR", "FTM", "ZEN", "MIOTA", "ZIL", "CHR", "GRT", "KAVA", "SC",
-    "ALGO", "WBTC", "1INCH", "COMP", "LSK", "ZRX", "SRM", "FLOW", "BAT", "NANO", "SNX", "XEM",
-    "ICX", "RSR", "REEF", "BURGER", "RLC", "TRB", "KLAY", "EGLD", "ONGAS", "NEAR", "DENT", "RVN",
-    "LRC", "VTHO", "KNC", "WIN", "ANKR", "BAND", "JST", "BTG", "BNT", "REP", "CRO", "ALPHA",
-    "OCEAN", "HIVE", "TFUEL", "STORJ", "YFII", "SUN", "OGN", "STMX", "COTI", "GT", "MTL", "MLK",
-    "MONA", "SNT", "DGB", "QKC", "CELR", "INJ", "SOC", "PAXG", "REN", "UNFI", "NKN", "CELO", "BAL",
-    "STEEM", "DAI", "GERO",
-];
+type TlsStackT = TlsLayer<'static, NetworkStack<platform::EthDeviceT>>;
+
+//price providers, in the order they are asked by the failover client
+static mut CRYPTOCOMPARE_CLIENT: Option<CryptoCompareApiClient<TlsStackT>> = None;
+static mut COINGECKO_CLIENT: Option<CoinGeckoApiClient<TlsStackT>> = None;
+
+//first and longest delay in seconds before asking the price API again after a failure
+const RETRY_BASE_DELAY: u32 = 2;
+const RETRY_MAX_DELAY: u32 = 600;
+//number of failed price updates in a row after which the error is shown on screen
+const SHOWN_FAILURES: u8 = 3;
+//seconds between updates of the market stats, they change slower than prices
+const STATS_REFRESH_INTERVAL: u32 = 600;
+//seconds between updates of the hourly price history
+const HISTORY_REFRESH_INTERVAL: u32 = 3600;
 
 static mut CANVAS: Option<display_abstraction::Screen> = None;
 static mut CONNECTED_DISPLAYS: Option<display_abstraction::ConnectedDisplays<PINS0, PINS1, PINS2>> =
     None;
 
-static mut CONFIG_SYMBOLS: Option<Mutex<Vec<String<16>, 64>>> = None;
+static mut DEVICE_CONFIG: Option<Mutex<DeviceConfig>> = None;
+//Set by the configuration handlers, cleared by `config_update_task` once the change is applied
+static CONFIG_CHANGED: AtomicBool = AtomicBool::new(false);
+static BRIGHTNESS: AtomicU8 = AtomicU8::new(1);
+//provider the last price of every symbol came from. Shown on the market page
+static mut PRICE_SOURCES: Option<Mutex<FnvIndexMap<String<16>, &'static str, MAX_SYMBOLS>>> = None;
+//24h high, low, volume and market cap of every symbol, in the first currency. Shown on the
+//market page
+static mut MARKET_STATS: Option<Mutex<MarketStatsMap<MAX_SYMBOLS>>> = None;
+//hourly candles of the last day of every symbol, in the first currency. Shown on the market page
+static mut HISTORY: Option<Mutex<FnvIndexMap<String<16>, History, MAX_SYMBOLS>>> = None;
+
+pub fn index_get<const SIZE: usize>(request: Request, body: &[u8]) -> String<SIZE> {
+    let (setup_complete, symbols, currencies) = {
+        let config = unsafe { DEVICE_CONFIG.as_ref().unwrap().lock() };
+        (
+            config.setup_complete,
+            config.symbols.clone(),
+            config.currencies.clone(),
+        )
+    };
+
+    //The setup wizard takes over the index until the first-boot setup is finished
+    if !setup_complete {
+        return setup_get(request, body);
+    }
+
+    webpages::index_get(&load_catalog(), &symbols, &currencies)
+        .unwrap_or_else(|_| response::server_error_response())
+}
+
+pub fn market_get<const SIZE: usize>(_request: Request, _body: &[u8]) -> String<SIZE> {
+    let (symbols, currencies) = {
+        let config = unsafe { DEVICE_CONFIG.as_ref().unwrap().lock() };
+        (config.symbols.clone(), config.currencies.clone())
+    };
+    let sources = unsafe { PRICE_SOURCES.as_ref().unwrap().lock() };
+    let stats = unsafe { MARKET_STATS.as_ref().unwrap().lock() };
+    let history = unsafe { HISTORY.as_ref().unwrap().lock() };
+
+    webpages::market_get(&symbols, &currencies[0], &sources, &stats, &history)
+        .unwrap_or_else(|_| response::server_error_response())
+}
 
-pub fn index_get<const SIZE: usize>(_request: Request, _body: &[u8]) -> String<SIZE> {
-    webpages::index_get(&ALL_SYMBOLS)
+pub fn setup_get<const SIZE: usize>(_request: Request, _body: &[u8]) -> String<SIZE> {
+    let config = unsafe { DEVICE_CONFIG.as_ref().unwrap().lock() };
+    webpages::setup_get(&load_catalog(), &config)
+        .unwrap_or_else(|_| response::server_error_response())
+}
+
+pub fn setup_post<const SIZE: usize>(_request: Request, body: &[u8]) -> String<SIZE> {
+    #[cfg(feature = "use_semihosting")]
+    hprintln!("{:?}", core::str::from_utf8(body)).ok();
+
+    let result = update_config(|config| {
+        config.update_from_form(body)?;
+        config.setup_complete = true;
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => response::redirect_response("/"),
+        Err(_) => response::bad_request_response(),
+    }
 }
 
 pub fn index_post<const SIZE: usize>(_request: Request, body: &[u8]) -> String<SIZE> {
     #[cfg(feature = "use_semihosting")]
-    hprintln!("{}", core::str::from_utf8(body).unwrap()).ok();
+    hprintln!("{:?}", core::str::from_utf8(body)).ok();
+
+    match update_config(|config| config.update_from_form(body)) {
+        Ok(()) => response::redirect_response("/"),
+        Err(_) => response::bad_request_response(),
+    }
+}
+
+/// Modifies the device configuration and hands it over to `config_update_task`,
+/// which applies and persists it. The configuration is left untouched if `modify` fails.
+pub fn update_config<E>(modify: impl FnOnce(&mut DeviceConfig) -> Result<(), E>) -> Result<(), E> {
+    let mut config = unsafe { DEVICE_CONFIG.as_ref().unwrap().lock() };
+
+    let mut updated = config.clone();
+    modify(&mut updated)?;
+    *config = updated;
+
+    CONFIG_CHANGED.store(true, Ordering::Release);
+    Ok(())
+}
+
+fn load_config() -> DeviceConfig {
+    storage::read_blob(platform::CONFIG_STORAGE_ADDRESS)
+        .and_then(|blob| DeviceConfig::from_form(blob).ok())
+        .unwrap_or_default()
+}
 
-    let symbols = webpages::parse_post_body(core::str::from_utf8(body).unwrap());
-    unsafe {
-        let cs = CONFIG_SYMBOLS.as_mut().unwrap();
-        let mut csval = cs.lock();
-        *csval = symbols;
+fn save_config(config: &DeviceConfig) {
+    if let Ok(form) = config.to_form() {
+        let mut writer = BlobWriter::new(platform::CONFIG_STORAGE_ADDRESS);
+        if writer.write(form.as_bytes()).is_ok() {
+            writer.finish();
+        }
     }
+}
 
-    return response::redirect_response("/");
+/// Symbols offered on the configuration pages, the built-in ones until a catalog is fetched
+fn load_catalog() -> Catalog<'static> {
+    Catalog::from_blob(storage::read_blob(platform::CATALOG_STORAGE_ADDRESS))
 }
 
-fn get_default_symbols() -> Vec<String<16>, 64> {
-    let mut vector = Vec::new();
-    for i in 0..8 {
-        vector.push(String::from(ALL_SYMBOLS[i])).unwrap();
+/// Stores a newly fetched catalog. The sector is only erased if the symbols changed.
+fn save_catalog(catalog: &CatalogBuilder) {
+    if load_catalog() == catalog.catalog() {
+        return;
     }
 
-    vector
+    let mut writer = BlobWriter::new(platform::CATALOG_STORAGE_ADDRESS);
+    if writer.write(catalog.as_bytes()).is_ok() {
+        writer.finish();
+    }
 }
 #[app(device = crate::hal::stm32, peripherals = true, monotonic = rtic::cyccnt::CYCCNT)]
 const APP: () = {
@@ -151,10 +255,27 @@ const APP: () = {
         led_r: PB14<Output<PushPull>>,
         led_g: PB0<Output<PushPull>>,
         led_b: platform::LedBType,
-        selected_symbols: Vec<String<16>, 64>,
-        //tuple contains actual price and a base price updated every 24 hours used to calculate 24h% change
-        prices: FnvIndexMap<String<16>, (Option<f32>, Option<f32>), 16>,
+        //configuration currently in effect, a copy of `DEVICE_CONFIG` made by `config_update_task`
+        config: DeviceConfig,
+        //seconds left until the next price update
+        update_countdown: u32,
+        //delays between retries of failed price updates
+        price_backoff: Backoff,
+        //seconds left until the next attempt to fetch the base prices, after a failure
+        openday_countdown: u32,
+        openday_backoff: Backoff,
+        //tuple contains actual prices in every currency and a base price in the first currency,
+        //updated every 24 hours, used to calculate 24h% change
+        prices: FnvIndexMap<String<16>, (Quotes, Option<f32>), MAX_SYMBOLS>,
+        //seconds left until the next market stats update
+        stats_countdown: u32,
+        //symbols whose history is fetched next, one with every price update
+        history_queue: Vec<String<16>, MAX_SYMBOLS>,
+        //seconds left until the history of all symbols is fetched again
+        history_countdown: u32,
         device_capabilities: DeviceCapabilities,
+        //keeps the connection to the price API open between updates
+        price_client: FailoverApiClient<'static, TlsStackT, MAX_SYMBOLS, 2>,
         http_server: HttpServer<128, 16384, 16, 2048, 20>,
         display_delay: platform::DisplayDelayProvider,
         display_task_timer: platform::DisplayTaskTimer,
@@ -208,14 +329,18 @@ const APP: () = {
         }
 
         unsafe {
-            CANVAS
-                .as_mut()
-                .unwrap()
-                .draw_intro(CONNECTED_DISPLAYS.as_mut().unwrap())
+            CANVAS.as_mut().unwrap().draw_intro(
+                CONNECTED_DISPLAYS.as_mut().unwrap(),
+                storage::read_blob(platform::LOGO_STORAGE_ADDRESS),
+            )
         }
         unsafe {
-            CONFIG_SYMBOLS = Some(Mutex::new(get_default_symbols()));
+            DEVICE_CONFIG = Some(Mutex::new(load_config()));
+            PRICE_SOURCES = Some(Mutex::new(FnvIndexMap::new()));
+            MARKET_STATS = Some(Mutex::new(MarketStatsMap::new()));
+            HISTORY = Some(Mutex::new(FnvIndexMap::new()));
         }
+        CONFIG_CHANGED.store(true, Ordering::Release);
 
         let device_capabilities = iface.device().capabilities();
 
@@ -232,9 +357,21 @@ const APP: () = {
 
         http_server.add_route("GET", "/", index_get).ok();
         http_server.add_route("POST", "/", index_post).ok();
+        http_server.add_route("GET", "/market", market_get).ok();
+        http_server.add_route("GET", "/setup", setup_get).ok();
+        http_server.add_route("POST", "/setup", setup_post).ok();
         http_server
             .add_route("GET", "/styles.css", webpages::styles_get)
             .ok();
+        http_server
+            .add_upload_route(
+                "/upload",
+                UploadHandler {
+                    on_event: upload::upload_event,
+                    on_complete: upload::upload_complete,
+                },
+            )
+            .ok();
 
         let mut icmp_socket = create_icmp_socket(unsafe { &mut SOCKET_STORAGE });
         icmp_socket.bind(IcmpEndpoint::Ident(1)).unwrap();
@@ -272,9 +409,18 @@ const APP: () = {
             TLS_LAYER = Some(TlsLayer::new(NETWORK_STACK.as_mut().unwrap()));
 
             TLS_LAYER.as_mut().unwrap().init(entropy);
+
+            CRYPTOCOMPARE_CLIENT = Some(CryptoCompareApiClient::new());
+            COINGECKO_CLIENT = Some(CoinGeckoApiClient::new());
         }
 
-        //TODO: Cap max selected cryptos to 60 (limited by API Url param length) or divide into chunks in the driver and make multiple requests
+        //CoinGecko has the coins CryptoCompare lacks, and takes over when it's down
+        let price_client = unsafe {
+            FailoverApiClient::new([
+                CRYPTOCOMPARE_CLIENT.as_mut().unwrap() as Provider<_, MAX_SYMBOLS>,
+                COINGECKO_CLIENT.as_mut().unwrap(),
+            ])
+        };
 
         let prices = FnvIndexMap::new();
 
@@ -295,8 +441,16 @@ const APP: () = {
             led_g,
             led_b,
             device_capabilities,
-            selected_symbols: Vec::new(),
+            config: DeviceConfig::default(),
+            update_countdown: 0,
+            price_backoff: Backoff::new(RETRY_BASE_DELAY, RETRY_MAX_DELAY),
+            openday_countdown: 0,
+            openday_backoff: Backoff::new(RETRY_BASE_DELAY, RETRY_MAX_DELAY),
             prices,
+            stats_countdown: 0,
+            history_queue: Vec::new(),
+            history_countdown: 0,
+            price_client,
             http_server,
             display_delay,
             display_task_timer,
@@ -316,12 +470,48 @@ const APP: () = {
         }
     }
 
-    #[task(resources=[selected_symbols, prices], schedule=[update_prices_task], priority=1)]
+    #[task(resources=[config, prices, history_queue, update_countdown, stats_countdown, history_countdown, price_backoff, price_client], schedule=[update_prices_task], priority=1)]
     fn update_prices_task(cx: update_prices_task::Context) {
-        let period = rtic::cyccnt::U32Ext::cycles(platform::CLOCK_FREQ_MHZ * 1000000 * 4);
+        //1s period, the refresh interval is counted down in seconds
+        let period = rtic::cyccnt::U32Ext::cycles(platform::CLOCK_FREQ_MHZ * 1000000);
+
+        let config = cx.resources.config;
 
         //unsafe only because we access static mutables
         unsafe {
+            if !config.setup_complete {
+                //Show the user where to find the setup wizard
+                let ip = NETWORK_STACK.as_mut().unwrap().ipv4_address();
+
+                CANVAS
+                    .as_mut()
+                    .unwrap()
+                    .clear(CONNECTED_DISPLAYS.as_mut().unwrap());
+                CANVAS
+                    .as_mut()
+                    .unwrap()
+                    .draw_setup_info(CONNECTED_DISPLAYS.as_mut().unwrap(), ip.map(|ip| ip.0));
+
+                cx.schedule
+                    .update_prices_task(cx.scheduled + period)
+                    .unwrap();
+                return;
+            }
+
+            let stats_countdown = cx.resources.stats_countdown;
+            *stats_countdown = stats_countdown.saturating_sub(1);
+            let history_countdown = cx.resources.history_countdown;
+            *history_countdown = history_countdown.saturating_sub(1);
+
+            let countdown = cx.resources.update_countdown;
+            if *countdown > 0 {
+                *countdown -= 1;
+                cx.schedule
+                    .update_prices_task(cx.scheduled + period)
+                    .unwrap();
+                return;
+            }
+
             if NETWORK_STACK.as_mut().unwrap().is_ip_unspecified() {
                 cx.schedule
                     .update_prices_task(cx.scheduled + period)
@@ -329,48 +519,134 @@ const APP: () = {
                 return;
             }
 
+            *countdown = config.refresh_interval - 1;
+
             let tls = TLS_LAYER.as_mut().unwrap();
 
-            let result = CryptoCompareApiClient::get_current_prices(
+            let result = cx.resources.price_client.get_current_prices(
                 tls,
-                cx.resources.selected_symbols,
-                &"USD",
+                &config.symbols,
+                &config.currencies,
             );
 
-            if let Ok(res) = result {
-                let prices = cx.resources.prices;
-                for (key, val) in res.iter() {
-                    if prices.contains_key(key) {
-                        let (price, _change) = prices.get_mut(key).unwrap();
-                        *price = Some(val.clone());
+            let backoff = cx.resources.price_backoff;
+
+            match result {
+                Ok(res) => {
+                    backoff.reset();
+
+                    let prices = cx.resources.prices;
+                    for (key, val) in res.iter() {
+                        if prices.contains_key(key) {
+                            let (price, _change) = prices.get_mut(key).unwrap();
+                            *price = val.clone();
+                        }
                     }
-                }
 
-                CANVAS
-                    .as_mut()
-                    .unwrap()
-                    .clear(CONNECTED_DISPLAYS.as_mut().unwrap());
+                    let price_client = &cx.resources.price_client;
+                    cortex_m::interrupt::free(|_| {
+                        let mut sources = PRICE_SOURCES.as_ref().unwrap().lock();
+                        sources.clear();
+                        for symbol in config.symbols.iter() {
+                            if let Some(source) = price_client.source_of(symbol) {
+                                sources.insert(symbol.clone(), source).ok();
+                            }
+                        }
+                    });
+
+                    CANVAS
+                        .as_mut()
+                        .unwrap()
+                        .clear(CONNECTED_DISPLAYS.as_mut().unwrap());
+
+                    let mut region = 0;
+
+                    for (symbol, (price_tick, price_24h)) in prices.into_iter() {
+                        let mut quotes = Vec::<(&str, Option<f32>), MAX_QUOTE_CURRENCIES>::new();
+                        for currency in config.currencies.iter() {
+                            quotes
+                                .push((currency.as_str(), price_tick.get(currency).copied()))
+                                .ok();
+                        }
+
+                        unsafe {
+                            CANVAS.as_mut().unwrap().draw_crypto(
+                                CONNECTED_DISPLAYS.as_mut().unwrap(),
+                                symbol.clone(),
+                                &quotes,
+                                *price_24h,
+                                (6, 8),
+                                region,
+                            );
+                        }
+                        #[cfg(feature = "use_semihosting")]
+                        hprintln!("{:?}", region).ok();
+                        region += 1;
+                    }
+
+                    // #[cfg(feature = "use_semihosting")]
+                    // hprintln!("{:?}", prices).ok();
 
-                let mut region = 0;
-
-                for (symbol, (price_tick, price_24h)) in prices.into_iter() {
-                    unsafe {
-                        CANVAS.as_mut().unwrap().draw_crypto(
-                            CONNECTED_DISPLAYS.as_mut().unwrap(),
-                            symbol.clone(),
-                            *price_tick,
-                            *price_24h,
-                            (6, 8),
-                            region,
+                    //Failed stats updates are tried again with the next prices
+                    if *stats_countdown == 0 {
+                        let stats = cx.resources.price_client.get_market_stats(
+                            tls,
+                            &config.symbols,
+                            &config.currencies[0],
                         );
+                        if let Ok(stats) = stats {
+                            cortex_m::interrupt::free(|_| {
+                                *MARKET_STATS.as_ref().unwrap().lock() = stats;
+                            });
+                            *stats_countdown = STATS_REFRESH_INTERVAL;
+                        }
                     }
-                    #[cfg(feature = "use_semihosting")]
-                    hprintln!("{:?}", region).ok();
-                    region += 1;
-                }
 
-                // #[cfg(feature = "use_semihosting")]
-                // hprintln!("{:?}", prices).ok();
+                    //Every symbol needs a request of its own, they are spread over the
+                    //price updates not to hold the prices up
+                    let queue = cx.resources.history_queue;
+                    if *history_countdown == 0 {
+                        *queue = config.symbols.clone();
+                        *history_countdown = HISTORY_REFRESH_INTERVAL;
+                    }
+                    if let Some(symbol) = queue.pop() {
+                        let history = cx.resources.price_client.get_history(
+                            tls,
+                            &symbol,
+                            &config.currencies[0],
+                            Interval::Hour,
+                            MAX_CANDLES,
+                        );
+                        //The HTTP handlers lock the history from a higher priority task
+                        if let Ok(history) = history {
+                            cortex_m::interrupt::free(|_| {
+                                HISTORY
+                                    .as_ref()
+                                    .unwrap()
+                                    .lock()
+                                    .insert(symbol, history)
+                                    .ok();
+                            });
+                        }
+                    }
+                }
+                Err(error) => {
+                    //Regular updates go on if the delay is shorter than the refresh interval
+                    let delay = backoff.next_delay(platform::random_u32(), error.retry_after());
+                    *countdown = (*countdown).max(delay.saturating_sub(1));
+
+                    //Network hiccups are expected now and then, only errors that won't go away
+                    //by themselves are shown right away
+                    if !error.is_retryable() || backoff.failures() >= SHOWN_FAILURES {
+                        let mut message = String::<32>::new();
+                        core::fmt::write(&mut message, format_args!("ERR: {}", error)).ok();
+
+                        CANVAS
+                            .as_mut()
+                            .unwrap()
+                            .draw_error(CONNECTED_DISPLAYS.as_mut().unwrap(), &message);
+                    }
+                }
             }
         }
 
@@ -379,12 +655,20 @@ const APP: () = {
             .unwrap();
     }
 
-    #[task(resources=[selected_symbols, prices], schedule=[update_24h], priority=1)]
+    #[task(resources=[config, prices, openday_countdown, openday_backoff, price_client], schedule=[update_24h], priority=1)]
     fn update_24h(cx: update_24h::Context) {
+        //1s period, the delay after a failure is counted down in seconds
         let period = rtic::cyccnt::U32Ext::cycles(platform::CLOCK_FREQ_MHZ * 1000000);
 
         //unsafe only because we access static mutables
         unsafe {
+            let countdown = cx.resources.openday_countdown;
+            if *countdown > 0 {
+                *countdown -= 1;
+                cx.schedule.update_24h(cx.scheduled + period).unwrap();
+                return;
+            }
+
             if NETWORK_STACK.as_mut().unwrap().is_ip_unspecified() {
                 cx.schedule.update_24h(cx.scheduled + period).unwrap();
                 return;
@@ -392,26 +676,50 @@ const APP: () = {
 
             let tls = TLS_LAYER.as_mut().unwrap();
 
-            let result = CryptoCompareApiClient::get_openday_price(
-                tls,
-                cx.resources.selected_symbols,
-                &"USD",
-            );
+            let config = cx.resources.config;
+
+            //The change is only shown in the first currency
+            let currency = &config.currencies[..1];
+            let result =
+                cx.resources
+                    .price_client
+                    .get_openday_price(tls, &config.symbols, currency);
 
-            if let Ok(res) = result {
-                let prices = cx.resources.prices;
+            let backoff = cx.resources.openday_backoff;
 
-                for (key, val) in res.iter() {
-                    if prices.contains_key(key) {
-                        let (_price, base_24) = prices.get_mut(key).unwrap();
-                        *base_24 = Some(val.clone());
+            match result {
+                Ok(res) => {
+                    backoff.reset();
+
+                    let prices = cx.resources.prices;
+
+                    for (key, val) in res.iter() {
+                        if prices.contains_key(key) {
+                            let (_price, base_24) = prices.get_mut(key).unwrap();
+                            *base_24 = val.get(&currency[0]).copied();
+                        }
                     }
+
+                    //The symbols on offer change about as rarely, they are listed in that currency
+                    let mut catalog = CatalogBuilder::new();
+                    let listed =
+                        cx.resources
+                            .price_client
+                            .list_symbols(tls, &currency[0], &mut |symbol| catalog.add(symbol));
+                    if listed.is_ok() && !catalog.is_empty() {
+                        save_catalog(&catalog);
+                    }
+
+                    //schedule for the next day
+                    return;
+                }
+                Err(error) => {
+                    //If failed, try again after a delay growing with every failure
+                    let delay = backoff.next_delay(platform::random_u32(), error.retry_after());
+                    *countdown = delay.saturating_sub(1);
                 }
-                //schedule for the next day
-                return;
             }
 
-            //If failed, try again in 1 second
             cx.schedule.update_24h(cx.scheduled + period).unwrap();
         }
     }
@@ -452,29 +760,70 @@ const APP: () = {
         cx.schedule.server_poll(cx.scheduled + period).unwrap();
     }
 
-    #[task(resources = [selected_symbols, prices], schedule=[config_update_task], spawn=[update_24h], priority=1)]
+    #[task(resources = [config, prices, update_countdown, stats_countdown, history_countdown, openday_countdown, openday_backoff, price_client], schedule=[config_update_task], spawn=[update_24h], priority=1)]
     fn config_update_task(cx: config_update_task::Context) {
         let period = rtic::cyccnt::U32Ext::cycles(platform::CLOCK_FREQ_MHZ * 1000);
 
-        let cs = unsafe { CONFIG_SYMBOLS.as_mut().unwrap() };
-        let cs = cs.try_lock();
+        if CONFIG_CHANGED.swap(false, Ordering::Acquire) {
+            //The HTTP handlers lock the configuration from a higher priority task,
+            //so it must not be preempted while holding the lock
+            let updated = cortex_m::interrupt::free(|_| unsafe {
+                DEVICE_CONFIG.as_ref().unwrap().lock().clone()
+            });
+
+            let config = cx.resources.config;
+            let prices = cx.resources.prices;
 
-        if let Some(mut vec) = cs {
-            if vec.len() > 0 {
-                let selected = cx.resources.selected_symbols;
-                let prices = cx.resources.prices;
+            let stored = load_config();
+            if stored != updated {
+                save_config(&updated);
+            }
 
-                *selected = Vec::from_slice(&vec.clone()).unwrap();
+            let prices_changed =
+                config.symbols != updated.symbols || config.currencies != updated.currencies;
 
-                vec.clear();
+            let price_client = cx.resources.price_client;
+            if config.cryptocompare_api_key != updated.cryptocompare_api_key {
+                price_client
+                    .set_api_key("CryptoCompare", &updated.cryptocompare_api_key)
+                    .ok();
+            }
+            if config.coingecko_api_key != updated.coingecko_api_key {
+                price_client
+                    .set_api_key("CoinGecko", &updated.coingecko_api_key)
+                    .ok();
+            }
+            *config = updated;
+
+            BRIGHTNESS.store(config.brightness, Ordering::Relaxed);
+
+            if prices_changed {
                 prices.clear();
 
                 //reset prices map
-                for element in selected {
-                    prices.insert(element.clone(), (None, None)).unwrap();
+                for element in config.symbols.iter() {
+                    prices.insert(element.clone(), (Quotes::new(), None)).ok();
+                }
+                cortex_m::interrupt::free(|_| unsafe {
+                    MARKET_STATS.as_ref().unwrap().lock().clear()
+                });
+                cortex_m::interrupt::free(|_| unsafe { HISTORY.as_ref().unwrap().lock().clear() });
+
+                unsafe {
+                    CANVAS
+                        .as_mut()
+                        .unwrap()
+                        .set_price_lines(config.currencies.len());
                 }
 
-                cx.spawn.update_24h().unwrap();
+                //refresh prices immediately. If fetching the base prices is waiting for a retry,
+                //the spawn fails and the pending retry happens within a second instead
+                *cx.resources.update_countdown = 0;
+                *cx.resources.stats_countdown = 0;
+                *cx.resources.history_countdown = 0;
+                *cx.resources.openday_countdown = 0;
+                cx.resources.openday_backoff.reset();
+                cx.spawn.update_24h().ok();
 
                 #[cfg(feature = "use_semihosting")]
                 hprintln!("{:?}", prices).ok();
@@ -502,10 +851,10 @@ const APP: () = {
     fn display(cx: display::Context) {
         cx.resources.led_b.set_high().unwrap();
         unsafe {
-            CONNECTED_DISPLAYS
-                .as_mut()
-                .unwrap()
-                .output_bcm(cx.resources.display_delay, 1)
+            CONNECTED_DISPLAYS.as_mut().unwrap().output_bcm(
+                cx.resources.display_delay,
+                BRIGHTNESS.load(Ordering::Relaxed),
+            )
         };
         cx.resources.led_b.set_low().unwrap();
 
@@ -287,6 +287,14 @@ where
             .unwrap()
             .is_unspecified()
     }
+
+    /// Returns the address assigned to the interface, if DHCP has provided one.
+    pub fn ipv4_address(&self) -> Option<Ipv4Address> {
+        self.network_interface
+            .lock()
+            .ipv4_addr()
+            .filter(|address| !address.is_unspecified())
+    }
 }
 
 impl<'a, 'b, DeviceT> drogue_network::tcp::TcpStack for NetworkStack<'a, 'b, DeviceT>
@@ -329,3 +329,88 @@ pub unsafe extern "C" fn f_source(
     RNG.as_mut().unwrap().read(buffer);
     return 0;
 }
+
+// Random number from the hardware generator, e.g. for the jitter of retry delays
+pub fn random_u32() -> u32 {
+    let mut bytes = [0u8; 4];
+    unsafe { RNG.as_mut().unwrap().read(&mut bytes).ok() };
+    u32::from_ne_bytes(bytes)
+}
+
+// Persistent storage lives in sectors 21 to 23, the last three sectors of flash bank 2.
+// Those sectors are excluded from the FLASH region in memory_f4.x
+pub const CATALOG_STORAGE_ADDRESS: usize = 0x081A_0000;
+pub const LOGO_STORAGE_ADDRESS: usize = 0x081C_0000;
+pub const CONFIG_STORAGE_ADDRESS: usize = 0x081E_0000;
+pub const STORAGE_SECTOR_SIZE: usize = 128 * 1024;
+// F4 could program single words, but we stay compatible with H7's 256-bit flash words
+pub const STORAGE_WORD_SIZE: usize = 32;
+
+const FLASH_BASE: usize = 0x4002_3C00;
+const FLASH_KEYR: *mut u32 = (FLASH_BASE + 0x04) as *mut u32;
+const FLASH_SR: *mut u32 = (FLASH_BASE + 0x0C) as *mut u32;
+const FLASH_CR: *mut u32 = (FLASH_BASE + 0x10) as *mut u32;
+// Sector 17 is the first 128K sector of bank 2. Bank 2 sector numbers are encoded with bit 4 set.
+const FIRST_128K_SECTOR_ADDRESS: usize = 0x0812_0000;
+const FIRST_128K_SECTOR_SNB: u32 = 0b10101;
+
+const CR_PG: u32 = 1 << 0;
+const CR_SER: u32 = 1 << 1;
+const CR_PSIZE_X32: u32 = 0b10 << 8;
+const CR_STRT: u32 = 1 << 16;
+const CR_LOCK: u32 = 1 << 31;
+const SR_BSY: u32 = 1 << 16;
+const SR_CLEAR_ALL: u32 = 0xF3;
+
+unsafe fn flash_unlock() {
+    if core::ptr::read_volatile(FLASH_CR) & CR_LOCK != 0 {
+        core::ptr::write_volatile(FLASH_KEYR, 0x4567_0123);
+        core::ptr::write_volatile(FLASH_KEYR, 0xCDEF_89AB);
+    }
+    core::ptr::write_volatile(FLASH_SR, SR_CLEAR_ALL);
+}
+
+unsafe fn flash_wait() {
+    while core::ptr::read_volatile(FLASH_SR) & SR_BSY != 0 {}
+}
+
+unsafe fn flash_lock() {
+    core::ptr::write_volatile(FLASH_CR, CR_LOCK);
+}
+
+/// Erases a whole storage sector. Blocks until the erase is complete.
+/// # Arguments
+/// * `sector_address` - One of the `*_STORAGE_ADDRESS` constants
+pub fn storage_erase(sector_address: usize) {
+    let snb = FIRST_128K_SECTOR_SNB
+        + ((sector_address - FIRST_128K_SECTOR_ADDRESS) / STORAGE_SECTOR_SIZE) as u32;
+
+    unsafe {
+        flash_unlock();
+        flash_wait();
+        core::ptr::write_volatile(FLASH_CR, CR_SER | CR_PSIZE_X32 | (snb << 3));
+        core::ptr::write_volatile(FLASH_CR, CR_SER | CR_PSIZE_X32 | (snb << 3) | CR_STRT);
+        flash_wait();
+        flash_lock();
+    }
+}
+
+/// Programs a single flash word. The word must have been erased before.
+/// # Arguments
+/// * `address` - Address inside one of the storage sectors, aligned to `STORAGE_WORD_SIZE`
+/// * `data` - Data to program
+pub fn storage_program(address: usize, data: &[u8; STORAGE_WORD_SIZE]) {
+    unsafe {
+        flash_unlock();
+        flash_wait();
+        core::ptr::write_volatile(FLASH_CR, CR_PG | CR_PSIZE_X32);
+
+        for (i, word) in data.chunks(4).enumerate() {
+            let value = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            core::ptr::write_volatile((address + i * 4) as *mut u32, value);
+            flash_wait();
+        }
+
+        flash_lock();
+    }
+}
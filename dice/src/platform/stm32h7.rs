@@ -332,3 +332,86 @@ pub unsafe extern "C" fn f_source(
 
     return 0;
 }
+
+// Random number from the hardware generator, e.g. for the jitter of retry delays
+pub fn random_u32() -> u32 {
+    unsafe { RNG.as_mut().unwrap().next().unwrap_or(0) }
+}
+
+// Persistent storage lives in the last three sectors of flash bank 2.
+// Those sectors are excluded from the FLASH region in memory_h7.x
+pub const CATALOG_STORAGE_ADDRESS: usize = 0x081A_0000;
+pub const LOGO_STORAGE_ADDRESS: usize = 0x081C_0000;
+pub const CONFIG_STORAGE_ADDRESS: usize = 0x081E_0000;
+pub const STORAGE_SECTOR_SIZE: usize = 128 * 1024;
+// H7 programs flash in 256-bit words. Forcing a write of a partial word doesn't work (see problems.md)
+pub const STORAGE_WORD_SIZE: usize = 32;
+
+const FLASH_BASE: usize = 0x5200_2000;
+const FLASH_KEYR2: *mut u32 = (FLASH_BASE + 0x104) as *mut u32;
+const FLASH_CR2: *mut u32 = (FLASH_BASE + 0x10C) as *mut u32;
+const FLASH_SR2: *mut u32 = (FLASH_BASE + 0x110) as *mut u32;
+const FLASH_CCR2: *mut u32 = (FLASH_BASE + 0x114) as *mut u32;
+const BANK2_START: usize = 0x0810_0000;
+
+const CR_LOCK: u32 = 1 << 0;
+const CR_PG: u32 = 1 << 1;
+const CR_SER: u32 = 1 << 2;
+const CR_PSIZE_X32: u32 = 0b10 << 4;
+const CR_START: u32 = 1 << 7;
+const SR_BSY: u32 = 1 << 0;
+const SR_QW: u32 = 1 << 2;
+const CCR_CLEAR_ALL: u32 = 0x0FEF_0000;
+
+unsafe fn flash_unlock() {
+    if core::ptr::read_volatile(FLASH_CR2) & CR_LOCK != 0 {
+        core::ptr::write_volatile(FLASH_KEYR2, 0x4567_0123);
+        core::ptr::write_volatile(FLASH_KEYR2, 0xCDEF_89AB);
+    }
+    core::ptr::write_volatile(FLASH_CCR2, CCR_CLEAR_ALL);
+}
+
+unsafe fn flash_wait() {
+    while core::ptr::read_volatile(FLASH_SR2) & (SR_BSY | SR_QW) != 0 {}
+}
+
+unsafe fn flash_lock() {
+    core::ptr::write_volatile(FLASH_CR2, CR_LOCK);
+}
+
+/// Erases a whole storage sector. Blocks until the erase is complete.
+/// # Arguments
+/// * `sector_address` - One of the `*_STORAGE_ADDRESS` constants
+pub fn storage_erase(sector_address: usize) {
+    let sector = ((sector_address - BANK2_START) / STORAGE_SECTOR_SIZE) as u32;
+
+    unsafe {
+        flash_unlock();
+        flash_wait();
+        core::ptr::write_volatile(FLASH_CR2, CR_SER | CR_PSIZE_X32 | (sector << 8));
+        core::ptr::write_volatile(FLASH_CR2, CR_SER | CR_PSIZE_X32 | (sector << 8) | CR_START);
+        flash_wait();
+        flash_lock();
+    }
+}
+
+/// Programs a single flash word. The word must have been erased before.
+/// # Arguments
+/// * `address` - Address inside one of the storage sectors, aligned to `STORAGE_WORD_SIZE`
+/// * `data` - Data to program
+pub fn storage_program(address: usize, data: &[u8; STORAGE_WORD_SIZE]) {
+    unsafe {
+        flash_unlock();
+        flash_wait();
+        core::ptr::write_volatile(FLASH_CR2, CR_PG | CR_PSIZE_X32);
+
+        for (i, word) in data.chunks(4).enumerate() {
+            let value = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            core::ptr::write_volatile((address + i * 4) as *mut u32, value);
+        }
+        cortex_m::asm::dsb();
+
+        flash_wait();
+        flash_lock();
+    }
+}
@@ -0,0 +1,107 @@
+//! Blobs of data persisted in the storage sectors of flash.
+//! A blob starts with a header word holding a magic number and the blob length.
+//! The header is programmed last, so an interrupted write leaves no valid blob behind.
+
+use crate::platform::{storage_erase, storage_program, STORAGE_SECTOR_SIZE, STORAGE_WORD_SIZE};
+
+const BLOB_MAGIC: u32 = 0xD1CE_B10B;
+pub const MAX_BLOB_SIZE: usize = STORAGE_SECTOR_SIZE - STORAGE_WORD_SIZE;
+
+#[derive(Debug)]
+pub enum StorageError {
+    BlobTooLarge,
+}
+
+/// Returns the blob stored in a storage sector, if there is a valid one.
+/// # Arguments
+/// * `sector_address` - Address of one of the platform's storage sectors
+pub fn read_blob(sector_address: usize) -> Option<&'static [u8]> {
+    let header =
+        unsafe { core::slice::from_raw_parts(sector_address as *const u8, STORAGE_WORD_SIZE) };
+
+    let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+
+    if magic != BLOB_MAGIC || length > MAX_BLOB_SIZE {
+        return None;
+    }
+
+    unsafe {
+        Some(core::slice::from_raw_parts(
+            (sector_address + STORAGE_WORD_SIZE) as *const u8,
+            length,
+        ))
+    }
+}
+
+/// Erases the sector, and with it the blob stored in it.
+/// # Arguments
+/// * `sector_address` - Address of one of the platform's storage sectors
+pub fn erase_blob(sector_address: usize) {
+    storage_erase(sector_address);
+}
+
+/// Writes a blob piece by piece, so it never has to be held in RAM as a whole.
+pub struct BlobWriter {
+    sector_address: usize,
+    length: usize,
+    staging: [u8; STORAGE_WORD_SIZE],
+    staged: usize,
+}
+
+impl BlobWriter {
+    /// Erases the sector (and any blob stored in it) and starts writing a new blob.
+    /// # Arguments
+    /// * `sector_address` - Address of one of the platform's storage sectors
+    pub fn new(sector_address: usize) -> Self {
+        storage_erase(sector_address);
+
+        BlobWriter {
+            sector_address,
+            length: 0,
+            staging: [0xFF; STORAGE_WORD_SIZE],
+            staged: 0,
+        }
+    }
+
+    pub fn write(&mut self, mut data: &[u8]) -> Result<(), StorageError> {
+        if self.length + self.staged + data.len() > MAX_BLOB_SIZE {
+            return Err(StorageError::BlobTooLarge);
+        }
+
+        while !data.is_empty() {
+            let count = (STORAGE_WORD_SIZE - self.staged).min(data.len());
+            self.staging[self.staged..self.staged + count].copy_from_slice(&data[..count]);
+            self.staged += count;
+            data = &data[count..];
+
+            if self.staged == STORAGE_WORD_SIZE {
+                self.flush();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the remaining data and the header, making the blob valid.
+    pub fn finish(mut self) {
+        if self.staged > 0 {
+            self.flush();
+        }
+
+        let mut header = [0xFF; STORAGE_WORD_SIZE];
+        header[0..4].copy_from_slice(&BLOB_MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&(self.length as u32).to_le_bytes());
+
+        storage_program(self.sector_address, &header);
+    }
+
+    fn flush(&mut self) {
+        let address = self.sector_address + STORAGE_WORD_SIZE + self.length;
+        storage_program(address, &self.staging);
+
+        self.length += self.staged;
+        self.staging = [0xFF; STORAGE_WORD_SIZE];
+        self.staged = 0;
+    }
+}
@@ -62,6 +62,9 @@ impl Into<TcpError> for TlsError {
     }
 }
 
+/// Longest host name sent with the handshake
+const MAX_HOSTNAME_LENGTH: usize = 253;
+
 #[repr(C)]
 pub struct TlsLayer<'a, StackT: TcpStack> {
     stack: &'a mut StackT,
@@ -172,6 +175,29 @@ impl<'a, StackT: TcpStack> TlsLayer<'a, StackT> {
         }
     }
 
+    /// Sets the name sent with the next handshake (SNI). Servers sharing an address, like
+    /// those behind a CDN, choose the certificate by it and may refuse handshakes without it.
+    fn set_hostname(&self, hostname: Option<&[u8]>) -> Result<(), TlsError> {
+        //mbedtls copies the name, which has to end with a zero byte. None clears it.
+        let mut buffer = [0 as c_char; MAX_HOSTNAME_LENGTH + 1];
+        let hostname_ptr = match hostname {
+            Some(name) if name.len() <= MAX_HOSTNAME_LENGTH => {
+                for (target, byte) in buffer.iter_mut().zip(name) {
+                    *target = *byte as c_char;
+                }
+                buffer.as_ptr()
+            }
+            _ => core::ptr::null(),
+        };
+
+        let result =
+            unsafe { ssl_set_hostname(&self.ssl_context as *const _ as *mut _, hostname_ptr) };
+        if result != 0 {
+            return Err(TlsError::CannotConnect);
+        }
+        Ok(())
+    }
+
     pub fn handle_disconnected(&mut self) {
         let state = self.state.try_lock();
 
@@ -224,6 +250,8 @@ impl<'a, StackT: TcpStack> TcpStack for TlsLayer<'a, StackT> {
             panic!("TlsLayer must be initialized before trying to connect!");
         }
 
+        self.set_hostname(remote.addr().hostname().map(|name| name.as_bytes()))?;
+
         let socket = self
             .stack
             .connect(self.socket.take().unwrap(), remote)
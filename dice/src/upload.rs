@@ -0,0 +1,165 @@
+//! Handler of the `/upload` route.
+//! Accepts a boot logo (part `logo`, a TGA image) which is streamed straight to flash,
+//! and a configuration backup (part `config`, in the format the configuration is stored in).
+
+use dice_common::config::SERIALIZED_CONFIG_SIZE;
+use dice_common::display::{is_valid_logo, is_valid_logo_header, TGA_HEADER_SIZE};
+use dice_http::multipart::{MultipartError, MultipartEvent};
+use dice_http::response;
+use heapless::{String, Vec};
+
+use crate::platform::LOGO_STORAGE_ADDRESS;
+use crate::storage::{self, BlobWriter};
+
+const MAX_CONFIG_SIZE: usize = SERIALIZED_CONFIG_SIZE;
+
+#[derive(PartialEq)]
+enum Part {
+    None,
+    Logo,
+    Config,
+}
+
+#[derive(Debug)]
+enum UploadError {
+    LogoTooLarge,
+    InvalidLogo,
+    ConfigTooLarge,
+    InvalidConfig,
+}
+
+struct UploadState {
+    part: Part,
+    //the start of the logo, collected until its header can be checked
+    logo_header: Vec<u8, TGA_HEADER_SIZE>,
+    logo: Option<BlobWriter>,
+    config: Vec<u8, MAX_CONFIG_SIZE>,
+    error: Option<UploadError>,
+}
+
+impl UploadState {
+    const fn new() -> Self {
+        UploadState {
+            part: Part::None,
+            logo_header: Vec::new(),
+            logo: None,
+            config: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+//Only accessed from the server poll task
+static mut STATE: UploadState = UploadState::new();
+
+pub fn upload_event(event: &MultipartEvent) {
+    let state = unsafe { &mut STATE };
+
+    if state.error.is_some() {
+        return;
+    }
+
+    match event {
+        MultipartEvent::PartStart(info) => {
+            state.part = match info.name {
+                Some("logo") => Part::Logo,
+                Some("config") => Part::Config,
+                _ => Part::None,
+            };
+        }
+        MultipartEvent::Data(data) => match state.part {
+            Part::Logo => write_logo(state, data),
+            Part::Config => {
+                if state.config.extend_from_slice(data).is_err() {
+                    state.error = Some(UploadError::ConfigTooLarge);
+                }
+            }
+            Part::None => {}
+        },
+        MultipartEvent::PartEnd => {
+            match state.part {
+                Part::Logo => {
+                    if let Some(logo) = state.logo.take() {
+                        logo.finish();
+
+                        //Only the header was checked before, an image cut short or with
+                        //broken data is removed rather than left in place of the old logo
+                        let logo = storage::read_blob(LOGO_STORAGE_ADDRESS);
+                        if !logo.map_or(false, is_valid_logo) {
+                            storage::erase_blob(LOGO_STORAGE_ADDRESS);
+                            state.error = Some(UploadError::InvalidLogo);
+                        }
+                    } else if !state.logo_header.is_empty() {
+                        //Too short to be an image, the stored logo is untouched
+                        state.error = Some(UploadError::InvalidLogo);
+                    }
+                    state.logo_header.clear();
+                }
+                Part::Config if !state.config.is_empty() => {
+                    //Backups are written by `DeviceConfig::to_form`, with every setting
+                    let result =
+                        crate::update_config(|config| config.update_from_form(&state.config));
+                    if result.is_err() {
+                        state.error = Some(UploadError::InvalidConfig);
+                    }
+                    state.config.clear();
+                }
+                Part::Config => {}
+                Part::None => {}
+            }
+            state.part = Part::None;
+        }
+        MultipartEvent::Finished => {}
+    }
+}
+
+/// Checks the header of the logo once it has arrived, and only then erases the stored logo
+/// and starts writing the new one. Browsers send empty parts for file inputs left blank,
+/// which leave the stored logo as it is.
+fn write_logo(state: &mut UploadState, data: &[u8]) {
+    if let Some(logo) = state.logo.as_mut() {
+        if logo.write(data).is_err() {
+            state.error = Some(UploadError::LogoTooLarge);
+        }
+        return;
+    }
+
+    let count = (TGA_HEADER_SIZE - state.logo_header.len()).min(data.len());
+    //Note(unwrap): `count` is at most the room left
+    state.logo_header.extend_from_slice(&data[..count]).unwrap();
+    if state.logo_header.len() < TGA_HEADER_SIZE {
+        return;
+    }
+    if !is_valid_logo_header(&state.logo_header) {
+        state.error = Some(UploadError::InvalidLogo);
+        return;
+    }
+
+    let mut logo = BlobWriter::new(LOGO_STORAGE_ADDRESS);
+    let result = logo
+        .write(&state.logo_header)
+        .and_then(|_| logo.write(&data[count..]));
+    if result.is_err() {
+        state.error = Some(UploadError::LogoTooLarge);
+    }
+    state.logo = Some(logo);
+}
+
+pub fn upload_complete<const SIZE: usize>(result: Result<(), MultipartError>) -> String<SIZE> {
+    let state = unsafe { &mut STATE };
+
+    let error = state.error.take();
+    //If the upload was interrupted, leave no half-written logo behind a valid header
+    state.logo = None;
+    state.logo_header.clear();
+    state.part = Part::None;
+    state.config.clear();
+
+    #[cfg(feature = "use_semihosting")]
+    cortex_m_semihosting::hprintln!("upload: {:?} {:?}", result, error).ok();
+
+    match (result, error) {
+        (Ok(()), None) => response::redirect_response("/"),
+        _ => response::bad_request_response(),
+    }
+}
@@ -1,19 +1,32 @@
+use dice_common::catalog::Catalog;
+use dice_common::config::{DeviceConfig, MAX_SYMBOLS};
+use dice_common::currency::{self, CURRENCIES};
 use dice_http::response;
+use dice_http_client::crypto_api_client::{Candle, History, MarketStats, MarketStatsMap};
 
-use heapless::{String, Vec};
+use heapless::{FnvIndexMap, String};
 use httparse::Request;
 
-fn replace<const SIZE: usize>(source: &str, replaced: &str, replacement: &str) -> String<SIZE>{
+/// The page doesn't fit in the response buffer
+#[derive(Debug)]
+pub struct PageTooLarge;
+
+fn replace<const SIZE: usize>(source: &str, replaced: &str, replacement: &str) -> Result<String<SIZE>, PageTooLarge>{
     let pos = source.find(replaced).unwrap();
 
     let before_replaced = &source[0..pos];
     let after_replaced = &source[pos+replaced.len()..];
 
-    let mut new_string = String::from(before_replaced);
-    new_string.push_str(replacement).ok();
-    new_string.push_str(after_replaced).ok();
+    let mut new_string = String::new();
+    new_string.push_str(before_replaced).map_err(|_| PageTooLarge)?;
+    new_string.push_str(replacement).map_err(|_| PageTooLarge)?;
+    new_string.push_str(after_replaced).map_err(|_| PageTooLarge)?;
+
+    Ok(new_string)
+}
 
-    new_string
+fn page_response<const SIZE: usize>(page: &str) -> Result<String<SIZE>, PageTooLarge> {
+    response::try_ok_response(page).ok_or(PageTooLarge)
 }
 
 pub fn styles_get<const SIZE: usize>(_request: Request, _body: &[u8]) -> String<SIZE>{
@@ -23,57 +36,237 @@ pub fn styles_get<const SIZE: usize>(_request: Request, _body: &[u8]) -> String<
 
 
 
-pub fn index_get<const SIZE: usize>(symbols: &[&str]) -> String<SIZE> {
+/// Options of a currency select, with `selected` chosen. A configured currency the list
+/// doesn't have is offered too, so that submitting the form keeps it. An empty `selected`
+/// leaves the placeholder option of the second currency select chosen.
+fn currency_options(selected: &str) -> String<1024> {
+    let mut options = String::new();
+
+    let mut push_option = |currency: &str| {
+        options.push_str("<option value=\"").ok();
+        options.push_str(currency).ok();
+        options.push_str(if currency == selected { "\" selected>" } else { "\">" }).ok();
+        options.push_str(currency).ok();
+        options.push_str("</option>\r\n").ok();
+    };
+
+    for currency in CURRENCIES.iter() {
+        push_option(currency);
+    }
+    if !selected.is_empty() && !CURRENCIES.iter().any(|currency| *currency == selected) {
+        push_option(selected);
+    }
 
-    let columns = symbols.chunks(16);
+    options
+}
 
+/// Checked checkboxes of the displayed symbols, unchecking one removes it. Symbols the
+/// provider doesn't list anymore are marked, their prices won't come.
+fn symbol_entries<const SIZE: usize>(catalog: &Catalog, symbols: &[String<16>]) -> String<SIZE> {
     let mut list_string = String::<SIZE>::new();
 
-    for column in columns{
+    for row in symbols.chunks(8){
         list_string.push_str("<tr>\r\n").ok();
-        for symbol in column{
+        for symbol in row{
             let mut entry_string = String::<128>::new();
 
-            entry_string.push_str("<td>").ok();
-            entry_string.push_str("<label>").ok();
-
-            entry_string.push_str("<input class=\"check\" type=\"checkbox\" id=\">").ok();
+            entry_string.push_str("<td><label><input type=\"checkbox\" name=\"symbol\" value=\"").ok();
             entry_string.push_str(symbol).ok();
-            entry_string.push_str("\" name=\"").ok();
+            entry_string.push_str("\" checked />").ok();
             entry_string.push_str(symbol).ok();
-            entry_string.push_str("\" />").ok();
+            if !catalog.contains(symbol) {
+                entry_string.push_str(" (not listed)").ok();
+            }
+            entry_string.push_str("</label></td>\r\n").ok();
 
-            entry_string.push_str(symbol).ok();
+            list_string.push_str(entry_string.as_str()).ok();
+        }
+        list_string.push_str("</tr>\r\n").ok();
+    }
 
-            entry_string.push_str("</label>").ok();
-            entry_string.push_str("</td>\r\n").ok();
+    list_string
+}
 
-            list_string.push_str(entry_string.as_str()).ok();
+/// Fills the symbol entries and the size of the catalog
+fn replace_symbols<const SIZE: usize>(page: &str, catalog: &Catalog, symbols: &[String<16>]) -> Result<String<SIZE>, PageTooLarge> {
+    let mut size = String::<8>::new();
+    core::fmt::write(&mut size, format_args!("{}", catalog.len())).ok();
+
+    let page_string: String<SIZE> = replace(page, "{entries}", symbol_entries::<SIZE>(catalog, symbols).as_str())?;
+    replace(page_string.as_str(), "{catalog_size}", size.as_str())
+}
+
+/// Fills the suggestions of the search box adding another symbol, filled last with as many
+/// symbols as the rest of the response leaves room for. The catalog has the most important
+/// symbols first.
+fn replace_catalog<const SIZE: usize>(page: &str, catalog: &Catalog) -> Result<String<SIZE>, PageTooLarge> {
+    let room = SIZE.saturating_sub(page.len() - "{catalog}".len() + response::OK_HEADER.len());
+
+    let mut options = String::<SIZE>::new();
+    for symbol in catalog.symbols() {
+        if options.len() + "<option value=\"\">".len() + symbol.len() > room {
+            break;
         }
-        list_string.push_str("\r\n</tr>").ok();
+        options.push_str("<option value=\"").ok();
+        options.push_str(symbol).ok();
+        options.push_str("\">").ok();
     }
 
+    replace(page, "{catalog}", options.as_str())
+}
 
+pub fn index_get<const SIZE: usize>(catalog: &Catalog, symbols: &[String<16>], currencies: &[String<8>]) -> Result<String<SIZE>, PageTooLarge> {
     let page = include_str!("webpages/index.html");
 
-    let page_string: String<SIZE> = replace(page, "{entries}", list_string.as_str());
+    let page_string: String<SIZE> = replace_symbols(page, catalog, symbols)?;
+    let page_string: String<SIZE> = replace_currencies(page_string.as_str(), currencies)?;
+    let page_string: String<SIZE> = replace_catalog(page_string.as_str(), catalog)?;
 
-    response::ok_response(page_string.as_str())
+    page_response(page_string.as_str())
 }
 
-pub fn parse_post_body(body: &str) -> Vec<String<16>, 64>{
-    let mut symbols = Vec::<String<16>, 64>::new();
+/// Fills the main and the optional second currency select
+fn replace_currencies<const SIZE: usize>(page: &str, currencies: &[String<8>]) -> Result<String<SIZE>, PageTooLarge> {
+    let main = currencies.get(0).map_or("", |currency| currency.as_str());
+    let second = currencies.get(1).map_or("", |currency| currency.as_str());
+
+    let page_string: String<SIZE> = replace(page, "{currencies}", currency_options(main).as_str())?;
+    replace(page_string.as_str(), "{second_currencies}", currency_options(second).as_str())
+}
+
+fn key_state(key: &str) -> &'static str {
+    if key.is_empty() { "not set" } else { "saved, leave empty to keep" }
+}
+
+pub fn setup_get<const SIZE: usize>(catalog: &Catalog, config: &DeviceConfig) -> Result<String<SIZE>, PageTooLarge> {
+
+    let mut brightness = String::<4>::new();
+    core::fmt::write(&mut brightness, format_args!("{}", config.brightness)).ok();
+    let mut refresh = String::<12>::new();
+    core::fmt::write(&mut refresh, format_args!("{}", config.refresh_interval)).ok();
+    let mut max_symbols = String::<4>::new();
+    core::fmt::write(&mut max_symbols, format_args!("{}", MAX_SYMBOLS)).ok();
 
-    let splices = body.split('&');
-    for splice in splices {
-        if splice.len() == 0{
+    let page = include_str!("webpages/setup.html");
+
+    let page_string: String<SIZE> = replace_symbols(page, catalog, &config.symbols)?;
+    let page_string: String<SIZE> = replace(page_string.as_str(), "{brightness}", brightness.as_str())?;
+    let page_string: String<SIZE> = replace(page_string.as_str(), "{refresh}", refresh.as_str())?;
+    let page_string: String<SIZE> = replace(page_string.as_str(), "{max_symbols}", max_symbols.as_str())?;
+    let page_string: String<SIZE> = replace_currencies(page_string.as_str(), &config.currencies)?;
+    //Only whether a key is set is shown, the keys themselves never leave the device
+    let page_string: String<SIZE> = replace(page_string.as_str(), "{cryptocompare_key_state}", key_state(&config.cryptocompare_api_key))?;
+    let page_string: String<SIZE> = replace(page_string.as_str(), "{coingecko_key_state}", key_state(&config.coingecko_api_key))?;
+    let page_string: String<SIZE> = replace_catalog(page_string.as_str(), catalog)?;
+
+    page_response(page_string.as_str())
+}
+
+/// Size of the price charts on the market page, in pixels
+const CHART_SIZE: (usize, usize) = (96, 24);
+
+/// Chart of the closing prices of `candles`, green if the price went up over them and red
+/// if it went down. Nothing is drawn of fewer than two candles.
+fn sparkline(candles: &[Candle]) -> String<384> {
+    let mut chart = String::new();
+    let (first, last) = match (candles.first(), candles.last()) {
+        (Some(first), Some(last)) if candles.len() > 1 => (first.close, last.close),
+        _ => return chart,
+    };
+
+    let low = candles.iter().map(|candle| candle.close).fold(f32::MAX, f32::min);
+    let high = candles.iter().map(|candle| candle.close).fold(f32::MIN, f32::max);
+    let color = if last >= first { "#00ff00" } else { "#ff0000" };
+
+    let (width, height) = CHART_SIZE;
+    core::fmt::write(&mut chart, format_args!("<svg width=\"{}\" height=\"{}\"><polyline fill=\"none\" stroke=\"{}\" points=\"", width, height, color)).ok();
+    for (index, candle) in candles.iter().enumerate() {
+        let x = index * (width - 1) / (candles.len() - 1);
+        //A price that didn't move is drawn in the middle
+        let y = if high > low { ((high - candle.close) / (high - low) * (height - 1) as f32) as usize } else { height / 2 };
+        core::fmt::write(&mut chart, format_args!("{},{} ", x, y)).ok();
+    }
+    chart.push_str("\"/></svg>").ok();
+
+    chart
+}
+
+/// `value` with a suffix of its magnitude, e.g. `812.3B`
+fn abbreviated(value: f32) -> String<16> {
+    let (scaled, suffix) = [(1e12, "T"), (1e9, "B"), (1e6, "M"), (1e3, "K")]
+        .iter()
+        .find(|(magnitude, _)| value >= *magnitude)
+        .map_or((value, ""), |(magnitude, suffix)| (value / magnitude, *suffix));
+
+    let mut text = String::new();
+    core::fmt::write(&mut text, format_args!("{:.1}{}", scaled, suffix)).ok();
+    text
+}
+
+/// Cells of the 24h high, low, volume and market cap of a symbol, in `currency`
+fn stats_cells(stats: Option<&MarketStats>, currency: &str) -> String<160> {
+    let stats = stats.copied().unwrap_or_default();
+    let mut cap = String::<32>::new();
+    if let Some(market_cap) = stats.market_cap {
+        cap.push_str(&currency::prefix(currency)).ok();
+        cap.push_str(&abbreviated(market_cap)).ok();
+    }
+
+    let cells = [
+        stats.high_24h.map(|high| currency::format_price(currency, high)),
+        stats.low_24h.map(|low| currency::format_price(currency, low)),
+        stats.volume_24h.map(|volume| String::from(abbreviated(volume).as_str())),
+        Some(cap).filter(|cap| !cap.is_empty()),
+    ];
+
+    let mut text = String::new();
+    for cell in cells.iter() {
+        text.push_str("<td>").ok();
+        //Not every provider has all of the stats
+        text.push_str(cell.as_ref().map_or("-", |cell| cell.as_str())).ok();
+        text.push_str("</td>").ok();
+    }
+    text
+}
+
+/// Fills the rows of the market table, one for each displayed symbol. Symbols whose row
+/// doesn't fit in the response are left out.
+fn replace_market<const SIZE: usize>(page: &str, symbols: &[String<16>], currency: &str, sources: &FnvIndexMap<String<16>, &str, MAX_SYMBOLS>, stats: &MarketStatsMap<MAX_SYMBOLS>, history: &FnvIndexMap<String<16>, History, MAX_SYMBOLS>) -> Result<String<SIZE>, PageTooLarge> {
+    let room = SIZE.saturating_sub(page.len() - "{market}".len() + response::OK_HEADER.len());
+
+    let mut rows = String::<SIZE>::new();
+    for symbol in symbols {
+        let mut row = String::<512>::new();
+        row.push_str("<tr>\r\n<td>").ok();
+        row.push_str(symbol).ok();
+        row.push_str("</td>\r\n<td>").ok();
+        //Prices of every symbol may come from a different provider
+        row.push_str(sources.get(symbol).copied().unwrap_or("-")).ok();
+        row.push_str("</td>\r\n").ok();
+        row.push_str(stats_cells(stats.get(symbol), currency).as_str()).ok();
+        row.push_str("\r\n<td>").ok();
+        //The history of every symbol is fetched a while after the prices
+        match history.get(symbol).map(|candles| sparkline(candles)) {
+            Some(chart) if !chart.is_empty() => row.push_str(chart.as_str()).ok(),
+            _ => row.push_str("pending").ok(),
+        };
+        row.push_str("</td>\r\n</tr>\r\n").ok();
+
+        if rows.len() + row.len() > room {
             break;
         }
-        let end = splice.find('=').unwrap();
-        let symbol = &splice[0 .. end];
-        
-        let _result = symbols.push(String::from(symbol));
+        rows.push_str(row.as_str()).ok();
     }
 
-    return symbols;
+    replace(page, "{market}", rows.as_str())
+}
+
+/// Market page of `symbols`, with the providers of their prices and the stats and history
+/// in `currency`
+pub fn market_get<const SIZE: usize>(symbols: &[String<16>], currency: &str, sources: &FnvIndexMap<String<16>, &str, MAX_SYMBOLS>, stats: &MarketStatsMap<MAX_SYMBOLS>, history: &FnvIndexMap<String<16>, History, MAX_SYMBOLS>) -> Result<String<SIZE>, PageTooLarge> {
+    let page = include_str!("webpages/market.html");
+
+    let page_string: String<SIZE> = replace_market(page, symbols, currency, sources, stats, history)?;
+
+    page_response(page_string.as_str())
 }
@@ -1,7 +1,8 @@
 MEMORY
 {
   /* NOTE K = KiBi = 1024 bytes */
-  FLASH : ORIGIN = 0x08000000, LENGTH = 2M 
+  /* The last three 128K sectors are reserved for persistent storage (symbol catalog, logo and configuration) */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 2M - 384K
   RAM : ORIGIN = 0x20000000, LENGTH = 192K
 }
 
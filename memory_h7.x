@@ -5,7 +5,8 @@ MEMORY
   /* STM32H742xI/743xI/753xI       */
   /* STM32H745xI/747xI/755xI/757xI */
   /* STM32H7A3xI/7B3xI             */
-  FLASH  : ORIGIN = 0x08000000, LENGTH = 2M
+  /* The last three 128K sectors are reserved for persistent storage (symbol catalog, logo and configuration) */
+  FLASH  : ORIGIN = 0x08000000, LENGTH = 2M - 384K
 
   /* STM32H742xG/743xG       */
   /* STM32H745xG/STM32H747xG */